@@ -0,0 +1,193 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::RgbImage;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Directory in the cache that recorded frames go to unless told otherwise
+pub const DIR_NAME: &str = "archive";
+
+/// Directory next to the cached wallpapers that their unprocessed frames go in
+pub const ORIGINALS_DIR_NAME: &str = "originals";
+
+/// How the unprocessed frames of cached wallpapers are stored. Close enough to lossless for
+/// rendering again, at a fraction of the size of PNG
+pub const ORIGINAL_FORMAT: FrameFormat = FrameFormat::Jpeg {
+    quality: 95,
+    progressive: false,
+};
+
+/// rav1e speed from 1 to 10. Slower is a little smaller, 6 keeps a 4K frame to a few seconds
+const AVIF_SPEED: u8 = 6;
+
+/// How recorded frames and wallpapers are stored
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// Lossless, about ten times the size of a good JPEG
+    Png,
+    Jpeg {
+        quality: u8,
+        /// Loads coarse to fine, usually a little smaller too
+        progressive: bool,
+    },
+    /// Lossless, smaller than PNG
+    Webp,
+    Avif {
+        quality: u8,
+    },
+}
+
+impl FrameFormat {
+    /// `png`, lossless `webp`, or `jpg`/`jpeg` or `avif` at a quality from 1 to 100
+    pub fn parse(format: &str, quality: u8) -> Result<Self> {
+        let format = format.trim().to_lowercase();
+        let lossy = matches!(format.as_str(), "jpg" | "jpeg" | "avif");
+        if lossy && !(1..=100).contains(&quality) {
+            return Err(anyhow!("Quality goes from 1 to 100: {}", quality));
+        }
+
+        match format.as_str() {
+            "png" => Ok(FrameFormat::Png),
+            "jpg" | "jpeg" => Ok(FrameFormat::Jpeg {
+                quality,
+                progressive: false,
+            }),
+            "webp" => Ok(FrameFormat::Webp),
+            "avif" => Ok(FrameFormat::Avif { quality }),
+            other => Err(anyhow!(
+                "Unknown image format: {} (expected png, jpg, webp or avif)",
+                other
+            )),
+        }
+    }
+
+    /// Whether frames in this format can be decoded again, for timelapses, rerendering and
+    /// ghost mode. The image crate only writes AVIF
+    pub fn readable(self) -> bool {
+        !matches!(self, FrameFormat::Avif { .. })
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            FrameFormat::Png => "png",
+            FrameFormat::Jpeg { .. } => "jpg",
+            FrameFormat::Webp => "webp",
+            FrameFormat::Avif { .. } => "avif",
+        }
+    }
+}
+
+/// Whether an image saved by citycam can be decoded again, going by its extension
+pub fn readable(path: &Path) -> bool {
+    !path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(FrameFormat::Avif { quality: 1 }.extension()))
+}
+
+/// Directory name for a camera: its name in lowercase with every run of other characters
+/// than letters and digits turned into a single '-'
+pub fn camera_dir_name(name: &str) -> String {
+    let mut dir = String::new();
+    for ch in name.chars() {
+        if ch.is_alphanumeric() {
+            dir.extend(ch.to_lowercase());
+        } else if !dir.is_empty() && !dir.ends_with('-') {
+            dir.push('-');
+        }
+    }
+    let dir = dir.trim_end_matches('-');
+    if dir.is_empty() {
+        "camera".to_string()
+    } else {
+        dir.to_string()
+    }
+}
+
+/// Fails when two of the cameras would be archived in the same directory, their names
+/// differing only in case or punctuation
+pub fn check_camera_dirs<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<()> {
+    let mut dirs: HashMap<String, &str> = HashMap::new();
+    for name in names {
+        let dir = camera_dir_name(name);
+        if let Some(other) = dirs.insert(dir.clone(), name) {
+            return Err(anyhow!(
+                "{} and {} would both be archived in {}, rename one of them",
+                other,
+                name,
+                dir
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Where a frame captured at `time` goes: `<root>/<camera>/<YYYY-MM-DD>/<YYYYMMDD-HHMMSS>.<ext>`.
+/// Every day directory can be used as a `--source` for ghost mode
+pub fn frame_path(
+    root: &Path,
+    camera: &str,
+    time: DateTime<Local>,
+    format: FrameFormat,
+) -> PathBuf {
+    root.join(camera_dir_name(camera))
+        .join(time.format("%Y-%m-%d").to_string())
+        .join(format!(
+            "{}.{}",
+            time.format("%Y%m%d-%H%M%S"),
+            format.extension()
+        ))
+}
+
+/// Where the unprocessed frame of a cached wallpaper goes: `originals/<id>.jpg` next to it
+pub fn original_path(wallpaper: &Path) -> PathBuf {
+    let dir = wallpaper.parent().unwrap_or(Path::new(""));
+    let stem = wallpaper.file_stem().unwrap_or_default().to_string_lossy();
+    dir.join(ORIGINALS_DIR_NAME)
+        .join(format!("{}.{}", stem, ORIGINAL_FORMAT.extension()))
+}
+
+/// Save a frame, creating the directories it goes in
+pub fn save(img: &RgbImage, path: &Path, format: FrameFormat) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+    }
+    let create = || -> Result<BufWriter<File>> {
+        let file = File::create(path).context(format!("Failed to create {}", path.display()))?;
+        Ok(BufWriter::new(file))
+    };
+
+    match format {
+        FrameFormat::Png => img.save(path)?,
+        FrameFormat::Jpeg {
+            quality,
+            progressive: false,
+        } => img.write_with_encoder(JpegEncoder::new_with_quality(create()?, quality))?,
+        // The image crate only writes baseline JPEGs
+        FrameFormat::Jpeg {
+            quality,
+            progressive: true,
+        } => {
+            let (Ok(width), Ok(height)) = (u16::try_from(img.width()), u16::try_from(img.height()))
+            else {
+                return Err(anyhow!(
+                    "Too large for a JPEG: {}x{}",
+                    img.width(),
+                    img.height()
+                ));
+            };
+            let mut encoder = jpeg_encoder::Encoder::new(create()?, quality);
+            encoder.set_progressive(true);
+            encoder.encode(img.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)?;
+        }
+        FrameFormat::Webp => img.write_with_encoder(WebPEncoder::new_lossless(create()?))?,
+        FrameFormat::Avif { quality } => img.write_with_encoder(
+            AvifEncoder::new_with_speed_quality(create()?, AVIF_SPEED, quality),
+        )?,
+    }
+    Ok(())
+}
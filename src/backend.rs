@@ -0,0 +1,398 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+
+/// Something that can put an image on the desktop
+pub trait WallpaperSetter {
+    /// Name used to pick the backend with `--wallpaper-backend`
+    fn name(&self) -> &'static str;
+
+    /// Show the image on every output
+    fn set(&self, path: &Path) -> Result<()>;
+
+    /// Show a different image on each output, by output name
+    fn set_outputs(&self, _wallpapers: &[(String, PathBuf)]) -> Result<()> {
+        Err(anyhow!(
+            "The {} backend can't set a wallpaper per output, try --span instead",
+            self.name()
+        ))
+    }
+}
+
+/// One image on every output, or one per output when there are several
+pub fn apply(setter: &dyn WallpaperSetter, wallpapers: &[(String, PathBuf)]) -> Result<()> {
+    match wallpapers {
+        [] => Err(anyhow!("No wallpaper to set")),
+        [(_, path)] => setter.set(path),
+        wallpapers => setter.set_outputs(wallpapers),
+    }
+}
+
+/// Parse a backend name: auto, native, sway, swaybg, swww, feh, gnome, kde, file or
+/// `command:<template>` where `{path}` (and `{output}` per output) are filled in
+pub fn parse(spec: &str) -> Result<Box<dyn WallpaperSetter>> {
+    let spec = spec.trim();
+    if let Some(template) = spec.strip_prefix("command:") {
+        if !template.contains("{path}") {
+            return Err(anyhow!("Command template needs a {{path}}: {}", template));
+        }
+        return Ok(Box::new(CommandTemplate {
+            template: template.to_string(),
+        }));
+    }
+
+    match spec.to_lowercase().as_str() {
+        "auto" => Ok(detect(|name| std::env::var(name).ok())),
+        "native" => Ok(Box::new(Native)),
+        "sway" => Ok(Box::new(Sway)),
+        "swaybg" => Ok(Box::new(Swaybg)),
+        "swww" => Ok(Box::new(Swww)),
+        "feh" => Ok(Box::new(Feh)),
+        "gnome" | "gsettings" => Ok(Box::new(Gsettings)),
+        "kde" | "plasma" => Ok(Box::new(Plasma)),
+        "file" | "none" => Ok(Box::new(FileOnly)),
+        other => Err(anyhow!(
+            "Unknown wallpaper backend: {} (expected auto, native, sway, swaybg, swww, feh, \
+             gnome, kde, file or command:<template>)",
+            other
+        )),
+    }
+}
+
+/// Backend for the desktop the environment variables point at, the `wallpaper` crate when
+/// nothing more specific is recognized
+pub fn detect(env: impl Fn(&str) -> Option<String>) -> Box<dyn WallpaperSetter> {
+    let desktop = env("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .to_lowercase();
+
+    if env("SWAYSOCK").is_some() {
+        Box::new(Sway)
+    } else if env("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        Box::new(Swww)
+    } else if desktop.contains("kde") {
+        Box::new(Plasma)
+    } else if desktop.contains("gnome") || desktop.contains("unity") {
+        Box::new(Gsettings)
+    } else if desktop.split(':').any(|name| name == "i3")
+        || (env("WAYLAND_DISPLAY").is_none() && env("DISPLAY").is_some() && desktop.is_empty())
+    {
+        // Bare X11 window managers such as i3 have no desktop of their own to ask. Display
+        // managers set XDG_CURRENT_DESKTOP=i3 for it
+        Box::new(Feh)
+    } else {
+        Box::new(Native)
+    }
+}
+
+/// Whatever the `wallpaper` crate does on this platform
+pub struct Native;
+
+impl WallpaperSetter for Native {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn set(&self, path: &Path) -> Result<()> {
+        let path_str = path.to_str().ok_or_else(|| anyhow!("Invalid path"))?;
+
+        wallpaper::set_from_path(path_str)
+            .map_err(|e| anyhow!("Failed to set wallpaper: {}", e))?;
+
+        #[cfg(target_os = "windows")]
+        wallpaper::set_mode(wallpaper::Mode::Crop)
+            .map_err(|e| anyhow!("Failed to set wallpaper mode: {}", e))?;
+
+        Ok(())
+    }
+}
+
+/// `swaymsg output <name> bg`
+pub struct Sway;
+
+impl WallpaperSetter for Sway {
+    fn name(&self) -> &'static str {
+        "sway"
+    }
+
+    fn set(&self, path: &Path) -> Result<()> {
+        self.set_outputs(&[("*".to_string(), path.to_path_buf())])
+    }
+
+    fn set_outputs(&self, wallpapers: &[(String, PathBuf)]) -> Result<()> {
+        for (output, path) in wallpapers {
+            let mut command = Command::new("swaymsg");
+            command.args(["output", output, "bg"]).arg(path).arg("fill");
+            run(command)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the swaybg it started last with a new one showing the images
+pub struct Swaybg;
+
+/// The swaybg started last by this process, stopped and reaped when the next one takes over.
+/// A pidfile covers the one started by an earlier citycam
+static SWAYBG: Mutex<Option<Child>> = Mutex::new(None);
+
+const SWAYBG_PIDFILE_NAME: &str = "citycam-swaybg.pid";
+
+impl Swaybg {
+    fn pidfile() -> PathBuf {
+        dirs::runtime_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join(SWAYBG_PIDFILE_NAME)
+    }
+
+    // Only ever the one citycam started, the swaybg sway runs itself stays
+    fn stop(previous: Option<Child>) {
+        if let Some(mut child) = previous {
+            let _ = child.kill();
+            let _ = child.wait();
+            return;
+        }
+
+        let Some(pid) = fs::read_to_string(Swaybg::pidfile())
+            .ok()
+            .and_then(|pid| pid.trim().parse::<u32>().ok())
+        else {
+            return;
+        };
+        // The pid may belong to something else by now
+        let name = fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default();
+        if name.trim() == "swaybg" {
+            let _ = Command::new("kill").arg(pid.to_string()).status();
+        }
+    }
+}
+
+impl WallpaperSetter for Swaybg {
+    fn name(&self) -> &'static str {
+        "swaybg"
+    }
+
+    fn set(&self, path: &Path) -> Result<()> {
+        self.set_outputs(&[("*".to_string(), path.to_path_buf())])
+    }
+
+    fn set_outputs(&self, wallpapers: &[(String, PathBuf)]) -> Result<()> {
+        let mut running = SWAYBG.lock().unwrap_or_else(|e| e.into_inner());
+
+        // There is no way to talk to a running swaybg, it has to be restarted. The new one
+        // starts first so the old one's images stay up until it draws
+        let mut command = Command::new("swaybg");
+        for (output, path) in wallpapers {
+            command.args(["-o", output, "-m", "fill", "-i"]).arg(path);
+        }
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to start swaybg")?;
+
+        Swaybg::stop(running.take());
+        if let Err(e) = fs::write(Swaybg::pidfile(), child.id().to_string()) {
+            eprintln!("Failed to write the swaybg pidfile: {}", e);
+        }
+        *running = Some(child);
+        Ok(())
+    }
+}
+
+/// `swww img`, needs swww-daemon running
+pub struct Swww;
+
+impl WallpaperSetter for Swww {
+    fn name(&self) -> &'static str {
+        "swww"
+    }
+
+    fn set(&self, path: &Path) -> Result<()> {
+        let mut command = Command::new("swww");
+        command.arg("img").arg(path);
+        run(command)
+    }
+
+    fn set_outputs(&self, wallpapers: &[(String, PathBuf)]) -> Result<()> {
+        for (output, path) in wallpapers {
+            let mut command = Command::new("swww");
+            command.args(["img", "--outputs", output]).arg(path);
+            run(command)?;
+        }
+        Ok(())
+    }
+}
+
+/// `feh --bg-fill`, which hands the images to the screens in the order given
+pub struct Feh;
+
+impl WallpaperSetter for Feh {
+    fn name(&self) -> &'static str {
+        "feh"
+    }
+
+    fn set(&self, path: &Path) -> Result<()> {
+        let mut command = Command::new("feh");
+        command.arg("--bg-fill").arg(path);
+        run(command)
+    }
+
+    fn set_outputs(&self, wallpapers: &[(String, PathBuf)]) -> Result<()> {
+        let mut command = Command::new("feh");
+        command
+            .arg("--bg-fill")
+            .args(wallpapers.iter().map(|(_, path)| path));
+        run(command)
+    }
+}
+
+/// GNOME's background settings, for both the light and dark style
+pub struct Gsettings;
+
+impl WallpaperSetter for Gsettings {
+    fn name(&self) -> &'static str {
+        "gnome"
+    }
+
+    fn set(&self, path: &Path) -> Result<()> {
+        let uri = file_uri(path)?;
+        for key in ["picture-uri", "picture-uri-dark"] {
+            let mut command = Command::new("gsettings");
+            command.args(["set", "org.gnome.desktop.background", key, &uri]);
+            run(command)?;
+        }
+        Ok(())
+    }
+}
+
+/// Plasma's scripting interface over D-Bus
+pub struct Plasma;
+
+impl Plasma {
+    /// Script setting the image on every desktop
+    pub fn script(path: &Path) -> Result<String> {
+        Ok(format!(
+            "desktops().forEach(function (d) {{ \
+                d.wallpaperPlugin = 'org.kde.image'; \
+                d.currentConfigGroup = ['Wallpaper', 'org.kde.image', 'General']; \
+                d.writeConfig('Image', {}); \
+            }});",
+            serde_json::to_string(&file_uri(path)?)?
+        ))
+    }
+}
+
+impl WallpaperSetter for Plasma {
+    fn name(&self) -> &'static str {
+        "kde"
+    }
+
+    fn set(&self, path: &Path) -> Result<()> {
+        let script = Plasma::script(path)?;
+        let args = [
+            "org.kde.plasmashell",
+            "/PlasmaShell",
+            "org.kde.PlasmaShell.evaluateScript",
+            &script,
+        ];
+
+        // Plasma 6 ships qdbus as qdbus6
+        let mut command = Command::new("qdbus");
+        command.args(args);
+        match run(command) {
+            Err(e) if e.downcast_ref::<std::io::Error>().is_some() => {
+                let mut command = Command::new("qdbus6");
+                command.args(args);
+                run(command)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Runs a user supplied command line through `sh -c`, with `{path}` and `{output}` replaced
+/// by the quoted values
+pub struct CommandTemplate {
+    pub template: String,
+}
+
+impl CommandTemplate {
+    /// The command line for an image, and the output it is meant for
+    pub fn command_line(&self, path: &Path, output: Option<&str>) -> String {
+        self.template
+            .replace("{path}", &shell_quote(&path.to_string_lossy()))
+            .replace("{output}", &shell_quote(output.unwrap_or("*")))
+    }
+}
+
+impl WallpaperSetter for CommandTemplate {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    fn set(&self, path: &Path) -> Result<()> {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(self.command_line(path, None));
+        run(command)
+    }
+
+    fn set_outputs(&self, wallpapers: &[(String, PathBuf)]) -> Result<()> {
+        if !self.template.contains("{output}") {
+            return Err(anyhow!(
+                "The command template needs an {{output}} to set a wallpaper per output"
+            ));
+        }
+        for (output, path) in wallpapers {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(self.command_line(path, Some(output)));
+            run(command)?;
+        }
+        Ok(())
+    }
+}
+
+/// Leaves the image in the cache for something else to pick up
+pub struct FileOnly;
+
+impl WallpaperSetter for FileOnly {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn set(&self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_outputs(&self, _wallpapers: &[(String, PathBuf)]) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn file_uri(path: &Path) -> Result<String> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let path = path.to_str().ok_or_else(|| anyhow!("Invalid path"))?;
+    Ok(format!("file://{}", path))
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn run(mut command: Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .context(format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
@@ -1,27 +1,366 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Camera {
     pub name: String,
+    /// Page, stream or image URL, or a path for local files
     pub url: String,
+    #[serde(default)]
+    pub kind: CameraKind,
+    /// Relative chance of being picked by the weighted rotation, 1.0 when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+    /// Where the camera is, in degrees, north and east positive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    /// IANA timezone of the camera, e.g. America/Detroit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// Free form labels such as `lake`, `downtown` or `zoo`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// How far down the frame the horizon sits, 0.0 (top) to 1.0 (bottom)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub horizon: Option<f32>,
+    /// Part of the frame worth keeping when it has to be cropped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crop: Option<Crop>,
+    /// Credit for the stream's owner, e.g. "Courtesy of the Drummond Island Chamber"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
+}
+
+impl Camera {
+    /// Camera for a local video file, .m3u8 playlist or image directory, named after the path
+    pub fn from_path(path: &Path) -> Camera {
+        let kind = if path.is_dir() {
+            CameraKind::Directory
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("m3u8"))
+        {
+            CameraKind::Hls
+        } else {
+            CameraKind::File
+        };
+
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+
+        Camera {
+            name,
+            url: path.display().to_string(),
+            kind,
+            ..Default::default()
+        }
+    }
+
+    /// Check what the file format can't: a place on the globe, a positive weight, a known
+    /// timezone, and a horizon and crop inside the frame
+    pub fn validate(&self) -> Result<()> {
+        if let Some(latitude) = self.latitude {
+            if !(-90.0..=90.0).contains(&latitude) {
+                return Err(anyhow!("{}: latitude has to be -90 to 90", self.name));
+            }
+        }
+        if let Some(longitude) = self.longitude {
+            if !(-180.0..=180.0).contains(&longitude) {
+                return Err(anyhow!("{}: longitude has to be -180 to 180", self.name));
+            }
+        }
+        if let Some(weight) = self.weight {
+            if !weight.is_finite() || weight <= 0.0 {
+                return Err(anyhow!("{}: weight has to be above 0", self.name));
+            }
+        }
+        if let Some(horizon) = self.horizon {
+            if !(0.0..=1.0).contains(&horizon) {
+                return Err(anyhow!("{}: horizon has to be from 0 to 1", self.name));
+            }
+        }
+        if let Some(timezone) = &self.timezone {
+            timezone
+                .parse::<Tz>()
+                .map_err(|_| anyhow!("{}: unknown timezone {}", self.name, timezone))?;
+        }
+        if let Some(crop) = &self.crop {
+            let fractions = [crop.x, crop.y, crop.width, crop.height];
+            if fractions.iter().any(|f| !(0.0..=1.0).contains(f))
+                || crop.width == 0.0
+                || crop.height == 0.0
+                || crop.x + crop.width > 1.0
+                || crop.y + crop.height > 1.0
+            {
+                return Err(anyhow!(
+                    "{}: crop has to be fractions of the frame from 0 to 1 that stay inside it",
+                    self.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The camera's timezone, `None` when unset or unknown
+    pub fn tz(&self) -> Option<Tz> {
+        self.timezone.as_deref()?.parse().ok()
+    }
+}
+
+/// Rectangle in fractions of the frame size, so it holds for every stream variant
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Crop {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Geographic area to pick cameras from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    /// Between two corners, latitude then longitude
+    Bounds {
+        south: f64,
+        west: f64,
+        north: f64,
+        east: f64,
+    },
+    /// Within `radius_km` of a point
+    Near {
+        latitude: f64,
+        longitude: f64,
+        radius_km: f64,
+    },
+}
+
+impl Region {
+    /// Parse `<lat>,<lon>,<lat>,<lon>` for the box between two corners or
+    /// `<lat>,<lon>@<km>` for a radius around a point
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (coordinates, radius) = match spec.split_once('@') {
+            Some((coordinates, radius)) => (coordinates, Some(radius)),
+            None => (spec, None),
+        };
+        let values = coordinates
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow!("Invalid region: {}", spec))?;
+
+        let on_globe = values.chunks(2).all(|pair| match pair {
+            &[latitude, longitude] => {
+                (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude)
+            }
+            _ => true,
+        });
+        if !on_globe {
+            return Err(anyhow!(
+                "Region latitudes have to be -90 to 90 and longitudes -180 to 180: {}",
+                spec
+            ));
+        }
+
+        match (values.as_slice(), radius) {
+            (&[latitude, longitude], Some(radius)) => Ok(Region::Near {
+                latitude,
+                longitude,
+                radius_km: radius
+                    .trim()
+                    .trim_end_matches("km")
+                    .parse()
+                    .ok()
+                    .filter(|km: &f64| km.is_finite() && *km > 0.0)
+                    .ok_or_else(|| anyhow!("Invalid region radius: {}", radius))?,
+            }),
+            (&[lat1, lon1, lat2, lon2], None) => Ok(Region::Bounds {
+                south: lat1.min(lat2),
+                west: lon1.min(lon2),
+                north: lat1.max(lat2),
+                east: lon1.max(lon2),
+            }),
+            _ => Err(anyhow!(
+                "Regions look like 41.7,-90.4,48.3,-82.1 or 42.96,-85.67@50km: {}",
+                spec
+            )),
+        }
+    }
+
+    /// Cameras without a location are never in a region
+    pub fn contains(&self, camera: &Camera) -> bool {
+        let (Some(lat), Some(lon)) = (camera.latitude, camera.longitude) else {
+            return false;
+        };
+
+        match *self {
+            Region::Bounds {
+                south,
+                west,
+                north,
+                east,
+            } => (south..=north).contains(&lat) && (west..=east).contains(&lon),
+            Region::Near {
+                latitude,
+                longitude,
+                radius_km,
+            } => distance_km(latitude, longitude, lat, lon) <= radius_km,
+        }
+    }
+}
+
+// Great circle distance using the haversine formula
+fn distance_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// How to get frames out of a camera's `url`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum CameraKind {
+    /// wetmet.net frame.php widget page
+    #[default]
+    Wetmet,
+    /// Direct .m3u8 playlist
+    Hls,
+    /// Still image refreshed by the camera
+    Snapshot,
+    /// Motion JPEG over HTTP
+    Mjpeg,
+    /// Local video file
+    File,
+    /// Local directory of still images, newest by file name last
+    Directory,
+}
+
+impl CameraKind {
+    /// Parse the kind names used in cams.json, e.g. `hls` or `snapshot`
+    pub fn parse(spec: &str) -> Result<Self> {
+        match spec.trim().to_lowercase().as_str() {
+            "wetmet" => Ok(CameraKind::Wetmet),
+            "hls" => Ok(CameraKind::Hls),
+            "snapshot" => Ok(CameraKind::Snapshot),
+            "mjpeg" => Ok(CameraKind::Mjpeg),
+            "file" => Ok(CameraKind::File),
+            "directory" => Ok(CameraKind::Directory),
+            other => Err(anyhow!("Unknown camera kind: {}", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for CameraKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            CameraKind::Wetmet => "wetmet",
+            CameraKind::Hls => "hls",
+            CameraKind::Snapshot => "snapshot",
+            CameraKind::Mjpeg => "mjpeg",
+            CameraKind::File => "file",
+            CameraKind::Directory => "directory",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Which cameras to list, every camera when nothing is set
+#[derive(Clone, Debug, Default)]
+pub struct CameraFilter {
+    /// Case insensitive part of the name
+    pub name: Option<String>,
+    pub kind: Option<CameraKind>,
+    /// Cameras need every one of these tags, case insensitive
+    pub tags: Vec<String>,
+    pub region: Option<Region>,
+}
+
+impl CameraFilter {
+    /// Filter for a `tag:<tag>` or `region:<region>` camera selector, `None` for plain names
+    /// and indexes
+    pub fn from_selector(selector: &str) -> Result<Option<Self>> {
+        match selector.split_once(':') {
+            Some((prefix, tag)) if prefix.eq_ignore_ascii_case("tag") => Ok(Some(CameraFilter {
+                tags: vec![tag.trim().to_string()],
+                ..Default::default()
+            })),
+            Some((prefix, region)) if prefix.eq_ignore_ascii_case("region") => {
+                Ok(Some(CameraFilter {
+                    region: Some(Region::parse(region)?),
+                    ..Default::default()
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn matches(&self, camera: &Camera) -> bool {
+        let name_matches = self
+            .name
+            .as_ref()
+            .is_none_or(|name| camera.name.to_lowercase().contains(&name.to_lowercase()));
+        let kind_matches = self.kind.is_none_or(|kind| camera.kind == kind);
+        let tags_match = self.tags.iter().all(|tag| {
+            camera
+                .tags
+                .iter()
+                .any(|camera_tag| camera_tag.eq_ignore_ascii_case(tag))
+        });
+        let region_matches = self.region.is_none_or(|region| region.contains(camera));
+
+        name_matches && kind_matches && tags_match && region_matches
+    }
+
+    /// Matching cameras with their 1-based index in the full list
+    pub fn apply<'a>(&self, cameras: &'a [Camera]) -> Vec<(usize, &'a Camera)> {
+        cameras
+            .iter()
+            .enumerate()
+            .filter(|(_, camera)| self.matches(camera))
+            .map(|(i, camera)| (i + 1, camera))
+            .collect()
+    }
 }
 
 pub fn get_embedded_cameras() -> Result<Vec<Camera>> {
     let content = include_str!("../resources/cams.json");
     let cameras: Vec<Camera> = serde_json::from_str(content)?;
+    validate_cameras(&cameras)?;
     Ok(cameras)
 }
 
 pub fn load_cameras<P: AsRef<Path>>(path: P) -> Result<Vec<Camera>> {
     let content = fs::read_to_string(path)?;
     let cameras: Vec<Camera> = serde_json::from_str(&content)?;
+    validate_cameras(&cameras)?;
     Ok(cameras)
 }
 
+pub fn validate_cameras(cameras: &[Camera]) -> Result<()> {
+    cameras.iter().try_for_each(Camera::validate)
+}
+
+/// Camera by 1-based index, part of its name, or the first one matching a `tag:<tag>` or
+/// `region:<region>` selector
 pub fn find_camera(cameras: &[Camera], selector: &str) -> Result<Camera> {
+    if let Some(filter) = CameraFilter::from_selector(selector)? {
+        return filter
+            .apply(cameras)
+            .first()
+            .map(|(_, camera)| (*camera).clone())
+            .ok_or_else(|| anyhow!("No camera matches {}", selector));
+    }
+
     if let Ok(index) = selector.parse::<usize>() {
         if index > 0 && index <= cameras.len() {
             return Ok(cameras[index - 1].clone());
@@ -44,10 +383,47 @@ pub fn find_camera(cameras: &[Camera], selector: &str) -> Result<Camera> {
     Err(anyhow!("Camera not found: {}", selector))
 }
 
+/// Cameras for every selector in turn, where tags and regions stand for every camera they
+/// match. A camera picked twice is only listed the first time
+pub fn find_cameras(cameras: &[Camera], selectors: &[String]) -> Result<Vec<Camera>> {
+    let mut selected: Vec<Camera> = Vec::new();
+    for selector in selectors {
+        let matching = match CameraFilter::from_selector(selector)? {
+            Some(filter) => filter
+                .apply(cameras)
+                .into_iter()
+                .map(|(_, camera)| camera.clone())
+                .collect(),
+            None => vec![find_camera(cameras, selector).context(format!(
+                "Failed to find camera: {}\n{}",
+                selector,
+                list_cameras(cameras)
+            ))?],
+        };
+        if matching.is_empty() {
+            return Err(anyhow!("No camera matches {}", selector));
+        }
+        for camera in matching {
+            if !selected.iter().any(|c| c.name == camera.name) {
+                selected.push(camera);
+            }
+        }
+    }
+    Ok(selected)
+}
+
+/// Numbered camera names with their location and tags, for picking one
 pub fn list_cameras(cameras: &[Camera]) -> String {
     let mut result = String::from("Available cameras:\n");
     for (i, camera) in cameras.iter().enumerate() {
-        result.push_str(&format!("  {}. {}\n", i + 1, camera.name));
+        result.push_str(&format!("  {}. {}", i + 1, camera.name));
+        if let (Some(latitude), Some(longitude)) = (camera.latitude, camera.longitude) {
+            result.push_str(&format!(" ({:.4}, {:.4})", latitude, longitude));
+        }
+        if !camera.tags.is_empty() {
+            result.push_str(&format!(" [{}]", camera.tags.join(", ")));
+        }
+        result.push('\n');
     }
     result
 }
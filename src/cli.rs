@@ -1,55 +1,438 @@
-use clap::{Parser, ValueEnum};
+use citycam::effects;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-/// A tool to process webcam images and set them as wallpaper
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+/// A tool to process webcam images and set them as wallpaper. Without a subcommand it
+/// fetches a single frame, or rotates with --rotate
+///
+/// Flags can also be set in config.toml, the flags given here override it. Serializes to the
+/// config file format for `config show`
+#[derive(Parser, Clone, Debug, Serialize)]
+#[command(author, version, about, long_about = None, args_override_self = true)]
+#[serde(rename_all = "kebab-case")]
 pub struct Args {
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub command: Option<Command>,
+
+    /// Config file to use instead of config.toml in the config directory
+    #[arg(long, global = true)]
+    #[serde(skip)]
+    pub config: Option<std::path::PathBuf>,
+
+    /// Apply the settings of a [profile.<name>] table from the config file
+    #[arg(long, global = true)]
+    #[serde(skip)]
+    pub profile: Option<String>,
+
     /// Convert image to grayscale
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub grayscale: bool,
 
     /// Apply color to sky based on time of day
-    #[arg(short = 's', long)]
+    #[arg(short = 's', long, global = true)]
     pub color_sky: bool,
 
     /// Type of noise to apply to the image
-    #[arg(short, long, value_enum)]
+    #[arg(short, long, value_enum, global = true)]
     pub noise: Option<NoiseType>,
 
     /// Noise intensity (0-255 for Salt/Pepper, standard deviation for Gaussian)
-    #[arg(short = 'i', long, default_value_t = 25.0)]
+    #[arg(short = 'i', long, default_value_t = 25.0, value_parser = amount(effects::NOISE_INTENSITY), global = true)]
     pub noise_intensity: f64,
 
     /// Skip caching the image
-    #[arg(long = "skip-cache")]
+    #[arg(long = "skip-cache", global = true)]
     pub skip_cache: bool,
 
-    /// Select camera by index (1-based) or name
-    #[arg(short, long)]
+    /// Don't keep the unprocessed frame next to each cached wallpaper for rerendering
+    #[arg(long, global = true)]
+    pub skip_originals: bool,
+
+    /// Format of the saved wallpapers: jpg, png, webp (lossless) or avif
+    #[arg(long, default_value = "jpg", global = true)]
+    pub output_format: String,
+
+    /// Quality of jpg and avif wallpapers, from 1 to 100
+    #[arg(long, default_value_t = 90, global = true)]
+    pub output_quality: u8,
+
+    /// Save jpg wallpapers as progressive JPEGs
+    #[arg(long, global = true)]
+    pub progressive: bool,
+
+    /// Keep at most this many cached wallpapers
+    #[arg(long, global = true)]
+    pub keep_last: Option<usize>,
+
+    /// Keep at most this many megabytes of cached wallpapers
+    #[arg(long, global = true)]
+    pub keep_size: Option<u64>,
+
+    /// Delete cached wallpapers older than this, e.g. 7d
+    #[arg(long, global = true)]
+    pub keep_age: Option<String>,
+
+    /// Keep only one cached wallpaper per hour once they are older than this, e.g. 1d
+    #[arg(long, global = true)]
+    pub keep_hourly_after: Option<String>,
+
+    /// Select camera by index (1-based), name, tag:<tag> or region:<region>
+    #[arg(short, long, global = true)]
     pub camera: Option<String>,
 
+    /// Use a local video file, .m3u8 playlist or directory of images instead of a camera
+    #[arg(long, conflicts_with_all = ["camera", "rotate"], global = true)]
+    pub source: Option<std::path::PathBuf>,
+
     /// Path to camera configuration file
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub cams_file: Option<std::path::PathBuf>,
 
     /// Enable camera rotation
-    #[arg(short = 'r', long, help = "Enable camera rotation")]
+    #[arg(
+        short = 'r',
+        long,
+        help = "Enable camera rotation, same as the rotate subcommand",
+        global = true
+    )]
     pub rotate: bool,
 
     /// Rotation interval in seconds
-    #[arg(long, default_value = "30", help = "Rotation interval in seconds")]
+    #[arg(
+        long,
+        default_value = "30",
+        help = "Rotation interval in seconds",
+        global = true
+    )]
     pub rotation_interval: u64,
 
+    /// How to pick the next camera: sequential, random, shuffle or weighted (by the `weight`
+    /// in cams.json). Defaults to the strategy used last time
+    #[arg(long, global = true)]
+    pub rotation_strategy: Option<String>,
+
+    /// Only rotate through these cameras during a time of day, e.g.
+    /// "05:30-09:00=harbor,pier" or "21:00-05:00=downtown". Can be repeated, the first
+    /// matching window wins and all cameras are used outside of them
+    #[arg(long = "playlist", global = true)]
+    #[serde(rename = "playlist")]
+    pub playlists: Vec<String>,
+
+    /// When to rotate instead of a fixed interval: every:<duration>, cron:<expression> or
+    /// sunrise/sunset[+|-<duration>]@<lat>,<lon>, several separated by ';'
+    #[arg(long, global = true)]
+    pub schedule: Option<String>,
+
+    /// Color the sky for this time instead of when the frame was recorded, e.g.
+    /// 2025-06-21T21:30:00-04:00 or "2025-06-21 21:30" (host time zone)
+    #[arg(long, global = true)]
+    pub at: Option<String>,
+
+    /// Show a camera on one monitor, e.g. "HDMI-1=harbor" or "DP-2=tag:lake|grayscale,sky"
+    /// with its own pipeline. Can be repeated, other monitors show --camera
+    #[arg(long = "output", global = true)]
+    #[serde(rename = "output")]
+    pub outputs: Vec<String>,
+
+    /// Monitor layout to use instead of asking xrandr or wlr-randr, e.g.
+    /// "HDMI-1=1920x1080+0+0". Can be repeated
+    #[arg(long = "monitor", global = true)]
+    #[serde(rename = "monitor")]
+    pub monitors: Vec<String>,
+
+    /// Stitch the monitors' images into one wallpaper spanning the whole layout instead of
+    /// setting one per monitor
+    #[arg(long, global = true)]
+    pub span: bool,
+
+    /// How to set the wallpaper: auto, native, sway, swaybg, swww, feh, gnome, kde, file
+    /// (only save it) or command:<template>, e.g. "command:swww img {path}"
+    #[arg(long, default_value = "auto", global = true)]
+    pub wallpaper_backend: String,
+
+    /// Resize the frame to the display: cover, contain (over a blurred fill), stretch or
+    /// smart (crop around the most detailed part). Frames keep their own size without it
+    #[arg(long, global = true)]
+    pub fit: Option<String>,
+
+    /// Resolution to fit frames to, e.g. 2560x1440. Defaults to the primary monitor's
+    #[arg(long, global = true)]
+    pub resolution: Option<String>,
+
+    /// Resampling filter for resizing: nearest, linear, cubic, gaussian or lanczos
+    #[arg(long, default_value = "lanczos", global = true)]
+    pub resample: String,
+
     /// Apply tint to the image
-    #[arg(short = 't', long)]
+    #[arg(short = 't', long, value_parser = hex_color, global = true)]
     pub tint_color: Option<String>,
 
     /// Tint intensity (0.0 to 1.0)
-    #[arg(long, default_value_t = 0.5)]
-    pub tint_intensity: f32
+    #[arg(long, default_value_t = 0.5, value_parser = amount(effects::TINT_INTENSITY), global = true)]
+    #[serde(serialize_with = "serialize_f32")]
+    pub tint_intensity: f32,
+
+    /// Keep bright, saturated light sources in glowing color over a grayscale frame
+    #[arg(long, global = true)]
+    pub neon: bool,
+
+    /// Hue ranges in degrees kept by --neon, e.g. "280-340,0-40" (default: all hues)
+    #[arg(long, global = true)]
+    pub neon_hues: Option<String>,
+
+    /// Minimum saturation (0.0 to 1.0) for a pixel to stay in color with --neon
+    #[arg(long, default_value_t = 0.45, value_parser = amount(effects::NEON_SATURATION), global = true)]
+    #[serde(serialize_with = "serialize_f32")]
+    pub neon_saturation: f32,
+
+    /// Glow radius around neon colors in pixels, 0 disables the glow
+    #[arg(long, default_value_t = 6.0, value_parser = amount(effects::NEON_GLOW), global = true)]
+    #[serde(serialize_with = "serialize_f32")]
+    pub neon_glow: f32,
+
+    /// Draw a flying saucer beaming light down onto the skyline
+    #[arg(long, global = true)]
+    pub alien_beams: bool,
+
+    /// Color of the alien beams
+    #[arg(long, default_value = "#7dffb0", value_parser = hex_color, global = true)]
+    pub beam_color: String,
+
+    /// Number of alien beams
+    #[arg(long, default_value_t = 3, value_parser = amount(effects::BEAM_COUNT), global = true)]
+    pub beam_count: u32,
+
+    /// Seed for overlays, reuse it to reproduce the same scene
+    #[arg(long, global = true)]
+    pub overlay_seed: Option<u64>,
+
+    /// Ordered effect pipeline, e.g. "grayscale,sky,tint:#ff5500@0.3,noise:gaussian@20"
+    /// or "neon:280-340+0-40@0.5@8,noise:poisson,beams:#7dffb0@3@42".
+    /// Overrides the individual effect flags
+    #[arg(short = 'p', long, global = true)]
+    pub pipeline: Option<String>,
+
+    /// Which live playlist segment to grab: latest, first, closest (to the current time)
+    /// or nth:<index>, where negative indexes count back from the newest
+    #[arg(long, default_value = "latest", global = true)]
+    pub segment: String,
+
+    /// Which rendition of a master playlist to use: highest, first, closest:<width>x<height>,
+    /// max-bandwidth:<bits per second> or index:<n>
+    #[arg(long, default_value = "highest", global = true)]
+    pub variant: String,
+
+    /// Grab the frame this many seconds into the stream segment instead of the first one
+    #[arg(long, global = true)]
+    pub frame_offset: Option<f64>,
+
+    /// Decode this many frames across the segment and keep the sharpest, best exposed one
+    #[arg(long, conflicts_with = "frame_offset", global = true)]
+    pub best_of: Option<usize>,
+
+    /// Blend several frames into a ghost/motion-trail image: mean, max, trail[@decay]
+    /// or median[@opacity]
+    #[arg(long, global = true)]
+    pub ghost: Option<String>,
+
+    /// Number of frames to blend in ghost mode
+    #[arg(long, default_value_t = 8, global = true)]
+    pub ghost_frames: usize,
+
+    /// Print details about the stream being captured, such as the variants on offer
+    #[arg(short = 'v', long, global = true)]
+    pub verbose: bool,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Fetch a single frame and set it as wallpaper (the default)
+    Fetch,
+    /// Rotate through the cameras on a schedule (same as --rotate)
+    Rotate,
+    /// List the available cameras
+    List {
+        /// Only cameras whose name contains this text
+        filter: Option<String>,
+        /// Only cameras of this kind: wetmet, hls, snapshot, mjpeg, file or directory
+        #[arg(long)]
+        kind: Option<String>,
+        /// Only cameras with this tag, can be repeated to require several
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Only cameras in a region: <lat>,<lon>,<lat>,<lon> for the box between two corners
+        /// or <lat>,<lon>@<km> for a radius around a point
+        #[arg(long, allow_hyphen_values = true)]
+        region: Option<String>,
+        /// Print the cameras as JSON, in the cams.json format
+        #[arg(long)]
+        json: bool,
+    },
+    /// Grab a frame from a camera and show what the stream offers, without setting the
+    /// wallpaper
+    Probe {
+        /// Camera index (1-based), name, tag:<tag> or region:<region>
+        camera: String,
+    },
+    /// Manage the cached wallpapers
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Browse the wallpapers saved to the cache and set one again
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+    /// Apply the effect flags or --pipeline to the original frame of a saved wallpaper and
+    /// set the result, without fetching anything
+    Rerender {
+        /// Wallpaper id from `citycam history list`, a prefix of one, or "latest"
+        id: String,
+    },
+    /// Turn the saved wallpapers of the --camera camera into a timelapse
+    Timelapse {
+        /// File to write, the extension picks the format: .mp4, .webm, .gif or .png (animated)
+        file: std::path::PathBuf,
+        /// Only frames captured at or after this time, e.g. "2025-03-10 18:00"
+        #[arg(long)]
+        since: Option<String>,
+        /// Only frames captured at or before this time
+        #[arg(long)]
+        until: Option<String>,
+        /// Frames per second
+        #[arg(long, default_value_t = 24)]
+        fps: u32,
+        /// Even out the exposure between frames so the video doesn't flicker
+        #[arg(long)]
+        deflicker: bool,
+        /// Write the capture time into the corner of every frame
+        #[arg(long)]
+        timestamps: bool,
+        /// Use the raw frames saved by `citycam record` instead of the wallpapers
+        #[arg(long)]
+        recorded: bool,
+    },
+    /// Save raw frames of cameras on a schedule without changing the wallpaper
+    Record {
+        /// Cameras to record: index, name, tag:<tag> or region:<region>, where tags and
+        /// regions record every matching camera. Defaults to --camera or --source
+        cameras: Vec<String>,
+        /// Time between frames, e.g. 30s or 5m. --schedule overrides it
+        #[arg(long, default_value = "5m")]
+        interval: String,
+        /// Image format: png (lossless), jpg or webp (lossless)
+        #[arg(long, default_value = "png")]
+        format: String,
+        /// JPEG quality from 1 to 100
+        #[arg(long, default_value_t = 95)]
+        quality: u8,
+        /// Directory to keep the frames in, "archive" in the cache directory by default
+        #[arg(long)]
+        dir: Option<std::path::PathBuf>,
+        /// Record one frame of every camera and exit, e.g. when run from cron
+        #[arg(long)]
+        once: bool,
+    },
+    /// List the effects available to --pipeline and their parameters
+    Effects,
+    /// Control a running rotation daemon
+    Ctl {
+        #[arg(value_enum)]
+        command: ControlCommand,
+    },
+    /// Show how each camera has been doing in the rotation
+    Health,
+    /// List the monitors and their layout
+    Monitors,
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum ConfigAction {
+    /// Print the settings in effect after merging the config file, profile and flags
+    Show,
+    /// Print where the config file is read from
+    Path,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum CacheAction {
+    /// Print the cache directory
+    Path,
+    /// List the cached wallpapers, oldest first
+    List,
+    /// Delete the cached wallpapers
+    Clear,
+    /// Delete the cached wallpapers the --keep-* limits don't keep
+    Prune {
+        /// Only list what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum HistoryAction {
+    /// List the saved wallpapers, oldest first
+    List {
+        /// Only wallpapers whose camera, effects or output contain this text
+        filter: Option<String>,
+        /// Only wallpapers from cameras whose name contains this text
+        #[arg(long)]
+        camera: Option<String>,
+        /// Only wallpapers captured at or after this time, e.g. "2025-03-10 18:00"
+        #[arg(long)]
+        since: Option<String>,
+        /// Only wallpapers captured at or before this time
+        #[arg(long)]
+        until: Option<String>,
+        /// Only the newest this many
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Print the entries as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print everything recorded about a wallpaper
+    Show {
+        /// Wallpaper id as listed, a prefix of it, or "latest"
+        id: String,
+    },
+    /// Set a saved wallpaper again
+    Apply {
+        /// Wallpaper id as listed, a prefix of it, or "latest"
+        id: String,
+    },
+}
+
+/// Commands understood by the rotation daemon's control socket
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+pub enum ControlCommand {
+    /// Switch to the next camera now
+    Next,
+    /// Stop changing the wallpaper until resumed
+    Pause,
+    /// Continue the schedule after a pause
+    Resume,
+    /// Show the current camera and when the next change is due
+    Status,
+    /// Read the config file and cameras again, keeping the command line's flags
+    Reload,
+    /// Shut the daemon down
+    Stop,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum NoiseType {
     /// Add Gaussian noise to the image
     Gaussian,
@@ -58,3 +441,25 @@ pub enum NoiseType {
     /// Add Poisson noise to the image
     Poisson,
 }
+
+// f32 flags would otherwise show up in `config show` as e.g. 0.44999998807907104
+fn serialize_f32<S: serde::Serializer>(value: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(value.to_string().parse().unwrap_or(*value as f64))
+}
+
+// Colors are checked up front, the effects have no way to report a bad one
+fn hex_color(value: &str) -> Result<String, String> {
+    match citycam::image_processing::hex_to_rgb(value) {
+        Some(_) => Ok(value.to_string()),
+        None => Err("expected a color like #ff5500".to_string()),
+    }
+}
+
+fn amount<T>(
+    range: RangeInclusive<T>,
+) -> impl Fn(&str) -> Result<T, String> + Clone + Send + Sync + 'static
+where
+    T: FromStr + PartialOrd + Display + Clone + Send + Sync + 'static,
+{
+    move |value| effects::parse_amount(value, range.clone()).map_err(|e| e.to_string())
+}
@@ -0,0 +1,432 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, Utc};
+use citycam::archive;
+use citycam::backend;
+use citycam::camera::{self, Camera, CameraFilter};
+use citycam::config::Config;
+use citycam::effects::AVAILABLE_EFFECTS;
+use citycam::fit::{self, FitMode};
+use citycam::health;
+use citycam::history::{self, HistoryEntry, HistoryFilter};
+use citycam::retention;
+use citycam::source;
+use citycam::stream::{FrameSelection, SegmentInfo};
+use citycam::sun;
+use citycam::timelapse::{self, TimelapseFormat, TimelapseOptions};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::cli::{self, CacheAction, ConfigAction, HistoryAction};
+use crate::image_processor;
+use crate::rotation;
+use crate::utils;
+
+/// Print the cameras matching the filters, as a numbered list or as JSON
+pub fn list(args: &cli::Args, config: &Config, filter: &CameraFilter, json: bool) -> Result<()> {
+    let cameras = crate::load_cameras(args, config)?;
+    let matching = filter.apply(&cameras);
+
+    if json {
+        let cameras: Vec<_> = matching.into_iter().map(|(_, camera)| camera).collect();
+        println!("{}", serde_json::to_string_pretty(&cameras)?);
+        return Ok(());
+    }
+
+    for (index, camera) in matching {
+        if camera.tags.is_empty() {
+            println!("{:>3}. {} ({})", index, camera.name, camera.kind);
+        } else {
+            println!(
+                "{:>3}. {} ({}) [{}]",
+                index,
+                camera.name,
+                camera.kind,
+                camera.tags.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Grab one frame from a camera and print what the source reported about it
+pub fn probe(args: &cli::Args, config: &Config, selector: &str) -> Result<()> {
+    let cameras = crate::load_cameras(args, config)?;
+    let camera = camera::find_camera(&cameras, selector).context(format!(
+        "Failed to find camera: {}\n{}",
+        selector,
+        camera::list_cameras(&cameras)
+    ))?;
+
+    println!("Camera:   {}", camera.name);
+    println!("Kind:     {}", camera.kind);
+    println!("URL:      {}", camera.url);
+    if let (Some(latitude), Some(longitude)) = (camera.latitude, camera.longitude) {
+        println!("Location: {:.4}, {:.4}", latitude, longitude);
+    }
+    if let Some(timezone) = &camera.timezone {
+        println!("Timezone: {}", timezone);
+    }
+    if !camera.tags.is_empty() {
+        println!("Tags:     {}", camera.tags.join(", "));
+    }
+    if let Some(attribution) = &camera.attribution {
+        println!("Credit:   {}", attribution);
+    }
+
+    let options = image_processor::stream_options(args)?;
+    let started = Instant::now();
+    let capture = source::capture(&camera, &options, FrameSelection::Spread(1))?;
+    let elapsed = started.elapsed();
+
+    if let Some(frame) = capture.frames.first() {
+        println!("Frame:    {}x{}", frame.width(), frame.height());
+    }
+    println!("Fetched:  {:.2}s", elapsed.as_secs_f64());
+    match capture.segment.timestamp {
+        Some(timestamp) => println!(
+            "Recorded: {}",
+            timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+        ),
+        None => println!("Recorded: unknown"),
+    }
+
+    if !capture.segment.available_variants.is_empty() {
+        println!("Variants:");
+        for variant in &capture.segment.available_variants {
+            let marker = if capture.segment.variant.as_ref() == Some(variant) {
+                "*"
+            } else {
+                " "
+            };
+            println!("  {} {}", marker, variant);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn cache(action: &CacheAction, args: &cli::Args, cache_dir: &Path) -> Result<()> {
+    match action {
+        CacheAction::Path => println!("{}", cache_dir.display()),
+        CacheAction::List => {
+            let mut total = 0;
+            for file in retention::cached_files(cache_dir)? {
+                total += file.size;
+                println!("{:>10}  {}", format_size(file.size), file.path.display());
+            }
+            println!("{:>10}  total", format_size(total));
+        }
+        CacheAction::Clear => {
+            let wallpapers = retention::cached_files(cache_dir)?;
+            for file in &wallpapers {
+                retention::remove(&file.path)?;
+            }
+            history::forget_missing(&cache_dir.join(history::INDEX_FILE_NAME))?;
+            println!("Deleted {} cached wallpapers", wallpapers.len());
+        }
+        CacheAction::Prune { dry_run } => {
+            let policy = image_processor::retention_policy(args)?;
+            if policy.is_empty() {
+                return Err(anyhow!(
+                    "Nothing to prune by, set --keep-last, --keep-size, --keep-age or \
+                     --keep-hourly-after"
+                ));
+            }
+
+            let expired =
+                retention::prune(cache_dir, &policy, Local::now().naive_local(), *dry_run)?;
+            if !dry_run {
+                history::forget_missing(&cache_dir.join(history::INDEX_FILE_NAME))?;
+            }
+            for file in &expired {
+                println!("{:>10}  {}", format_size(file.size), file.path.display());
+            }
+            let freed: u64 = expired.iter().map(|file| file.size).sum();
+            println!(
+                "{} {} cached wallpapers ({})",
+                if *dry_run { "Would delete" } else { "Deleted" },
+                expired.len(),
+                format_size(freed)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// List, inspect or bring back the wallpapers recorded in the history
+pub fn history(action: &HistoryAction, args: &cli::Args, cache_dir: &Path) -> Result<()> {
+    let entries = history::load(&cache_dir.join(history::INDEX_FILE_NAME))?;
+
+    match action {
+        HistoryAction::List {
+            filter,
+            camera,
+            since,
+            until,
+            limit,
+            json,
+        } => {
+            let filter = HistoryFilter {
+                text: filter.clone(),
+                camera: camera.clone(),
+                since: since.as_deref().map(sun::parse_datetime).transpose()?,
+                until: until.as_deref().map(sun::parse_datetime).transpose()?,
+                recorded: None,
+            };
+            let matching: Vec<&HistoryEntry> = entries
+                .iter()
+                .filter(|entry| filter.matches(entry))
+                .collect();
+            // The newest are the interesting ones when there are too many
+            let skip = limit.map_or(0, |limit| matching.len().saturating_sub(limit));
+            let matching = &matching[skip..];
+
+            if *json {
+                println!("{}", serde_json::to_string_pretty(matching)?);
+                return Ok(());
+            }
+            for entry in matching {
+                let time = entry.captured_at.unwrap_or(entry.saved_at);
+                let pipeline = if entry.recorded {
+                    "recorded"
+                } else if entry.pipeline.is_empty() {
+                    "no effects"
+                } else {
+                    &entry.pipeline
+                };
+                println!(
+                    "{}  {}  {} ({})",
+                    entry.id(),
+                    time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+                    entry.camera,
+                    pipeline
+                );
+            }
+        }
+        HistoryAction::Show { id } => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(history::find(&entries, id)?)?
+            );
+        }
+        HistoryAction::Apply { id } => {
+            let entry = history::find(&entries, id)?;
+            if !entry.path.exists() {
+                return Err(anyhow!(
+                    "{} is no longer in the cache: {}",
+                    entry.id(),
+                    entry.path.display()
+                ));
+            }
+            let setter = backend::parse(&args.wallpaper_backend)?;
+            match &entry.output {
+                // Back on the monitor it was made for
+                Some(output) if output != "span" => {
+                    setter.set_outputs(&[(output.clone(), entry.path.clone())])?
+                }
+                _ => setter.set(&entry.path)?,
+            }
+            println!("Set {} from {}", entry.id(), entry.camera);
+        }
+    }
+    Ok(())
+}
+
+/// Render the original frame of a saved wallpaper with the current effects into a new wallpaper
+/// and set it. The new one is named after now, the capture time already has a wallpaper
+pub fn rerender(args: &cli::Args, config: &Config, cache_dir: &Path, id: &str) -> Result<()> {
+    let entries = history::load(&cache_dir.join(history::INDEX_FILE_NAME))?;
+    let entry = history::find(&entries, id)?;
+    let source = entry.source_frame().ok_or_else(|| {
+        anyhow!(
+            "{} has no original frame to render again, it was saved with --skip-originals, \
+             as a span or before originals were kept",
+            entry.id()
+        )
+    })?;
+    let frame = image::open(source)
+        .context(format!("Failed to open {}", source.display()))?
+        .to_rgb8();
+
+    // The camera's location and crop, when it is still in the list
+    let cameras = crate::load_cameras(args, config)?;
+    let camera = cameras
+        .into_iter()
+        .find(|camera| camera.name == entry.camera)
+        .unwrap_or_else(|| Camera {
+            name: entry.camera.clone(),
+            url: entry.url.clone(),
+            ..Default::default()
+        });
+    // Effects follow the time the wallpaper was rendered for unless --at says otherwise
+    let segment = SegmentInfo {
+        uri: entry.segment.clone(),
+        timestamp: entry.at.or(entry.captured_at),
+        ..Default::default()
+    };
+
+    let pipeline = image_processor::build_pipeline(args)?;
+    let pipeline_spec = pipeline.spec();
+    let image = image_processor::render(&frame, pipeline, &camera, &segment, args)?;
+    let (image, fit) = match &entry.fit {
+        _ if args.fit.is_some() || args.resolution.is_some() => (
+            image_processor::fit_to_display(image, &camera, args)?,
+            Some(image_processor::fit_name(args)?),
+        ),
+        // Same size and fit as before
+        Some(fit) => {
+            let (_, filter) = image_processor::fit_options(args)?;
+            let mode = FitMode::parse(fit)?;
+            let image = fit::fit(&image, entry.width, entry.height, mode, filter, camera.crop);
+            (image, Some(fit.clone()))
+        }
+        None => (image, None),
+    };
+
+    let output = entry.output.as_deref().filter(|_| !entry.recorded);
+    let path = image_processor::wallpaper_path(args, cache_dir, output)?;
+    let setter = backend::parse(&args.wallpaper_backend)?;
+    image_processor::save_wallpaper(&image, &path, args)?;
+    match output {
+        Some(output) => setter.set_outputs(&[(output.to_string(), path.clone())])?,
+        None => setter.set(&path)?,
+    }
+
+    let rendered =
+        image_processor::history_entry(&camera, &segment, pipeline_spec, &image, &path, args)?;
+    let new_entry = HistoryEntry {
+        variant: entry.variant.clone(),
+        captured_at: entry.captured_at,
+        at: rendered.at.or(entry.at),
+        fit,
+        output: output.map(str::to_string),
+        original: image_processor::reuse_original(args, &frame, source, &path),
+        ..rendered
+    };
+    image_processor::record_history(args, cache_dir, &new_entry);
+    println!("Set {} rendered from {}", new_entry.id(), entry.id());
+    image_processor::prune_cache(args, cache_dir);
+    Ok(())
+}
+
+/// Write a timelapse of the `--camera` camera's saved wallpapers, or of the only camera in the
+/// history when none is selected
+pub fn timelapse(
+    args: &cli::Args,
+    config: &Config,
+    cache_dir: &Path,
+    file: &Path,
+    filter: HistoryFilter,
+    options: &TimelapseOptions,
+) -> Result<()> {
+    // Catch a bad file name before reading any frames
+    TimelapseFormat::from_path(file)?;
+    let entries = history::load(&cache_dir.join(history::INDEX_FILE_NAME))?;
+    let mut frames = timelapse::select_frames(&entries, &filter);
+    let unreadable = frames
+        .iter()
+        .filter(|entry| !archive::readable(&entry.path))
+        .count();
+    if unreadable > 0 {
+        println!(
+            "Leaving out {} AVIF wallpapers, they can't be read back",
+            unreadable
+        );
+        frames.retain(|entry| archive::readable(&entry.path));
+    }
+
+    if let Some(selector) = &args.camera {
+        let cameras = crate::load_cameras(args, config)?;
+        let selected = camera::find_camera(&cameras, selector).context(format!(
+            "Failed to find camera: {}\n{}",
+            selector,
+            camera::list_cameras(&cameras)
+        ))?;
+        frames.retain(|entry| entry.camera == selected.name);
+    }
+
+    let mut cameras: Vec<&str> = frames.iter().map(|entry| entry.camera.as_str()).collect();
+    cameras.sort_unstable();
+    cameras.dedup();
+    match cameras.as_slice() {
+        [] if filter.recorded == Some(true) => {
+            return Err(anyhow!("No recorded frames to make a timelapse of"))
+        }
+        [] => {
+            return Err(anyhow!(
+                "No saved wallpapers to make a timelapse of, --recorded uses the frames of \
+                 citycam record"
+            ))
+        }
+        [camera] => println!("{} frames of {}", frames.len(), camera),
+        cameras => {
+            return Err(anyhow!(
+                "The history has frames of several cameras, pick one with --camera: {}",
+                cameras.join(", ")
+            ))
+        }
+    }
+
+    timelapse::write(file, &frames, options)?;
+    println!("Wrote {}", file.display());
+    Ok(())
+}
+
+pub fn config(action: &ConfigAction, args: &cli::Args) -> Result<()> {
+    match action {
+        ConfigAction::Show => {
+            if let Some(profile) = &args.profile {
+                println!("# profile: {}", profile);
+            }
+            print!("{}", toml::to_string(args)?);
+        }
+        ConfigAction::Path => match crate::config_path(args)? {
+            Some(path) => println!("{}", path.display()),
+            None => println!("{} (not created yet)", utils::get_config_path()?.display()),
+        },
+    }
+    Ok(())
+}
+
+pub fn effects() {
+    for effect in AVAILABLE_EFFECTS {
+        println!("{}", effect.usage);
+        println!("    {}", effect.description);
+        println!("    e.g. {}", effect.example);
+    }
+}
+
+pub fn health(args: &cli::Args, config: &Config, cache_dir: &Path) -> Result<()> {
+    let tracker = rotation::load_health(cache_dir);
+    let cameras = crate::load_cameras(args, config)?;
+    print!("{}", health::format_table(&tracker, &cameras, Utc::now()));
+    Ok(())
+}
+
+pub fn monitors(args: &cli::Args) -> Result<()> {
+    for monitor in crate::monitors::monitors(args)? {
+        let scale = match monitor.scale {
+            scale if scale != 1.0 => format!("  scale {}", scale),
+            _ => String::new(),
+        };
+        println!(
+            "{:<12} {}x{}+{}+{}{}{}",
+            monitor.name,
+            monitor.width,
+            monitor.height,
+            monitor.x,
+            monitor.y,
+            scale,
+            if monitor.primary { "  primary" } else { "" }
+        );
+    }
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{} B", b),
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::{anyhow, Context, Result};
+use clap::parser::ValueSource;
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use toml::{Table, Value};
+
+use crate::camera::{self, Camera};
+
+/// Settings from config.toml. Top level keys are defaults named like the long command line
+/// flags, e.g. `grayscale = true` or `tint-color = "#ff5500"`, `[profile.<name>]` tables hold
+/// the same keys for `--profile <name>` and `[[cameras]]` replaces the embedded camera list
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub cameras: Vec<Camera>,
+    #[serde(default, rename = "profile")]
+    pub profiles: BTreeMap<String, Table>,
+    #[serde(flatten)]
+    pub defaults: Table,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let content = fs::read_to_string(path)
+            .context(format!("Failed to read config file {}", path.display()))?;
+        Config::parse(&content).context(format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn parse(content: &str) -> Result<Config> {
+        let config: Config = toml::from_str(content)?;
+        camera::validate_cameras(&config.cameras)?;
+        Ok(config)
+    }
+
+    /// The command line with the settings put in front of it as flags, the profile's over the
+    /// defaults. A setting is left out when a flag on the command line, or for the defaults one
+    /// in the profile, sets the same thing or conflicts with it. `command` should come from
+    /// `negatable_flags` so that `false` can turn a flag off
+    pub fn merge_args(
+        &self,
+        command: &Command,
+        command_line: &[OsString],
+        profile: Option<&str>,
+    ) -> Result<Vec<OsString>> {
+        let mut layers = Vec::new();
+        if let Some(name) = profile {
+            layers.push(self.profiles.get(name).ok_or_else(|| {
+                let available: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                anyhow!(
+                    "Unknown profile: {} (available: {})",
+                    name,
+                    if available.is_empty() {
+                        "none".to_string()
+                    } else {
+                        available.join(", ")
+                    }
+                )
+            })?);
+        }
+        layers.push(&self.defaults);
+
+        let matches = command.clone().try_get_matches_from(command_line)?;
+        let mut taken: Vec<&Arg> = command
+            .get_arguments()
+            .filter(|arg| given(&matches, arg.get_id().as_str()))
+            .collect();
+
+        // The profile comes after the defaults so its arrays don't mix with theirs
+        let mut settings = Vec::new();
+        for layer in layers {
+            let mut layer_args = Vec::new();
+            let mut layer_taken = Vec::new();
+            for (key, value) in layer {
+                let arg = command
+                    .get_arguments()
+                    .find(|arg| {
+                        arg.get_long() == Some(key) && !matches!(key.as_str(), "config" | "profile")
+                    })
+                    .ok_or_else(|| anyhow!("Unknown setting: {}", key))?;
+                if taken
+                    .iter()
+                    .any(|other| *other == arg || conflict(command, arg, other))
+                {
+                    continue;
+                }
+                push_args(key, value, &mut layer_args)?;
+                layer_taken.push(arg);
+            }
+            taken.extend(layer_taken);
+            settings.splice(0..0, layer_args);
+        }
+
+        Ok(command_line
+            .iter()
+            .take(1)
+            .cloned()
+            .chain(settings.into_iter().map(OsString::from))
+            .chain(command_line.iter().skip(1).cloned())
+            .collect())
+    }
+}
+
+/// Let every boolean flag take a value too, `--grayscale=false`, so the command line can turn
+/// off what the config turns on
+pub fn negatable_flags(command: Command) -> Command {
+    command.mut_args(|arg| {
+        if !matches!(arg.get_action(), ArgAction::SetTrue) {
+            return arg;
+        }
+        arg.action(ArgAction::Set)
+            .value_parser(value_parser!(bool))
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("true")
+            .default_value("false")
+    })
+}
+
+/// Whether a flag was given on the command line, before the subcommand or after it, rather
+/// than coming from its default
+pub fn given(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+        || matches
+            .subcommand()
+            .is_some_and(|(_, matches)| given(matches, id))
+}
+
+fn conflict(command: &Command, a: &Arg, b: &Arg) -> bool {
+    command.get_arg_conflicts_with(a).contains(&b) || command.get_arg_conflicts_with(b).contains(&a)
+}
+
+// `key = true` becomes `--key=true`, arrays repeat the flag
+fn push_args(key: &str, value: &Value, args: &mut Vec<String>) -> Result<()> {
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                args.push(format!("--{}={}", key, scalar(key, value)?));
+            }
+        }
+        value => args.push(format!("--{}={}", key, scalar(key, value)?)),
+    }
+    Ok(())
+}
+
+fn scalar(key: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Integer(i) => Ok(i.to_string()),
+        Value::Float(f) => Ok(f.to_string()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        _ => Err(anyhow!("Unsupported value for {}: {}", key, value)),
+    }
+}
@@ -0,0 +1,298 @@
+use anyhow::{anyhow, Context, Result};
+use citycam::config::Config;
+use citycam::playlist::Rotation;
+use citycam::schedule::Schedule;
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::cli::{self, ControlCommand};
+use crate::rotation;
+use crate::ticker::Ticker;
+use crate::utils;
+
+const PIDFILE_NAME: &str = "citycam.pid";
+const SOCKET_NAME: &str = "citycam.sock";
+
+/// A control command from the socket and where to send the answer
+struct Request {
+    command: ControlCommand,
+    reply: mpsc::Sender<String>,
+}
+
+/// Rotate wallpapers on a schedule until SIGTERM/SIGINT or a `stop` command, taking
+/// commands from `citycam ctl` in the meantime
+pub fn run(args: &cli::Args, config: &Config, cache_dir: &Path) -> Result<()> {
+    let mut args = args.clone();
+    let mut ticker = Ticker::start(schedule(&args)?)?;
+
+    let runtime_dir = utils::get_runtime_dir()?;
+    fs::create_dir_all(&runtime_dir)?;
+    let _pidfile = PidFile::create(runtime_dir.join(PIDFILE_NAME))?;
+
+    let (sender, requests) = mpsc::channel();
+    let _socket = control::serve(&runtime_dir.join(SOCKET_NAME), sender)?;
+
+    let mut rotation = rotation::from_args(crate::load_cameras(&args, config)?, &args, cache_dir)?;
+
+    println!(
+        "Starting camera rotation daemon (pid {})",
+        std::process::id()
+    );
+
+    while ticker.running() {
+        if ticker.is_due() {
+            if let Err(e) = rotation::show_next(&mut rotation, &args, cache_dir) {
+                eprintln!("Failed to show a camera: {}", e);
+            }
+            ticker.reschedule();
+        }
+
+        let wait = ticker.wait();
+        let request = match requests.recv_timeout(wait) {
+            Ok(request) => request,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                std::thread::sleep(wait);
+                continue;
+            }
+        };
+
+        let reply = match request.command {
+            ControlCommand::Next => {
+                let shown = rotation::show_next(&mut rotation, &args, cache_dir);
+                ticker.reschedule();
+                match shown {
+                    Ok(name) => format!("Showing {}", name),
+                    Err(e) => format!("Failed to show a camera: {}", e),
+                }
+            }
+            ControlCommand::Pause => {
+                ticker.pause();
+                "Paused".to_string()
+            }
+            ControlCommand::Resume => {
+                ticker.resume();
+                "Resumed".to_string()
+            }
+            ControlCommand::Status => status(&rotation, &ticker),
+            ControlCommand::Reload => match reload(cache_dir) {
+                Ok((reloaded_args, reloaded_rotation, schedule)) => {
+                    let count = reloaded_rotation.cameras().len();
+                    args = reloaded_args;
+                    rotation = reloaded_rotation;
+                    ticker.set_schedule(schedule);
+                    format!("Reloaded the config, {} cameras", count)
+                }
+                Err(e) => format!("Reload failed, keeping the current settings: {}", e),
+            },
+            ControlCommand::Stop => {
+                ticker.stop();
+                "Stopping".to_string()
+            }
+        };
+        let _ = request.reply.send(reply);
+    }
+
+    println!("Shutting down camera rotation daemon");
+    Ok(())
+}
+
+/// `--schedule`, or every `--rotation-interval` seconds
+fn schedule(args: &cli::Args) -> Result<Schedule> {
+    match &args.schedule {
+        Some(spec) => Schedule::parse(spec),
+        None => Ok(Schedule::every(
+            i64::try_from(args.rotation_interval)
+                .ok()
+                .and_then(chrono::Duration::try_seconds)
+                .ok_or_else(|| anyhow!("Rotation interval too long: {}", args.rotation_interval))?,
+        )),
+    }
+}
+
+/// The command line on top of the config file and profile as they are now, with the rotation
+/// and schedule they make. Nothing changes unless all of it is valid
+fn reload(cache_dir: &Path) -> Result<(cli::Args, Rotation, Schedule)> {
+    let (args, config) = crate::reload_args()?;
+    let cameras = crate::load_cameras(&args, &config)?;
+    let rotation = rotation::from_args(cameras, &args, cache_dir)?;
+    let schedule = schedule(&args)?;
+    Ok((args, rotation, schedule))
+}
+
+fn status(rotation: &Rotation, ticker: &Ticker) -> String {
+    let state = if ticker.paused() { "paused" } else { "running" };
+    let camera = rotation
+        .current()
+        .map(|camera| camera.name.as_str())
+        .unwrap_or("none yet");
+    let next = match ticker.next() {
+        Some(due) => due.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => "-".to_string(),
+    };
+
+    format!(
+        "{} ({} rotation), camera: {}, next change: {}",
+        state,
+        rotation.strategy(),
+        camera,
+        next
+    )
+}
+
+/// Send a command to the running daemon and return its answer
+pub fn send_command(command: ControlCommand) -> Result<String> {
+    let socket_path = utils::get_runtime_dir()?.join(SOCKET_NAME);
+    let name = command
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .ok_or_else(|| anyhow!("Unknown command"))?;
+
+    control::send(&socket_path, &name)
+}
+
+/// Holds the pidfile for the lifetime of the daemon and removes it afterwards
+struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    fn create(path: PathBuf) -> Result<PidFile> {
+        if let Some(pid) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| content.trim().parse::<u32>().ok())
+        {
+            if process_alive(pid) {
+                return Err(anyhow!(
+                    "A rotation daemon is already running (pid {}, {})",
+                    pid,
+                    path.display()
+                ));
+            }
+        }
+
+        fs::write(&path, format!("{}\n", std::process::id()))
+            .context(format!("Failed to write pidfile {}", path.display()))?;
+        Ok(PidFile { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn process_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+// Without /proc a leftover pidfile is assumed stale, the control socket still refuses to
+// start a second daemon while the first one answers
+#[cfg(not(target_os = "linux"))]
+fn process_alive(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(unix)]
+mod control {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    /// How long a control client waits for the daemon, `next` has to capture a frame first
+    const REPLY_TIMEOUT: Duration = Duration::from_secs(120);
+
+    /// Removes the socket file when the daemon exits
+    pub struct Socket {
+        path: PathBuf,
+    }
+
+    impl Drop for Socket {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    /// Listen on a unix socket and forward one line commands to the main loop
+    pub fn serve(path: &Path, requests: mpsc::Sender<Request>) -> Result<Socket> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(anyhow!(
+                    "A rotation daemon is already listening on {}",
+                    path.display()
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .context(format!("Failed to bind control socket {}", path.display()))?;
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = handle_client(stream, &requests) {
+                    eprintln!("Control connection failed: {}", e);
+                }
+            }
+        });
+
+        Ok(Socket {
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn handle_client(mut stream: UnixStream, requests: &mpsc::Sender<Request>) -> Result<()> {
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+
+        let reply = match ControlCommand::from_str(line.trim(), true) {
+            Ok(command) => {
+                let (reply, answer) = mpsc::channel();
+                requests.send(Request { command, reply })?;
+                answer
+                    .recv_timeout(REPLY_TIMEOUT)
+                    .unwrap_or_else(|_| "No answer from the daemon".to_string())
+            }
+            Err(_) => format!("Unknown command: {}", line.trim()),
+        };
+
+        writeln!(stream, "{}", reply)?;
+        Ok(())
+    }
+
+    pub fn send(path: &Path, command: &str) -> Result<String> {
+        let mut stream = UnixStream::connect(path).context(format!(
+            "No rotation daemon running (could not connect to {})",
+            path.display()
+        ))?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        writeln!(stream, "{}", command)?;
+
+        let mut reply = String::new();
+        BufReader::new(&stream).read_line(&mut reply)?;
+        Ok(reply.trim_end().to_string())
+    }
+}
+
+// No unix sockets, the daemon runs on its schedule but can't be controlled
+#[cfg(not(unix))]
+mod control {
+    use super::*;
+
+    pub struct Socket;
+
+    pub fn serve(_path: &Path, _requests: mpsc::Sender<Request>) -> Result<Socket> {
+        Ok(Socket)
+    }
+
+    pub fn send(_path: &Path, _command: &str) -> Result<String> {
+        Err(anyhow!(
+            "Controlling the daemon is only supported on unix systems"
+        ))
+    }
+}
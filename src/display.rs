@@ -0,0 +1,283 @@
+use anyhow::{anyhow, Context, Result};
+use image::RgbImage;
+use std::process::Command;
+
+/// A connected display and where it sits in the desktop layout
+#[derive(Clone, Debug, PartialEq)]
+pub struct Monitor {
+    /// Output name such as `HDMI-1` or `eDP-1`
+    pub name: String,
+    /// Size of its current mode, in pixels, which is what its wallpaper is rendered at
+    pub width: u32,
+    pub height: u32,
+    /// Position in the layout, which a scaling compositor counts in logical pixels
+    pub x: i32,
+    pub y: i32,
+    pub primary: bool,
+    /// Pixels per logical pixel
+    pub scale: f64,
+}
+
+impl Monitor {
+    /// Parse `<name>=<width>x<height>[+<x>+<y>]` for monitors listed in the config instead
+    /// of detected
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (name, geometry) = spec
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Monitors look like HDMI-1=1920x1080+0+0: {}", spec))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("Monitor has no name: {}", spec));
+        }
+
+        let (width, height, x, y) = parse_geometry(geometry.trim())
+            .ok_or_else(|| anyhow!("Invalid monitor geometry: {}", geometry))?;
+        Ok(Monitor {
+            name: name.to_string(),
+            width,
+            height,
+            x,
+            y,
+            primary: false,
+            scale: 1.0,
+        })
+    }
+
+    /// Size in the layout, the mode size over the scale
+    pub fn layout_size(&self) -> (u32, u32) {
+        if self.scale > 0.0 && self.scale != 1.0 {
+            (
+                ((self.width as f64 / self.scale).round() as u32).max(1),
+                ((self.height as f64 / self.scale).round() as u32).max(1),
+            )
+        } else {
+            (self.width, self.height)
+        }
+    }
+}
+
+// `1920x1080+0+0` or `1920x1080`, positions may be negative as in `+-1920+0`. Sizes can't be zero
+fn parse_geometry(geometry: &str) -> Option<(u32, u32, i32, i32)> {
+    let (size, position) = match geometry.split_once('+') {
+        Some((size, position)) => (size, Some(position)),
+        None => (geometry, None),
+    };
+    let (width, height) = size.split_once('x')?;
+    let (x, y) = match position {
+        Some(position) => {
+            let (x, y) = position.split_once('+')?;
+            (x.parse().ok()?, y.parse().ok()?)
+        }
+        None => (0, 0),
+    };
+
+    let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some((width, height, x, y))
+}
+
+/// Active monitors in `xrandr --query` output
+pub fn parse_xrandr(output: &str) -> Vec<Monitor> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with(char::is_whitespace))
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let name = words.next()?;
+            if words.next()? != "connected" {
+                return None;
+            }
+
+            let rest: Vec<&str> = words.collect();
+            // Connected but switched off outputs have no geometry
+            let (width, height, x, y) = rest.iter().find_map(|word| parse_geometry(word))?;
+            Some(Monitor {
+                name: name.to_string(),
+                width,
+                height,
+                x,
+                y,
+                primary: rest.first() == Some(&"primary"),
+                scale: 1.0,
+            })
+        })
+        .collect()
+}
+
+/// Enabled monitors in `wlr-randr` output, sized by their current mode
+pub fn parse_wlr_randr(output: &str) -> Vec<Monitor> {
+    let mut monitors = Vec::new();
+    // The monitor and whether it is enabled
+    let mut current: Option<(Monitor, bool)> = None;
+
+    fn finish(current: Option<(Monitor, bool)>) -> Option<Monitor> {
+        current
+            .filter(|(_, enabled)| *enabled)
+            .map(|(monitor, _)| monitor)
+    }
+
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            monitors.extend(finish(current.take()));
+            let Some(name) = line.split_whitespace().next() else {
+                continue;
+            };
+            current = Some((
+                Monitor {
+                    name: name.to_string(),
+                    width: 0,
+                    height: 0,
+                    x: 0,
+                    y: 0,
+                    primary: false,
+                    scale: 1.0,
+                },
+                true,
+            ));
+            continue;
+        }
+
+        let Some((monitor, enabled)) = current.as_mut() else {
+            continue;
+        };
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("Enabled:") {
+            *enabled = value.trim() == "yes";
+        } else if let Some(value) = line.strip_prefix("Scale:") {
+            monitor.scale = value
+                .trim()
+                .parse()
+                .ok()
+                .filter(|s: &f64| *s > 0.0)
+                .unwrap_or(1.0);
+        } else if let Some(value) = line.strip_prefix("Position:") {
+            if let Some((x, y)) = value.trim().split_once(',') {
+                monitor.x = x.trim().parse().unwrap_or(0);
+                monitor.y = y.trim().parse().unwrap_or(0);
+            }
+        } else if let Some(value) = line.strip_prefix("Transform:") {
+            // Portrait outputs are rotated by the compositor, the mode is still landscape
+            if matches!(value.trim(), "90" | "270" | "flipped-90" | "flipped-270") {
+                std::mem::swap(&mut monitor.width, &mut monitor.height);
+            }
+        } else if line.contains("current") {
+            let size = line.split_whitespace().next().unwrap_or("");
+            if let Some((width, height, _, _)) = parse_geometry(size) {
+                monitor.width = width;
+                monitor.height = height;
+            }
+        }
+    }
+    monitors.extend(finish(current));
+
+    monitors.retain(|monitor| monitor.width > 0 && monitor.height > 0);
+    monitors
+}
+
+/// Ask wlr-randr under Wayland or xrandr under X11 for the connected monitors
+pub fn detect_monitors() -> Result<Vec<Monitor>> {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let (program, args): (&str, &[&str]) = if wayland {
+        ("wlr-randr", &[])
+    } else {
+        ("xrandr", &["--query"])
+    };
+
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .context(format!("Failed to run {} to detect monitors", program))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let monitors = if wayland {
+        parse_wlr_randr(&stdout)
+    } else {
+        parse_xrandr(&stdout)
+    };
+    if monitors.is_empty() {
+        return Err(anyhow!("{} reported no active monitors", program));
+    }
+    Ok(monitors)
+}
+
+/// Top left corner and size of the box around every monitor, in the layout's units
+pub fn layout_bounds(monitors: &[Monitor]) -> (i32, i32, u32, u32) {
+    let left = monitors.iter().map(|m| m.x).min().unwrap_or(0);
+    let top = monitors.iter().map(|m| m.y).min().unwrap_or(0);
+    let right = monitors
+        .iter()
+        .map(|m| m.x + m.layout_size().0 as i32)
+        .max()
+        .unwrap_or(0);
+    let bottom = monitors
+        .iter()
+        .map(|m| m.y + m.layout_size().1 as i32)
+        .max()
+        .unwrap_or(0);
+
+    (left, top, (right - left) as u32, (bottom - top) as u32)
+}
+
+/// One image spanning the whole layout with each monitor's image at its position, gaps
+/// between monitors stay black. Images should already be sized to their monitor's
+/// `layout_size`
+pub fn stitch(parts: &[(Monitor, RgbImage)]) -> RgbImage {
+    let monitors: Vec<Monitor> = parts.iter().map(|(monitor, _)| monitor.clone()).collect();
+    let (left, top, width, height) = layout_bounds(&monitors);
+
+    let mut canvas = RgbImage::new(width, height);
+    for (monitor, image) in parts {
+        image::imageops::replace(
+            &mut canvas,
+            image,
+            (monitor.x - left) as i64,
+            (monitor.y - top) as i64,
+        );
+    }
+    canvas
+}
+
+/// Which camera, and optionally which effects, to show on a monitor
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputAssignment {
+    pub output: String,
+    /// Camera selector, matched like `--camera`
+    pub camera: String,
+    /// Pipeline spec used instead of the effect flags
+    pub pipeline: Option<String>,
+}
+
+impl OutputAssignment {
+    /// Parse `<output>=<camera>[|<pipeline>]`, e.g. `HDMI-1=harbor|grayscale,sky`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (output, rest) = spec.split_once('=').ok_or_else(|| {
+            anyhow!(
+                "Outputs look like HDMI-1=harbor or DP-2=tag:lake|grayscale,sky: {}",
+                spec
+            )
+        })?;
+        let (camera, pipeline) = match rest.split_once('|') {
+            Some((camera, pipeline)) => (camera, Some(pipeline.trim().to_string())),
+            None => (rest, None),
+        };
+
+        let (output, camera) = (output.trim(), camera.trim());
+        if output.is_empty() || camera.is_empty() {
+            return Err(anyhow!("Output needs a monitor and a camera: {}", spec));
+        }
+        Ok(OutputAssignment {
+            output: output.to_string(),
+            camera: camera.to_string(),
+            pipeline: pipeline.filter(|p| !p.is_empty()),
+        })
+    }
+}
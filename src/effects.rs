@@ -0,0 +1,480 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, Timelike, Utc};
+use chrono_tz::Tz;
+use image::{Rgb, RgbImage};
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::image_processing;
+use crate::overlay;
+use crate::sky_detection::{self, SkyPalette};
+use crate::sun;
+
+pub const DEFAULT_BEAM_COLOR: &str = "#7dffb0";
+
+/// From the untouched frame to the plain tint color
+pub const TINT_INTENSITY: RangeInclusive<f32> = 0.0..=1.0;
+/// Standard deviation for gaussian noise, out of 255 for salt and pepper
+pub const NOISE_INTENSITY: RangeInclusive<f64> = 0.0..=255.0;
+pub const NEON_SATURATION: RangeInclusive<f32> = 0.0..=1.0;
+/// Glow radius in pixels, a wider blur takes minutes on a large frame
+pub const NEON_GLOW: RangeInclusive<f32> = 0.0..=100.0;
+pub const BEAM_COUNT: RangeInclusive<u32> = 0..=64;
+
+/// A single image transformation that can be chained in a [`Pipeline`]
+pub trait Effect {
+    /// Name used to refer to the effect in a pipeline spec
+    fn name(&self) -> &'static str;
+
+    fn apply(&self, img: &RgbImage) -> RgbImage;
+
+    /// The pipeline stage that recreates this effect, e.g. `tint:#ff5500@0.3`
+    fn spec(&self) -> String {
+        self.name().to_string()
+    }
+
+    /// Learn where and when the frame was captured, for effects that depend on it
+    fn set_scene(&mut self, _scene: &Scene) {}
+}
+
+/// Where and when a frame was captured
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Scene {
+    pub time: DateTime<Utc>,
+    /// Latitude and longitude of the camera, when known
+    pub location: Option<(f64, f64)>,
+    /// The camera's timezone, when known
+    pub timezone: Option<Tz>,
+    /// How far down the frame the camera's horizon sits, when known. There is no sky below it
+    pub horizon: Option<f32>,
+}
+
+impl Scene {
+    /// Sky for the sun's position at the camera, or by the hour on the camera's clock when its
+    /// location is unknown. The host's clock stands in when the timezone is unknown too
+    pub fn sky_palette(&self) -> SkyPalette {
+        match self.location {
+            Some((latitude, longitude)) => {
+                let sun = sun::sun_position(self.time, latitude, longitude);
+                sky_detection::sky_palette_for_sun(sun.elevation)
+            }
+            None => {
+                let hour = match self.timezone {
+                    Some(timezone) => self.time.with_timezone(&timezone).hour(),
+                    None => self.time.with_timezone(&Local).hour(),
+                };
+                SkyPalette::uniform(sky_detection::sky_color_for_hour(hour))
+            }
+        }
+    }
+}
+
+pub struct Grayscale;
+
+impl Effect for Grayscale {
+    fn name(&self) -> &'static str {
+        "grayscale"
+    }
+
+    fn apply(&self, img: &RgbImage) -> RgbImage {
+        let gray_image = image::imageops::grayscale(img);
+        image_processing::convert_grayscale_to_rgb(&gray_image)
+    }
+}
+
+/// Color the sky for the time of day, by the sun's position when the scene has a location
+#[derive(Default)]
+pub struct SkyColor {
+    /// The current time on the host's clock when unset
+    pub scene: Option<Scene>,
+}
+
+impl Effect for SkyColor {
+    fn name(&self) -> &'static str {
+        "sky"
+    }
+
+    fn apply(&self, img: &RgbImage) -> RgbImage {
+        let palette = match &self.scene {
+            Some(scene) => scene.sky_palette(),
+            None => SkyPalette::uniform(sky_detection::get_sky_color_for_time()),
+        };
+        let gray_for_sky = image::imageops::grayscale(img);
+        let mut sky_mask = sky_detection::detect_sky_region_growing(&gray_for_sky);
+        if let Some(horizon) = self.scene.and_then(|scene| scene.horizon) {
+            sky_detection::limit_sky_to_horizon(&mut sky_mask, horizon);
+        }
+        sky_detection::apply_sky_palette(img, &sky_mask, &palette)
+    }
+
+    fn set_scene(&mut self, scene: &Scene) {
+        self.scene = Some(*scene);
+    }
+}
+
+pub struct Tint {
+    pub color: String,
+    pub intensity: f32,
+}
+
+impl Effect for Tint {
+    fn name(&self) -> &'static str {
+        "tint"
+    }
+
+    fn apply(&self, img: &RgbImage) -> RgbImage {
+        image_processing::apply_tint_to_rgb(img, &self.color, self.intensity)
+    }
+
+    fn spec(&self) -> String {
+        format!("tint:{}@{}", self.color, self.intensity)
+    }
+}
+
+/// Keep vivid light sources in color while the rest of the frame goes grayscale
+pub struct Neon {
+    /// Hue ranges in degrees, a range may wrap around 360. Empty keeps every hue
+    pub hue_ranges: Vec<(f32, f32)>,
+    pub saturation_threshold: f32,
+    pub glow_radius: f32,
+}
+
+impl Effect for Neon {
+    fn name(&self) -> &'static str {
+        "neon"
+    }
+
+    fn apply(&self, img: &RgbImage) -> RgbImage {
+        image_processing::apply_neon_over_grayscale(
+            img,
+            &self.hue_ranges,
+            self.saturation_threshold,
+            self.glow_radius,
+        )
+    }
+
+    fn spec(&self) -> String {
+        let hues: Vec<String> = self
+            .hue_ranges
+            .iter()
+            .map(|(start, end)| format!("{}-{}", start, end))
+            .collect();
+        format!(
+            "neon:{}@{}@{}",
+            if hues.is_empty() {
+                "*".to_string()
+            } else {
+                hues.join("+")
+            },
+            self.saturation_threshold,
+            self.glow_radius
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NoiseKind {
+    Gaussian,
+    SaltPepper,
+    Poisson,
+}
+
+pub struct Noise {
+    pub kind: NoiseKind,
+    /// 0-255 for salt/pepper, standard deviation for gaussian, unused for poisson
+    pub intensity: f64,
+}
+
+impl Effect for Noise {
+    fn name(&self) -> &'static str {
+        "noise"
+    }
+
+    fn apply(&self, img: &RgbImage) -> RgbImage {
+        match self.kind {
+            NoiseKind::Gaussian => {
+                image_processing::add_gaussian_noise_to_rgb(img, 0.0, self.intensity)
+            }
+            NoiseKind::SaltPepper => {
+                image_processing::add_salt_and_pepper_noise_to_rgb(img, self.intensity / 255.0)
+            }
+            NoiseKind::Poisson => image_processing::add_poisson_noise_to_rgb(img),
+        }
+    }
+
+    fn spec(&self) -> String {
+        let kind = match self.kind {
+            NoiseKind::Gaussian => "gaussian",
+            NoiseKind::SaltPepper => "salt-pepper",
+            NoiseKind::Poisson => "poisson",
+        };
+        format!("noise:{}@{}", kind, self.intensity)
+    }
+}
+
+/// Saucer with light beams coming down from the detected sky onto the skyline
+pub struct AlienBeams {
+    pub color: String,
+    pub beam_count: u32,
+    /// Where the saucer and beams go. Stages without one get a random seed when parsed, and
+    /// the spec always has it so the history can draw the same scene again
+    pub seed: u64,
+}
+
+impl Effect for AlienBeams {
+    fn name(&self) -> &'static str {
+        "beams"
+    }
+
+    fn apply(&self, img: &RgbImage) -> RgbImage {
+        let color = Rgb(image_processing::hex_to_rgb(&self.color).unwrap_or_default());
+        overlay::draw_alien_beams(img, color, self.beam_count, self.seed)
+    }
+
+    fn spec(&self) -> String {
+        format!("beams:{}@{}@{}", self.color, self.beam_count, self.seed)
+    }
+}
+
+/// What `citycam effects` tells about an effect
+pub struct EffectInfo {
+    pub name: &'static str,
+    /// Other names the stage can be written as
+    pub aliases: &'static [&'static str],
+    /// Pipeline stage syntax, optional parts in brackets
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub example: &'static str,
+}
+
+/// Every effect [`parse_effect`] understands. It only accepts the names listed here
+pub const AVAILABLE_EFFECTS: &[EffectInfo] = &[
+    EffectInfo {
+        name: "grayscale",
+        aliases: &["gray"],
+        usage: "grayscale",
+        description: "Convert the frame to grayscale (alias: gray)",
+        example: "grayscale",
+    },
+    EffectInfo {
+        name: "sky",
+        aliases: &[],
+        usage: "sky",
+        description: "Color the detected sky for the sun's position at the camera, or the \
+                      hour of day when the camera has no location",
+        example: "sky",
+    },
+    EffectInfo {
+        name: "tint",
+        aliases: &[],
+        usage: "tint:<#rrggbb>[@intensity]",
+        description: "Blend a color over the frame, intensity 0.0 to 1.0 (default 0.5)",
+        example: "tint:#ff5500@0.3",
+    },
+    EffectInfo {
+        name: "noise",
+        aliases: &[],
+        usage: "noise[:gaussian|salt-pepper|poisson][@intensity]",
+        description: "Add noise, intensity is the standard deviation for gaussian and 0-255 \
+                      for salt-pepper (default 25)",
+        example: "noise:gaussian@20",
+    },
+    EffectInfo {
+        name: "neon",
+        aliases: &[],
+        usage: "neon[:<hue ranges>][@saturation][@glow]",
+        description: "Keep bright, saturated light sources in color over grayscale. Hue \
+                      ranges in degrees joined by '+', saturation 0.0 to 1.0 (default 0.45), \
+                      glow radius in pixels (default 6)",
+        example: "neon:280-340+0-40@0.5@8",
+    },
+    EffectInfo {
+        name: "beams",
+        aliases: &[],
+        usage: "beams[:<#rrggbb>][@count][@seed]",
+        description: "Draw a flying saucer beaming light onto the skyline (default color \
+                      #7dffb0, 3 beams, a random seed that is kept in the history)",
+        example: "beams:#7dffb0@3@42",
+    },
+];
+
+/// An ordered list of effects applied one after another
+#[derive(Default)]
+pub struct Pipeline {
+    effects: Vec<Box<dyn Effect>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a comma separated spec such as `grayscale,sky,tint:#ff5500@0.3,noise:gaussian@20`
+    /// or `neon:280-340+0-40@0.5@8,beams:#7dffb0@3@42`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut pipeline = Self::new();
+        for stage in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            pipeline.effects.push(parse_effect(stage)?);
+        }
+        Ok(pipeline)
+    }
+
+    pub fn push<E: Effect + 'static>(&mut self, effect: E) {
+        self.effects.push(Box::new(effect));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Tell every effect where and when the frame was captured
+    pub fn set_scene(&mut self, scene: &Scene) {
+        for effect in &mut self.effects {
+            effect.set_scene(scene);
+        }
+    }
+
+    /// Spec that parses back into the same pipeline
+    pub fn spec(&self) -> String {
+        self.effects
+            .iter()
+            .map(|e| e.spec())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.effects.iter().map(|e| e.name()).collect()
+    }
+
+    pub fn apply(&self, img: &RgbImage) -> RgbImage {
+        self.effects
+            .iter()
+            .fold(img.clone(), |current, effect| effect.apply(&current))
+    }
+}
+
+/// Parse a single `name[:value[@amount...]]` pipeline stage
+pub fn parse_effect(stage: &str) -> Result<Box<dyn Effect>> {
+    let (name, params) = match stage.split_once(':') {
+        Some((name, params)) => (name.trim(), Some(params.trim())),
+        None => (stage.trim(), None),
+    };
+    let mut parts = params.into_iter().flat_map(|p| p.split('@'));
+    let value = parts.next().filter(|v| !v.is_empty() && *v != "*");
+    let amounts: Vec<&str> = parts.collect();
+    let amount = amounts.first().copied();
+
+    let lowercase = name.to_lowercase();
+    let info = AVAILABLE_EFFECTS
+        .iter()
+        .find(|info| info.name == lowercase || info.aliases.contains(&lowercase.as_str()))
+        .ok_or_else(|| {
+            let names: Vec<&str> = AVAILABLE_EFFECTS.iter().map(|info| info.name).collect();
+            anyhow!("Unknown effect: {} (available: {})", name, names.join(", "))
+        })?;
+
+    match info.name {
+        "grayscale" => Ok(Box::new(Grayscale)),
+        "sky" => Ok(Box::new(SkyColor::default())),
+        "tint" => {
+            let color = value.ok_or_else(|| anyhow!("tint requires a color, e.g. tint:#ff5500"))?;
+            if image_processing::hex_to_rgb(color).is_none() {
+                return Err(anyhow!("Invalid tint color: {}", color));
+            }
+            Ok(Box::new(Tint {
+                color: color.to_string(),
+                intensity: amount_or(amount, 0.5, TINT_INTENSITY)?,
+            }))
+        }
+        "noise" => {
+            let kind = match value.unwrap_or("gaussian").to_lowercase().as_str() {
+                "gaussian" => NoiseKind::Gaussian,
+                "salt-pepper" | "saltpepper" => NoiseKind::SaltPepper,
+                "poisson" => NoiseKind::Poisson,
+                other => return Err(anyhow!("Unknown noise type: {}", other)),
+            };
+            Ok(Box::new(Noise {
+                kind,
+                intensity: amount_or(amount, 25.0, NOISE_INTENSITY)?,
+            }))
+        }
+        "neon" => Ok(Box::new(Neon {
+            hue_ranges: value.map(parse_hue_ranges).transpose()?.unwrap_or_default(),
+            saturation_threshold: amount_or(amount, 0.45, NEON_SATURATION)?,
+            glow_radius: amount_or(amounts.get(1).copied(), 6.0, NEON_GLOW)?,
+        })),
+        "beams" => {
+            let color = value.unwrap_or(DEFAULT_BEAM_COLOR);
+            if image_processing::hex_to_rgb(color).is_none() {
+                return Err(anyhow!("Invalid beam color: {}", color));
+            }
+            Ok(Box::new(AlienBeams {
+                color: color.to_string(),
+                beam_count: amount_or(amount, 3, BEAM_COUNT)?,
+                seed: amounts
+                    .get(1)
+                    .map(|seed| parse_amount(seed, 0..=u64::MAX))
+                    .transpose()?
+                    .unwrap_or_else(rand::random),
+            }))
+        }
+        other => unreachable!("{} is listed in AVAILABLE_EFFECTS but not parsed", other),
+    }
+}
+
+/// Parse an effect amount, which has to be in `range`. NaN never is
+pub fn parse_amount<T>(amount: &str, range: RangeInclusive<T>) -> Result<T>
+where
+    T: FromStr + PartialOrd + Display,
+{
+    let value: T = amount
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid effect amount: {}", amount))?;
+    if !range.contains(&value) {
+        return Err(anyhow!(
+            "Effect amount {} out of range, expected {} to {}",
+            amount.trim(),
+            range.start(),
+            range.end()
+        ));
+    }
+    Ok(value)
+}
+
+fn amount_or<T>(amount: Option<&str>, default: T, range: RangeInclusive<T>) -> Result<T>
+where
+    T: FromStr + PartialOrd + Display,
+{
+    match amount {
+        Some(amount) => parse_amount(amount, range),
+        None => Ok(default),
+    }
+}
+
+/// Parse hue ranges like `280-340+0-40` or `280-340,0-40` into degree pairs
+pub fn parse_hue_ranges(spec: &str) -> Result<Vec<(f32, f32)>> {
+    spec.split(['+', ','])
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(|range| {
+            let (start, end) = range
+                .split_once('-')
+                .ok_or_else(|| anyhow!("Invalid hue range: {}", range))?;
+            let start: f32 = start
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid hue range: {}", range))?;
+            let end: f32 = end
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid hue range: {}", range))?;
+            if !(0.0..=360.0).contains(&start) || !(0.0..=360.0).contains(&end) {
+                return Err(anyhow!("Hue range out of 0-360: {}", range));
+            }
+            Ok((start, end))
+        })
+        .collect()
+}
@@ -0,0 +1,216 @@
+use anyhow::{anyhow, Result};
+use image::imageops::{self, FilterType};
+use image::RgbImage;
+
+use crate::camera::Crop;
+
+/// Strength of the blur behind a contained frame, relative to the display's longer side
+const BACKGROUND_BLUR: f32 = 0.02;
+/// Smart crop scores the frame at this width, the exact pixels don't matter
+const ENERGY_SAMPLE_WIDTH: u32 = 256;
+
+/// How to make a frame fill a display of a different size or shape
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FitMode {
+    /// Scale to cover the display and cut off what sticks out, keeping the middle
+    Cover,
+    /// Scale to fit inside the display over a blurred, zoomed copy of the frame
+    Contain,
+    /// Scale to the display's size, ignoring the aspect ratio
+    Stretch,
+    /// Like cover, but keep the most detailed part of the frame
+    Smart,
+}
+
+impl FitMode {
+    pub fn parse(mode: &str) -> Result<Self> {
+        match mode.trim().to_lowercase().as_str() {
+            "cover" | "fill" => Ok(FitMode::Cover),
+            "contain" | "fit" => Ok(FitMode::Contain),
+            "stretch" => Ok(FitMode::Stretch),
+            "smart" | "smart-crop" => Ok(FitMode::Smart),
+            other => Err(anyhow!(
+                "Unknown fit mode: {} (expected cover, contain, stretch or smart)",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for FitMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            FitMode::Cover => "cover",
+            FitMode::Contain => "contain",
+            FitMode::Stretch => "stretch",
+            FitMode::Smart => "smart",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Resampling filter by name: nearest, linear, cubic, gaussian or lanczos
+pub fn parse_filter(name: &str) -> Result<FilterType> {
+    match name.trim().to_lowercase().as_str() {
+        "nearest" => Ok(FilterType::Nearest),
+        "linear" | "bilinear" | "triangle" => Ok(FilterType::Triangle),
+        "cubic" | "bicubic" | "catmull-rom" => Ok(FilterType::CatmullRom),
+        "gaussian" => Ok(FilterType::Gaussian),
+        "lanczos" | "lanczos3" => Ok(FilterType::Lanczos3),
+        other => Err(anyhow!("Unknown resampling filter: {}", other)),
+    }
+}
+
+/// Parse a `<width>x<height>` resolution
+pub fn parse_resolution(resolution: &str) -> Result<(u32, u32)> {
+    resolution
+        .trim()
+        .split_once(['x', 'X'])
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .ok_or_else(|| anyhow!("Resolutions look like 1920x1080: {}", resolution))
+}
+
+/// Resize a frame to `width` x `height`. The camera's preferred crop, when it has one, is kept
+/// in view by the modes that crop
+pub fn fit(
+    img: &RgbImage,
+    width: u32,
+    height: u32,
+    mode: FitMode,
+    filter: FilterType,
+    crop: Option<Crop>,
+) -> RgbImage {
+    if img.dimensions() == (width, height) {
+        return img.clone();
+    }
+
+    match mode {
+        FitMode::Stretch => imageops::resize(img, width, height, filter),
+        FitMode::Cover => {
+            let focus = crop.map(crop_center).unwrap_or((0.5, 0.5));
+            cover(img, width, height, filter, focus)
+        }
+        FitMode::Smart => {
+            let focus = crop
+                .map(crop_center)
+                .unwrap_or_else(|| interesting_center(img, width, height));
+            cover(img, width, height, filter, focus)
+        }
+        FitMode::Contain => contain(img, width, height, filter),
+    }
+}
+
+fn crop_center(crop: Crop) -> (f32, f32) {
+    (crop.x + crop.width / 2.0, crop.y + crop.height / 2.0)
+}
+
+// Largest window of the display's shape, as close to centered on `focus` (fractions of the
+// frame) as fits, scaled to the display
+fn cover(
+    img: &RgbImage,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+    focus: (f32, f32),
+) -> RgbImage {
+    let (window_width, window_height) = window_size(img.dimensions(), (width, height));
+    let place = |size: u32, window: u32, center: f32| {
+        let start = (center * size as f32 - window as f32 / 2.0).round();
+        start.clamp(0.0, (size - window) as f32) as u32
+    };
+    let x = place(img.width(), window_width, focus.0);
+    let y = place(img.height(), window_height, focus.1);
+
+    let window = imageops::crop_imm(img, x, y, window_width, window_height).to_image();
+    imageops::resize(&window, width, height, filter)
+}
+
+// Biggest part of a `frame` sized image with the aspect ratio of `target`
+fn window_size(frame: (u32, u32), target: (u32, u32)) -> (u32, u32) {
+    let (frame_width, frame_height) = (frame.0 as u64, frame.1 as u64);
+    let (target_width, target_height) = (target.0 as u64, target.1 as u64);
+
+    if frame_width * target_height > target_width * frame_height {
+        // Frame is wider than the display, cut the sides
+        let width = (frame_height * target_width / target_height).max(1);
+        (width as u32, frame.1)
+    } else {
+        let height = (frame_width * target_height / target_width).max(1);
+        (frame.0, height as u32)
+    }
+}
+
+fn contain(img: &RgbImage, width: u32, height: u32, filter: FilterType) -> RgbImage {
+    // Blurring a small copy and scaling it up is much cheaper than blurring at full size
+    let small = imageops::resize(img, (width / 8).max(1), (height / 8).max(1), filter);
+    let sigma = BACKGROUND_BLUR * width.max(height) as f32 / 8.0;
+    let mut canvas = imageops::resize(
+        &imageops::blur(&small, sigma.max(1.0)),
+        width,
+        height,
+        FilterType::Triangle,
+    );
+
+    let scale = (width as f32 / img.width() as f32).min(height as f32 / img.height() as f32);
+    let scaled_width = ((img.width() as f32 * scale).round() as u32).clamp(1, width);
+    let scaled_height = ((img.height() as f32 * scale).round() as u32).clamp(1, height);
+    let scaled = imageops::resize(img, scaled_width, scaled_height, filter);
+
+    imageops::replace(
+        &mut canvas,
+        &scaled,
+        ((width - scaled_width) / 2) as i64,
+        ((height - scaled_height) / 2) as i64,
+    );
+    canvas
+}
+
+/// Center, as fractions of the frame, of the display shaped window with the most detail in it,
+/// judged by how much the brightness changes from pixel to pixel
+pub fn interesting_center(img: &RgbImage, width: u32, height: u32) -> (f32, f32) {
+    let scale = (ENERGY_SAMPLE_WIDTH as f32 / img.width() as f32).min(1.0);
+    let sample_width = ((img.width() as f32 * scale) as u32).max(2);
+    let sample_height = ((img.height() as f32 * scale) as u32).max(2);
+    let gray = imageops::grayscale(&imageops::resize(
+        img,
+        sample_width,
+        sample_height,
+        FilterType::Triangle,
+    ));
+
+    let mut columns = vec![0.0f64; sample_width as usize];
+    let mut rows = vec![0.0f64; sample_height as usize];
+    for y in 1..sample_height {
+        for x in 1..sample_width {
+            let value = gray.get_pixel(x, y).0[0] as f64;
+            let energy = (value - gray.get_pixel(x - 1, y).0[0] as f64).abs()
+                + (value - gray.get_pixel(x, y - 1).0[0] as f64).abs();
+            columns[x as usize] += energy;
+            rows[y as usize] += energy;
+        }
+    }
+
+    let (window_width, window_height) = window_size((sample_width, sample_height), (width, height));
+    (
+        best_window_center(&columns, window_width as usize),
+        best_window_center(&rows, window_height as usize),
+    )
+}
+
+// Center of the `window` long run of `energy` with the highest sum, as a fraction of the length
+fn best_window_center(energy: &[f64], window: usize) -> f32 {
+    let window = window.clamp(1, energy.len());
+    let mut sum: f64 = energy[..window].iter().sum();
+    let (mut best_sum, mut best_start) = (sum, 0);
+
+    for start in 1..=energy.len() - window {
+        sum += energy[start + window - 1] - energy[start - 1];
+        if sum > best_sum {
+            best_sum = sum;
+            best_start = start;
+        }
+    }
+
+    (best_start as f32 + window as f32 / 2.0) / energy.len() as f32
+}
@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Result};
+use image::{Rgb, RgbImage};
+
+/// How several frames of the same scene are merged into one ghosted image
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GhostMode {
+    /// Average of all frames, moving objects fade to translucent ghosts
+    Mean,
+    /// Brightest value per pixel, good for light trails at night
+    Max,
+    /// Weighted average where each older frame counts `decay` times less than the next
+    Trail { decay: f32 },
+    /// Median background with moving objects laid over it at `opacity`
+    Median { opacity: f32 },
+}
+
+/// Pixels differing from the median background by more than this are treated as movers
+const MOVER_THRESHOLD: u8 = 32;
+
+impl GhostMode {
+    /// Parse `mean`, `max`, `trail[@decay]` or `median[@opacity]`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (name, amount) = match spec.split_once('@') {
+            Some((name, amount)) => (name.trim(), Some(amount.trim())),
+            None => (spec.trim(), None),
+        };
+        let amount = match amount {
+            Some(amount) => Some(
+                amount
+                    .parse::<f32>()
+                    .map_err(|_| anyhow!("Invalid ghost amount: {}", amount))?,
+            ),
+            None => None,
+        };
+
+        match name.to_lowercase().as_str() {
+            "mean" => Ok(GhostMode::Mean),
+            "max" => Ok(GhostMode::Max),
+            "trail" => Ok(GhostMode::Trail {
+                decay: amount.unwrap_or(0.7).clamp(0.0, 1.0),
+            }),
+            "median" => Ok(GhostMode::Median {
+                opacity: amount.unwrap_or(0.5).clamp(0.0, 1.0),
+            }),
+            _ => Err(anyhow!("Unknown ghost mode: {}", name)),
+        }
+    }
+}
+
+/// Blend frames ordered oldest to newest into a single image
+pub fn composite_frames(frames: &[RgbImage], mode: GhostMode) -> Result<RgbImage> {
+    let first = frames
+        .first()
+        .ok_or_else(|| anyhow!("No frames to composite"))?;
+    let (width, height) = first.dimensions();
+    if frames.iter().any(|f| f.dimensions() != (width, height)) {
+        return Err(anyhow!("Frames to composite differ in size"));
+    }
+
+    let result = match mode {
+        GhostMode::Mean => weighted_average(frames, &vec![1.0; frames.len()]),
+        GhostMode::Max => {
+            let mut result = first.clone();
+            for frame in &frames[1..] {
+                for (out, pixel) in result.pixels_mut().zip(frame.pixels()) {
+                    for c in 0..3 {
+                        out[c] = out[c].max(pixel[c]);
+                    }
+                }
+            }
+            result
+        }
+        GhostMode::Trail { decay } => {
+            let weights: Vec<f32> = (0..frames.len())
+                .map(|i| decay.powi((frames.len() - 1 - i) as i32))
+                .collect();
+            weighted_average(frames, &weights)
+        }
+        GhostMode::Median { opacity } => median_with_movers(frames, opacity),
+    };
+
+    Ok(result)
+}
+
+fn weighted_average(frames: &[RgbImage], weights: &[f32]) -> RgbImage {
+    let (width, height) = frames[0].dimensions();
+    let total: f32 = weights.iter().sum::<f32>().max(f32::EPSILON);
+    let mut sums = vec![0.0f32; (width * height * 3) as usize];
+
+    for (frame, weight) in frames.iter().zip(weights) {
+        for (sum, value) in sums.iter_mut().zip(frame.as_raw()) {
+            *sum += *value as f32 * weight;
+        }
+    }
+
+    let data = sums.iter().map(|sum| (sum / total) as u8).collect();
+    RgbImage::from_raw(width, height, data).expect("buffer matches frame dimensions")
+}
+
+fn median_with_movers(frames: &[RgbImage], opacity: f32) -> RgbImage {
+    let (width, height) = frames[0].dimensions();
+    let mut result = RgbImage::new(width, height);
+    let mut values = Vec::with_capacity(frames.len());
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut background = [0u8; 3];
+            for (c, channel) in background.iter_mut().enumerate() {
+                values.clear();
+                values.extend(frames.iter().map(|f| f.get_pixel(x, y)[c]));
+                values.sort_unstable();
+                *channel = values[values.len() / 2];
+            }
+
+            // Lay every frame's moving pixels over the background, newest last
+            let mut pixel = background.map(|v| v as f32);
+            for frame in frames {
+                let value = frame.get_pixel(x, y).0;
+                let diff = (0..3)
+                    .map(|c| value[c].abs_diff(background[c]))
+                    .max()
+                    .unwrap_or(0);
+                if diff > MOVER_THRESHOLD {
+                    for c in 0..3 {
+                        pixel[c] = pixel[c] * (1.0 - opacity) + value[c] as f32 * opacity;
+                    }
+                }
+            }
+
+            result.put_pixel(x, y, Rgb(pixel.map(|v| v as u8)));
+        }
+    }
+
+    result
+}
@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::camera::Camera;
+
+/// Wait this long before retrying a camera after its first failure, doubling with every
+/// failure after that
+const BASE_BACKOFF_SECONDS: i64 = 60;
+/// Dead cameras are still retried at least this often
+const MAX_BACKOFF_SECONDS: i64 = 6 * 60 * 60;
+/// Weight of the newest fetch in the latency average
+const LATENCY_SMOOTHING: f64 = 0.3;
+
+/// How a camera has been doing
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CameraHealth {
+    pub consecutive_failures: u32,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// Moving average of how long a successful fetch takes
+    pub average_latency_ms: Option<f64>,
+}
+
+impl CameraHealth {
+    /// When a failing camera may be tried again, `None` if it can be tried now
+    pub fn retry_at(&self) -> Option<DateTime<Utc>> {
+        if self.consecutive_failures == 0 {
+            return None;
+        }
+
+        let doublings = (self.consecutive_failures - 1).min(16);
+        let backoff = (BASE_BACKOFF_SECONDS << doublings).min(MAX_BACKOFF_SECONDS);
+        self.last_failure
+            .map(|failed| failed + Duration::seconds(backoff))
+    }
+
+    pub fn is_available(&self, now: DateTime<Utc>) -> bool {
+        self.retry_at().is_none_or(|retry| retry <= now)
+    }
+}
+
+/// Health of every camera, by name
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HealthTracker {
+    pub cameras: BTreeMap<String, CameraHealth>,
+}
+
+impl HealthTracker {
+    /// Saved health, or an empty tracker when there is none or it can't be read
+    pub fn load(path: &Path) -> HealthTracker {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?).context(format!(
+            "Failed to save camera health to {}",
+            path.display()
+        ))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CameraHealth> {
+        self.cameras.get(name)
+    }
+
+    /// Cameras never seen failing count as available
+    pub fn is_available(&self, name: &str, now: DateTime<Utc>) -> bool {
+        self.get(name).is_none_or(|health| health.is_available(now))
+    }
+
+    pub fn record_success(&mut self, name: &str, latency: std::time::Duration, now: DateTime<Utc>) {
+        let health = self.cameras.entry(name.to_string()).or_default();
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+
+        health.consecutive_failures = 0;
+        health.last_success = Some(now);
+        health.average_latency_ms = Some(match health.average_latency_ms {
+            Some(average) => average + LATENCY_SMOOTHING * (latency_ms - average),
+            None => latency_ms,
+        });
+    }
+
+    pub fn record_failure(&mut self, name: &str, error: &str, now: DateTime<Utc>) {
+        let health = self.cameras.entry(name.to_string()).or_default();
+
+        health.consecutive_failures += 1;
+        health.last_failure = Some(now);
+        health.last_error = Some(error.to_string());
+    }
+}
+
+/// Table of the cameras' health, in camera list order followed by any cameras no longer in
+/// the list
+pub fn format_table(tracker: &HealthTracker, cameras: &[Camera], now: DateTime<Utc>) -> String {
+    let mut names: Vec<&str> = cameras.iter().map(|camera| camera.name.as_str()).collect();
+    for name in tracker.cameras.keys() {
+        if !names.contains(&name.as_str()) {
+            names.push(name);
+        }
+    }
+
+    let width = names
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+    let mut table = format!(
+        "{:<width$}  {:<22}  {:>8}  {:>10}  {:<19}  {}\n",
+        "CAMERA", "STATUS", "FAILURES", "LATENCY", "LAST SUCCESS", "LAST ERROR"
+    );
+
+    let format_time = |time: Option<DateTime<Utc>>| {
+        time.map(|t| {
+            t.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_else(|| "-".to_string())
+    };
+
+    for name in names {
+        let health = tracker.get(name).cloned().unwrap_or_default();
+        let status = match health.retry_at() {
+            _ if health.last_success.is_none() && health.last_failure.is_none() => {
+                "unknown".to_string()
+            }
+            None => "ok".to_string(),
+            Some(retry) if retry <= now => "failing, retry now".to_string(),
+            Some(retry) => format!(
+                "backoff until {}",
+                retry.with_timezone(&Local).format("%H:%M:%S")
+            ),
+        };
+        let latency = health
+            .average_latency_ms
+            .map(|ms| format!("{:.0} ms", ms))
+            .unwrap_or_else(|| "-".to_string());
+
+        table.push_str(&format!(
+            "{:<width$}  {:<22}  {:>8}  {:>10}  {:<19}  {}\n",
+            name,
+            status,
+            health.consecutive_failures,
+            latency,
+            format_time(health.last_success),
+            health.last_error.as_deref().unwrap_or("-"),
+        ));
+    }
+
+    table
+}
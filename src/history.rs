@@ -0,0 +1,230 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Index of every saved wallpaper, in the cache directory
+pub const INDEX_FILE_NAME: &str = "history.jsonl";
+
+/// Everything known about how a saved wallpaper was made. One JSON line per wallpaper in the
+/// index, and a `.json` file of the same name next to the image
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub camera: String,
+    /// Page, stream or image URL of the camera, or a path for local sources
+    pub url: String,
+    /// Segment, snapshot or file the frame was decoded from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment: Option<String>,
+    /// Stream variant followed, e.g. "1280x720, 2500 kbps"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    /// When the camera recorded the frame, if the source said
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captured_at: Option<DateTime<Utc>>,
+    pub saved_at: DateTime<Utc>,
+    /// Pipeline spec the frame went through, empty for none
+    pub pipeline: String,
+    /// Time given with `--at` to render the effects for instead of the capture time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<DateTime<Utc>>,
+    /// Fit mode, when the frame was fitted to a display
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fit: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    /// Monitor the wallpaper was made for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Raw frame saved by `citycam record` rather than a wallpaper
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub recorded: bool,
+    pub path: PathBuf,
+    /// Unprocessed frame the wallpaper was made from, kept for `citycam rerender`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original: Option<PathBuf>,
+}
+
+impl HistoryEntry {
+    /// Entry for an unprocessed frame of `camera` saved to `path` just now, for the caller to
+    /// fill in the rest
+    pub fn new(camera: &str, path: &Path, captured_at: Option<DateTime<Utc>>) -> Self {
+        HistoryEntry {
+            camera: camera.to_string(),
+            url: String::new(),
+            segment: None,
+            variant: None,
+            captured_at,
+            saved_at: Utc::now(),
+            pipeline: String::new(),
+            at: None,
+            fit: None,
+            width: 0,
+            height: 0,
+            output: None,
+            recorded: false,
+            path: path.to_path_buf(),
+            original: None,
+        }
+    }
+
+    /// The image's file name without extension, e.g. `20250310-114500` or
+    /// `20250310-114500-DP-1`
+    pub fn id(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Frame to render again: the kept original, or the image itself for recorded frames
+    pub fn source_frame(&self) -> Option<&Path> {
+        if self.recorded {
+            Some(&self.path)
+        } else {
+            self.original.as_deref()
+        }
+    }
+}
+
+/// Metadata file written next to an image
+pub fn sidecar_path(image: &Path) -> PathBuf {
+    image.with_extension("json")
+}
+
+/// Add an entry to the index and write its sidecar
+pub fn record(index: &Path, entry: &HistoryEntry) -> Result<()> {
+    fs::write(
+        sidecar_path(&entry.path),
+        serde_json::to_string_pretty(entry)?,
+    )
+    .context(format!(
+        "Failed to write metadata for {}",
+        entry.path.display()
+    ))?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(index)
+        .context(format!("Failed to open history index {}", index.display()))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// Entries in the index, oldest first. Lines that don't parse are skipped
+pub fn load(index: &Path) -> Result<Vec<HistoryEntry>> {
+    if !index.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(index)
+        .context(format!("Failed to read history index {}", index.display()))?;
+
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Drop the entries whose image is gone, e.g. after pruning the cache. Lines that aren't
+/// entries are left alone, and the index is replaced in one go so a crash can't truncate it
+pub fn forget_missing(index: &Path) -> Result<usize> {
+    if !index.exists() {
+        return Ok(0);
+    }
+    let content = fs::read_to_string(index)
+        .context(format!("Failed to read history index {}", index.display()))?;
+
+    let mut kept = String::new();
+    let mut missing = 0;
+    for line in content.lines() {
+        match serde_json::from_str::<HistoryEntry>(line) {
+            Ok(entry) if !entry.path.exists() => missing += 1,
+            _ => {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+    }
+    if missing == 0 {
+        return Ok(0);
+    }
+
+    let rewrite = || -> Result<()> {
+        let dir = index.parent().unwrap_or(Path::new("."));
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        file.write_all(kept.as_bytes())?;
+        file.persist(index)?;
+        Ok(())
+    };
+    rewrite().context(format!(
+        "Failed to rewrite history index {}",
+        index.display()
+    ))?;
+    Ok(missing)
+}
+
+/// Which entries to list, every entry when nothing is set
+#[derive(Clone, Debug, Default)]
+pub struct HistoryFilter {
+    /// Case insensitive text to look for in the camera name, pipeline or output
+    pub text: Option<String>,
+    /// Case insensitive part of the camera name
+    pub camera: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Only recorded frames, or only wallpapers
+    pub recorded: Option<bool>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        let contains =
+            |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+        let time = entry.captured_at.unwrap_or(entry.saved_at);
+
+        let text_matches = self.text.as_ref().is_none_or(|text| {
+            contains(&entry.camera, text)
+                || contains(&entry.pipeline, text)
+                || entry
+                    .output
+                    .as_ref()
+                    .is_some_and(|output| contains(output, text))
+        });
+        let camera_matches = self
+            .camera
+            .as_ref()
+            .is_none_or(|camera| contains(&entry.camera, camera));
+
+        text_matches
+            && camera_matches
+            && self
+                .recorded
+                .is_none_or(|recorded| entry.recorded == recorded)
+            && self.since.is_none_or(|since| time >= since)
+            && self.until.is_none_or(|until| time <= until)
+    }
+}
+
+/// Entry by id, `latest` for the newest, or the newest whose id starts with the given text
+pub fn find<'a>(entries: &'a [HistoryEntry], id: &str) -> Result<&'a HistoryEntry> {
+    if id.eq_ignore_ascii_case("latest") {
+        return entries
+            .last()
+            .ok_or_else(|| anyhow!("The history is empty"));
+    }
+
+    entries
+        .iter()
+        .rev()
+        .find(|entry| entry.id() == id)
+        .or_else(|| {
+            entries
+                .iter()
+                .rev()
+                .find(|entry| entry.id().starts_with(id))
+        })
+        .ok_or_else(|| anyhow!("No wallpaper in the history matches {}", id))
+}
@@ -111,8 +111,8 @@ pub fn apply_tint_to_rgb(
     let height = img.height();
     let mut tinted_img = RgbImage::new(width, height);
 
-    // Parse hex color
-    let tint_rgb = hex_to_rgb(tint_color);
+    // Parse hex color, the flags and pipeline specs are checked so black never shows up
+    let tint_rgb = hex_to_rgb(tint_color).unwrap_or_default();
 
     for y in 0..height {
         for x in 0..width {
@@ -130,14 +130,177 @@ pub fn apply_tint_to_rgb(
     tinted_img
 }
 
-fn hex_to_rgb(hex: &str) -> [u8; 3] {
+/// `#rrggbb` or `rrggbb` as RGB, `None` for anything else
+pub fn hex_to_rgb(hex: &str) -> Option<[u8; 3]> {
     let hex = hex.trim_start_matches('#');
-    let r = u8::from_str_radix(&hex[0..2], 16).unwrap_or(0);
-    let g = u8::from_str_radix(&hex[2..4], 16).unwrap_or(0);
-    let b = u8::from_str_radix(&hex[4..6], 16).unwrap_or(0);
-    [r, g, b]
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
 }
 
 fn blend(original: u8, tint: u8, intensity: f32) -> u8 {
     ((original as f32) * (1.0 - intensity) + (tint as f32) * intensity) as u8
 }
+
+pub fn apply_neon_over_grayscale(
+    img: &RgbImage,
+    hue_ranges: &[(f32, f32)], // Hue ranges in degrees to keep, empty keeps every hue
+    saturation_threshold: f32, // Minimum HSV saturation (0.0 to 1.0) of kept light sources
+    glow_radius: f32,          // Blur sigma of the bloom around kept colors, 0 disables it
+) -> RgbImage {
+    let width = img.width();
+    let height = img.height();
+    let gray_img = convert_grayscale_to_rgb(&image::imageops::grayscale(img));
+
+    // Only the vivid light sources end up in this layer, everything else stays black
+    let mut neon_layer = RgbImage::new(width, height);
+    let mut neon_mask = vec![0.0f32; (width * height) as usize];
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = img.get_pixel(x, y);
+            let (hue, saturation, value) = rgb_to_hsv(pixel.0);
+
+            let hue_match = hue_ranges.is_empty()
+                || hue_ranges
+                    .iter()
+                    .any(|&(start, end)| hue_in_range(hue, start, end));
+
+            if hue_match && saturation >= saturation_threshold && value >= 0.35 {
+                // Fade in just above the threshold so edges don't look cut out
+                let weight = ((saturation - saturation_threshold) / 0.15).min(1.0);
+                neon_mask[(y * width + x) as usize] = weight.max(0.2);
+                neon_layer.put_pixel(x, y, boost_saturation(pixel.0, 1.4));
+            }
+        }
+    }
+
+    let glow = if glow_radius > 0.0 {
+        Some(image::imageops::blur(&neon_layer, glow_radius))
+    } else {
+        None
+    };
+
+    let mut neon_img = RgbImage::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let weight = neon_mask[(y * width + x) as usize];
+            let base = gray_img.get_pixel(x, y);
+            let color = neon_layer.get_pixel(x, y);
+
+            let mut out = [0u8; 3];
+            for c in 0..3 {
+                let mixed = blend(base[c], color[c], weight);
+                out[c] = match &glow {
+                    // Screen blend so the bloom only ever brightens
+                    Some(glow) => screen(mixed, glow.get_pixel(x, y)[c]),
+                    None => mixed,
+                };
+            }
+
+            neon_img.put_pixel(x, y, Rgb(out));
+        }
+    }
+
+    neon_img
+}
+
+fn rgb_to_hsv(rgb: [u8; 3]) -> (f32, f32, f32) {
+    let r = rgb[0] as f32 / 255.0;
+    let g = rgb[1] as f32 / 255.0;
+    let b = rgb[2] as f32 / 255.0;
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+    (hue, saturation, max)
+}
+
+fn hue_in_range(hue: f32, start: f32, end: f32) -> bool {
+    if start <= end {
+        hue >= start && hue <= end
+    } else {
+        // Range wraps around red, e.g. 330-30
+        hue >= start || hue <= end
+    }
+}
+
+fn boost_saturation(rgb: [u8; 3], factor: f32) -> Rgb<u8> {
+    let luma = 0.299 * rgb[0] as f32 + 0.587 * rgb[1] as f32 + 0.114 * rgb[2] as f32;
+    Rgb(rgb.map(|c| (luma + (c as f32 - luma) * factor).clamp(0.0, 255.0) as u8))
+}
+
+fn screen(a: u8, b: u8) -> u8 {
+    255 - (((255 - a) as u16 * (255 - b) as u16) / 255) as u8
+}
+
+/// Variance of the Laplacian of the luma, higher means a sharper frame
+pub fn laplacian_variance(img: &RgbImage) -> f64 {
+    let gray = image::imageops::grayscale(img);
+    let (width, height) = gray.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let mut sum = 0.0;
+    let mut sum_sq = 0.0;
+    let mut count = 0.0;
+
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let center = gray.get_pixel(x, y).0[0] as f64;
+            let laplacian = gray.get_pixel(x - 1, y).0[0] as f64
+                + gray.get_pixel(x + 1, y).0[0] as f64
+                + gray.get_pixel(x, y - 1).0[0] as f64
+                + gray.get_pixel(x, y + 1).0[0] as f64
+                - 4.0 * center;
+
+            sum += laplacian;
+            sum_sq += laplacian * laplacian;
+            count += 1.0;
+        }
+    }
+
+    let mean = sum / count;
+    sum_sq / count - mean * mean
+}
+
+/// 1.0 for a well exposed frame, falling towards 0.0 for dark, washed out or clipped frames
+pub fn exposure_score(img: &RgbImage) -> f64 {
+    let gray = image::imageops::grayscale(img);
+    let total = gray.pixels().len().max(1) as f64;
+
+    let mean = gray.pixels().map(|p| p.0[0] as f64).sum::<f64>() / total;
+    let clipped = gray
+        .pixels()
+        .filter(|p| p.0[0] <= 5 || p.0[0] >= 250)
+        .count() as f64
+        / total;
+
+    let balance = 1.0 - ((mean - 128.0) / 128.0).powi(2);
+    (balance * (1.0 - clipped)).max(0.0)
+}
+
+/// Keep the frame with the best combined sharpness and exposure
+pub fn pick_best_frame(frames: Vec<RgbImage>) -> Option<RgbImage> {
+    frames
+        .into_iter()
+        .map(|frame| (laplacian_variance(&frame) * exposure_score(&frame), frame))
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, frame)| frame)
+}
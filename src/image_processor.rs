@@ -1,67 +1,401 @@
-use anyhow::Result;
-use chrono::Local;
+use anyhow::{anyhow, Result};
+use chrono::{Local, Utc};
+use citycam::archive::{self, FrameFormat};
+use citycam::backend;
+use citycam::camera::Camera;
+use citycam::effects::{self, Pipeline, Scene};
+use citycam::fit::{self, FitMode};
+use citycam::ghost::{self, GhostMode};
+use citycam::history::{self, HistoryEntry};
+use citycam::retention::{self, RetentionPolicy};
+use citycam::schedule::parse_duration;
+use citycam::source;
+use citycam::stream::{
+    FrameSelection, SegmentInfo, SegmentSelection, StreamOptions, VariantSelection,
+};
+use citycam::sun::{self, SkyPhase};
+use image::imageops::FilterType;
 use image::RgbImage;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use crate::cli;
-use crate::image_processing;
-use crate::sky_detection;
-use crate::utils;
+use crate::monitors;
+
+/// Grab the image to process from the camera, blending several frames in ghost mode
+pub fn capture_image(camera: &Camera, args: &cli::Args) -> Result<(RgbImage, SegmentInfo)> {
+    let options = stream_options(args)?;
+    let ghost_mode = args.ghost.as_deref().map(GhostMode::parse).transpose()?;
+
+    let selection = match (ghost_mode, args.best_of, args.frame_offset) {
+        (Some(_), _, _) => FrameSelection::Spread(args.ghost_frames.max(1)),
+        (None, Some(candidates), _) => FrameSelection::Best(candidates),
+        (None, None, Some(offset)) => FrameSelection::At(offset),
+        (None, None, None) => FrameSelection::Spread(1),
+    };
+
+    let mut capture = source::capture(camera, &options, selection)?;
+
+    if args.verbose {
+        for variant in &capture.segment.available_variants {
+            let marker = if capture.segment.variant.as_ref() == Some(variant) {
+                "*"
+            } else {
+                " "
+            };
+            println!("{} {}", marker, variant);
+        }
+    }
+
+    if let Some(variant) = &capture.segment.variant {
+        println!("Using stream variant: {}", variant);
+    }
+
+    if let Some(timestamp) = capture.segment.timestamp {
+        println!(
+            "Segment recorded at {}",
+            timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+
+    let image = match ghost_mode {
+        Some(mode) => ghost::composite_frames(&capture.frames, mode)?,
+        None => capture.frames.remove(0),
+    };
+
+    Ok((image, capture.segment))
+}
+
+/// Segment and variant selection from `--segment` and `--variant`
+pub fn stream_options(args: &cli::Args) -> Result<StreamOptions> {
+    Ok(StreamOptions {
+        segment: SegmentSelection::parse(&args.segment)?,
+        variant: VariantSelection::parse(&args.variant)?,
+    })
+}
 
 pub fn process_and_set_wallpaper(
     original_image: RgbImage,
+    camera: &Camera,
+    segment: &SegmentInfo,
     args: &cli::Args,
     cache_dir: &Path,
 ) -> Result<()> {
-    let output_path = if args.skip_cache {
-        std::env::temp_dir().join("current_wallpaper.jpg")
+    let output_path = wallpaper_path(args, cache_dir, None)?;
+    let pipeline = build_pipeline(args)?;
+    let pipeline_spec = pipeline.spec();
+    let processed_image = render(&original_image, pipeline, camera, segment, args)?;
+    let processed_image = fit_to_display(processed_image, camera, args)?;
+
+    let setter = backend::parse(&args.wallpaper_backend)?;
+    save_wallpaper(&processed_image, &output_path, args)?;
+    setter.set(&output_path)?;
+
+    let fitted = args.fit.is_some() || args.resolution.is_some();
+    let entry = HistoryEntry {
+        fit: fitted.then(|| fit_name(args)).transpose()?,
+        original: save_original(args, &original_image, &output_path),
+        ..history_entry(
+            camera,
+            segment,
+            pipeline_spec,
+            &processed_image,
+            &output_path,
+            args,
+        )?
+    };
+    record_history(args, cache_dir, &entry);
+    prune_cache(args, cache_dir);
+
+    Ok(())
+}
+
+/// Run the pipeline over a captured frame
+pub fn render(
+    original_image: &RgbImage,
+    mut pipeline: Pipeline,
+    camera: &Camera,
+    segment: &SegmentInfo,
+    args: &cli::Args,
+) -> Result<RgbImage> {
+    pipeline.set_scene(&scene(camera, segment, args)?);
+    Ok(pipeline.apply(original_image))
+}
+
+/// Resize to `--resolution`, or the primary monitor's, when `--fit` or `--resolution` is given
+pub fn fit_to_display(image: RgbImage, camera: &Camera, args: &cli::Args) -> Result<RgbImage> {
+    if args.fit.is_none() && args.resolution.is_none() {
+        return Ok(image);
+    }
+
+    let (width, height) = match &args.resolution {
+        Some(resolution) => fit::parse_resolution(resolution)?,
+        None => {
+            let monitors = monitors::monitors(args)?;
+            let primary = monitors
+                .iter()
+                .find(|monitor| monitor.primary)
+                .or(monitors.first())
+                .ok_or_else(|| anyhow!("No monitor to take the resolution from"))?;
+            (primary.width, primary.height)
+        }
+    };
+
+    let (mode, filter) = fit_options(args)?;
+    Ok(fit::fit(&image, width, height, mode, filter, camera.crop))
+}
+
+/// Fit mode from `--fit`, cover when not given, and the `--resample` filter
+pub fn fit_options(args: &cli::Args) -> Result<(FitMode, FilterType)> {
+    let mode = match &args.fit {
+        Some(mode) => FitMode::parse(mode)?,
+        None => FitMode::Cover,
+    };
+    Ok((mode, fit::parse_filter(&args.resample)?))
+}
+
+/// Name of the fit mode in effect, for the history
+pub fn fit_name(args: &cli::Args) -> Result<String> {
+    let (mode, _) = fit_options(args)?;
+    Ok(mode.to_string())
+}
+
+/// What the history should say about `image`, saved to `path`. The caller fills in the fit
+/// and output when they apply
+pub fn history_entry(
+    camera: &Camera,
+    segment: &SegmentInfo,
+    pipeline: String,
+    image: &RgbImage,
+    path: &Path,
+    args: &cli::Args,
+) -> Result<HistoryEntry> {
+    Ok(HistoryEntry {
+        url: camera.url.clone(),
+        segment: segment.uri.clone(),
+        variant: segment.variant.as_ref().map(|variant| variant.to_string()),
+        pipeline,
+        at: args.at.as_deref().map(sun::parse_datetime).transpose()?,
+        width: image.width(),
+        height: image.height(),
+        ..HistoryEntry::new(&camera.name, path, segment.timestamp)
+    })
+}
+
+/// Keep the unprocessed frame of a cached wallpaper so it can be rendered again. Returns where
+/// it went, or `None` with `--skip-cache` or `--skip-originals` or when saving failed
+pub fn save_original(args: &cli::Args, image: &RgbImage, wallpaper: &Path) -> Option<PathBuf> {
+    if args.skip_cache || args.skip_originals {
+        return None;
+    }
+    let path = archive::original_path(wallpaper);
+    match archive::save(image, &path, archive::ORIGINAL_FORMAT) {
+        Ok(()) => Some(path),
+        Err(e) => {
+            eprintln!("Failed to keep the original frame: {}", e);
+            None
+        }
+    }
+}
+
+/// Keep the original a wallpaper was rendered again from for the new wallpaper as well. The
+/// file is shared when it can be rather than encoded again, which would lose a little more of
+/// the frame on every rerender
+pub fn reuse_original(
+    args: &cli::Args,
+    image: &RgbImage,
+    source: &Path,
+    wallpaper: &Path,
+) -> Option<PathBuf> {
+    if args.skip_cache || args.skip_originals {
+        return None;
+    }
+    let path = archive::original_path(wallpaper);
+    if source.extension() == path.extension() {
+        let shared = path
+            .parent()
+            .is_some_and(|dir| fs::create_dir_all(dir).is_ok())
+            && (fs::hard_link(source, &path).is_ok() || fs::copy(source, &path).is_ok());
+        if shared {
+            return Some(path);
+        }
+    }
+    save_original(args, image, wallpaper)
+}
+
+/// Add a saved wallpaper to the history index. Like pruning, failing to is only reported
+pub fn record_history(args: &cli::Args, cache_dir: &Path, entry: &HistoryEntry) {
+    if args.skip_cache {
+        return;
+    }
+    if let Err(e) = history::record(&cache_dir.join(history::INDEX_FILE_NAME), entry) {
+        eprintln!("Failed to record the wallpaper in the history: {}", e);
+    }
+}
+
+/// Limits on the cache from the `--keep-*` flags
+pub fn retention_policy(args: &cli::Args) -> Result<RetentionPolicy> {
+    Ok(RetentionPolicy {
+        keep_last: args.keep_last,
+        max_bytes: args.keep_size.map(|megabytes| megabytes * 1024 * 1024),
+        max_age: args.keep_age.as_deref().map(parse_duration).transpose()?,
+        hourly_after: args
+            .keep_hourly_after
+            .as_deref()
+            .map(parse_duration)
+            .transpose()?,
+    })
+}
+
+/// Apply the retention policy after saving a wallpaper. Failing to clean up is no reason to
+/// fail the fetch, so errors are only reported
+pub fn prune_cache(args: &cli::Args, cache_dir: &Path) {
+    if args.skip_cache {
+        return;
+    }
+
+    let pruned = retention_policy(args).and_then(|policy| {
+        if policy.is_empty() {
+            return Ok(Vec::new());
+        }
+        retention::prune(cache_dir, &policy, Local::now().naive_local(), false)
+    });
+    match pruned {
+        Ok(expired) if !expired.is_empty() => {
+            if args.verbose {
+                println!("Pruned {} cached wallpapers", expired.len());
+            }
+            if let Err(e) = history::forget_missing(&cache_dir.join(history::INDEX_FILE_NAME)) {
+                eprintln!("Failed to update the history: {}", e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("Failed to prune the cache: {}", e),
+    }
+}
+
+/// Where to save the wallpaper, with the output name appended for per-monitor wallpapers and
+/// the `--output-format` extension. Never an existing file in the cache
+pub fn wallpaper_path(args: &cli::Args, cache_dir: &Path, output: Option<&str>) -> Result<PathBuf> {
+    let suffix = output.map(|name| format!("-{}", name)).unwrap_or_default();
+    let extension = output_format(args)?.extension();
+    if args.skip_cache {
+        Ok(std::env::temp_dir().join(format!("current_wallpaper{}.{}", suffix, extension)))
     } else {
-        let filename = Local::now().format("%Y%m%d-%H%M%S.jpg").to_string();
-        cache_dir.join(filename)
+        // Named after when it was saved, so retention never takes a fresh wallpaper from an old
+        // segment for an old one. The capture time is in its history entry
+        let stamp = Local::now().format("%Y%m%d-%H%M%S");
+        let mut path = cache_dir.join(format!("{}{}.{}", stamp, suffix, extension));
+        // A second save within the same second is numbered rather than overwriting the first,
+        // which has its own history entry
+        let mut copy = 1;
+        while path.exists() {
+            copy += 1;
+            path = cache_dir.join(format!("{}_{}{}.{}", stamp, copy, suffix, extension));
+        }
+        Ok(path)
+    }
+}
+
+/// Format from `--output-format`, `--output-quality` and `--progressive`
+pub fn output_format(args: &cli::Args) -> Result<FrameFormat> {
+    match FrameFormat::parse(&args.output_format, args.output_quality)? {
+        FrameFormat::Jpeg { quality, .. } => Ok(FrameFormat::Jpeg {
+            quality,
+            progressive: args.progressive,
+        }),
+        _ if args.progressive => Err(anyhow!("--progressive only applies to jpg wallpapers")),
+        format => Ok(format),
+    }
+}
+
+/// Save a wallpaper in the `--output-format`
+pub fn save_wallpaper(image: &RgbImage, path: &Path, args: &cli::Args) -> Result<()> {
+    archive::save(image, path, output_format(args)?)
+}
+
+/// The camera's location at `--at`, or when the segment was recorded
+fn scene(camera: &Camera, segment: &SegmentInfo, args: &cli::Args) -> Result<Scene> {
+    let time = match &args.at {
+        Some(at) => sun::parse_datetime(at)?,
+        None => segment.timestamp.unwrap_or_else(Utc::now),
     };
+    let location = camera.latitude.zip(camera.longitude);
+
+    if args.verbose {
+        if let Some((latitude, longitude)) = location {
+            let position = sun::sun_position(time, latitude, longitude);
+            println!(
+                "Sun at {:.1}° elevation, {:.0}° azimuth ({})",
+                position.elevation,
+                position.azimuth,
+                SkyPhase::from_elevation(position.elevation)
+            );
+        }
+    }
+
+    Ok(Scene {
+        time,
+        location,
+        timezone: camera.tz(),
+        horizon: camera.horizon,
+    })
+}
 
-    let mut processed_image = original_image.clone();
+/// Use the `--pipeline` spec when given, otherwise the individual effect flags in their
+/// historical order: grayscale (or neon), sky, tint, noise, overlays
+pub fn build_pipeline(args: &cli::Args) -> Result<Pipeline> {
+    if let Some(spec) = &args.pipeline {
+        return Pipeline::parse(spec);
+    }
+
+    let mut pipeline = Pipeline::new();
 
-    if args.grayscale {
-        let gray_image = image::imageops::grayscale(&processed_image);
-        processed_image = image_processing::convert_grayscale_to_rgb(&gray_image);
+    // Neon already turns everything but the light sources gray
+    if args.neon {
+        let hue_ranges = match &args.neon_hues {
+            Some(hues) => effects::parse_hue_ranges(hues)?,
+            None => Vec::new(),
+        };
+        pipeline.push(effects::Neon {
+            hue_ranges,
+            saturation_threshold: args.neon_saturation,
+            glow_radius: args.neon_glow,
+        });
+    } else if args.grayscale {
+        pipeline.push(effects::Grayscale);
     }
 
     if args.color_sky {
-        let sky_color = sky_detection::get_sky_color_for_time();
-        let gray_for_sky = image::imageops::grayscale(&processed_image);
-        let sky_mask = sky_detection::detect_sky_region_growing(&gray_for_sky);
-        processed_image =
-            sky_detection::apply_sky_color_with_gradient(&processed_image, &sky_mask, sky_color);
+        pipeline.push(effects::SkyColor::default());
     }
 
     if let Some(tint_color) = &args.tint_color {
-        processed_image =
-            image_processing::apply_tint_to_rgb(&processed_image, tint_color, args.tint_intensity);
+        pipeline.push(effects::Tint {
+            color: tint_color.clone(),
+            intensity: args.tint_intensity,
+        });
     }
 
     if let Some(noise_type) = &args.noise {
-        match noise_type {
-            cli::NoiseType::Gaussian => {
-                processed_image = image_processing::add_gaussian_noise_to_rgb(
-                    &processed_image,
-                    0.0,
-                    args.noise_intensity,
-                );
-            }
-            cli::NoiseType::SaltPepper => {
-                let density = args.noise_intensity / 255.0;
-                processed_image =
-                    image_processing::add_salt_and_pepper_noise_to_rgb(&processed_image, density);
-            }
-            cli::NoiseType::Poisson => {
-                processed_image = image_processing::add_poisson_noise_to_rgb(&processed_image);
-            }
-        }
+        let kind = match noise_type {
+            cli::NoiseType::Gaussian => effects::NoiseKind::Gaussian,
+            cli::NoiseType::SaltPepper => effects::NoiseKind::SaltPepper,
+            cli::NoiseType::Poisson => effects::NoiseKind::Poisson,
+        };
+        pipeline.push(effects::Noise {
+            kind,
+            intensity: args.noise_intensity,
+        });
     }
 
-    processed_image.save(&output_path)?;
-    utils::set_wallpaper(&output_path)?;
+    if args.alien_beams {
+        pipeline.push(effects::AlienBeams {
+            color: args.beam_color.clone(),
+            beam_count: args.beam_count,
+            seed: args.overlay_seed.unwrap_or_else(rand::random),
+        });
+    }
 
-    Ok(())
+    Ok(pipeline)
 }
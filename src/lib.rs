@@ -1,2 +1,20 @@
+pub mod archive;
+pub mod backend;
+pub mod camera;
+pub mod config;
+pub mod display;
+pub mod effects;
+pub mod fit;
+pub mod ghost;
+pub mod health;
+pub mod history;
 pub mod image_processing;
+pub mod overlay;
+pub mod playlist;
+pub mod retention;
+pub mod schedule;
 pub mod sky_detection;
+pub mod source;
+pub mod stream;
+pub mod sun;
+pub mod timelapse;
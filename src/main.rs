@@ -1,39 +1,133 @@
-mod camera;
 mod cli;
-mod image_processing;
+mod commands;
+mod daemon;
 mod image_processor;
+mod monitors;
+mod recorder;
 mod rotation;
-mod sky_detection;
-mod stream;
+mod ticker;
 mod utils;
 
-use anyhow::{Context, Result};
-use clap::Parser;
+use anyhow::{anyhow, Context, Result};
+use citycam::archive::{self, FrameFormat};
+use citycam::camera;
+use citycam::config::{self, Config};
+use citycam::history::HistoryFilter;
+use citycam::schedule;
+use citycam::sun;
+use citycam::timelapse::TimelapseOptions;
+use clap::{CommandFactory, FromArgMatches};
+use std::ffi::OsString;
 use std::fs;
+use std::path::{Path, PathBuf};
 
 fn main() -> Result<()> {
-    let args = cli::Args::parse();
-
-    let cameras = match &args.cams_file {
-        Some(path) => camera::load_cameras(path)
-            .context(format!("Failed to load cameras from {}", path.display()))?,
-        None => camera::get_embedded_cameras().context("Failed to load embedded cameras")?,
-    };
+    let (args, config) = parse_args()?;
 
     let cache_dir = utils::get_cache_dir()?;
     fs::create_dir_all(&cache_dir)?;
 
-    if args.rotate {
-        return rotation::start_rotation(cameras, &args, &cache_dir);
+    match &args.command {
+        Some(cli::Command::List {
+            filter,
+            kind,
+            tags,
+            region,
+            json,
+        }) => {
+            let filter = camera::CameraFilter {
+                name: filter.clone(),
+                kind: kind.as_deref().map(camera::CameraKind::parse).transpose()?,
+                tags: tags.clone(),
+                region: region.as_deref().map(camera::Region::parse).transpose()?,
+            };
+            commands::list(&args, &config, &filter, *json)
+        }
+        Some(cli::Command::Probe { camera }) => commands::probe(&args, &config, camera),
+        Some(cli::Command::Cache { action }) => commands::cache(action, &args, &cache_dir),
+        Some(cli::Command::History { action }) => commands::history(action, &args, &cache_dir),
+        Some(cli::Command::Rerender { id }) => commands::rerender(&args, &config, &cache_dir, id),
+        Some(cli::Command::Timelapse {
+            file,
+            since,
+            until,
+            fps,
+            deflicker,
+            timestamps,
+            recorded,
+        }) => {
+            let filter = HistoryFilter {
+                since: since.as_deref().map(sun::parse_datetime).transpose()?,
+                until: until.as_deref().map(sun::parse_datetime).transpose()?,
+                recorded: Some(*recorded),
+                ..Default::default()
+            };
+            let options = TimelapseOptions {
+                fps: *fps,
+                deflicker: *deflicker,
+                timestamps: *timestamps,
+            };
+            commands::timelapse(&args, &config, &cache_dir, file, filter, &options)
+        }
+        Some(cli::Command::Record {
+            cameras,
+            interval,
+            format,
+            quality,
+            dir,
+            once,
+        }) => {
+            let format = FrameFormat::parse(format, *quality)?;
+            if !format.readable() {
+                return Err(anyhow!(
+                    "Recorded frames have to be read back for timelapses and rerendering, \
+                     which {} can't be. Use png, jpg or webp",
+                    format.extension()
+                ));
+            }
+            let recording = recorder::Recording {
+                selectors: cameras.clone(),
+                interval: schedule::parse_duration(interval)?,
+                format,
+                root: dir
+                    .clone()
+                    .unwrap_or_else(|| cache_dir.join(archive::DIR_NAME)),
+                once: *once,
+            };
+            recorder::run(&args, &config, &cache_dir, &recording)
+        }
+        Some(cli::Command::Effects) => {
+            commands::effects();
+            Ok(())
+        }
+        Some(cli::Command::Ctl { command }) => {
+            println!("{}", daemon::send_command(*command)?);
+            Ok(())
+        }
+        Some(cli::Command::Health) => commands::health(&args, &config, &cache_dir),
+        Some(cli::Command::Monitors) => commands::monitors(&args),
+        Some(cli::Command::Config { action }) => commands::config(action, &args),
+        Some(cli::Command::Rotate) => daemon::run(&args, &config, &cache_dir),
+        None if args.rotate => daemon::run(&args, &config, &cache_dir),
+        Some(cli::Command::Fetch) | None if monitors::is_enabled(&args) => {
+            monitors::fetch(&args, &config, &cache_dir)
+        }
+        Some(cli::Command::Fetch) | None => fetch(&args, &config, &cache_dir),
     }
+}
 
-    let selected_camera = match &args.camera {
-        Some(selector) => camera::find_camera(&cameras, selector).context(format!(
+/// Capture one frame from the selected camera or source and set it as wallpaper
+fn fetch(args: &cli::Args, config: &Config, cache_dir: &Path) -> Result<()> {
+    let cameras = load_cameras(args, config)?;
+
+    let selected_camera = match (&args.source, &args.camera) {
+        (Some(path), _) => camera::Camera::from_path(path),
+        (None, Some(selector)) => camera::find_camera(&cameras, selector).context(format!(
             "Failed to find camera: {}\n{}",
             selector,
             camera::list_cameras(&cameras)
         ))?,
-        None => {
+        (None, None) => {
             println!("No camera specified, using: {}", cameras[0].name);
             cameras[0].clone()
         }
@@ -41,6 +135,118 @@ fn main() -> Result<()> {
 
     println!("Using camera: {}", selected_camera.name);
 
-    let original_image = stream::get_first_frame(&selected_camera)?;
-    image_processor::process_and_set_wallpaper(original_image, &args, &cache_dir)
+    let (original_image, segment) = image_processor::capture_image(&selected_camera, args)?;
+    image_processor::process_and_set_wallpaper(
+        original_image,
+        &selected_camera,
+        &segment,
+        args,
+        cache_dir,
+    )
+}
+
+/// Parse the command line on top of the config file: its defaults first, then the selected
+/// profile, then the flags actually given
+fn parse_args() -> Result<(cli::Args, Config)> {
+    let command_line: Vec<OsString> = std::env::args_os().collect();
+    let command = config::negatable_flags(cli::Args::command());
+    let matches = command.clone().get_matches_from(&command_line);
+    check_rotation_flags(&command, &matches)?;
+    let args = parse_matches(matches);
+
+    let path = match config_path(&args)? {
+        Some(path) => path,
+        None if args.profile.is_some() => {
+            return Err(anyhow!(
+                "No config file at {} to take the profile from",
+                utils::get_config_path()?.display()
+            ))
+        }
+        None => return Ok((args, Config::default())),
+    };
+
+    let config = Config::load(&path)?;
+    let merged = config
+        .merge_args(&command, &command_line, args.profile.as_deref())
+        .context(format!("Invalid config file {}", path.display()))?;
+    Ok((parse_matches(command.get_matches_from(merged)), config))
+}
+
+/// The command line on top of the config file as it is now, for the daemon to reload. Unlike at
+/// startup a bad config file is reported rather than ending the process
+fn reload_args() -> Result<(cli::Args, Config)> {
+    let command_line: Vec<OsString> = std::env::args_os().collect();
+    let command = config::negatable_flags(cli::Args::command());
+    let args = try_parse_args(command.clone(), &command_line)?;
+
+    let Some(path) = config_path(&args)? else {
+        return Ok((args, Config::default()));
+    };
+    let config = Config::load(&path)?;
+    let merged = config
+        .merge_args(&command, &command_line, args.profile.as_deref())
+        .context(format!("Invalid config file {}", path.display()))?;
+    Ok((try_parse_args(command, &merged)?, config))
+}
+
+fn try_parse_args(command: clap::Command, command_line: &[OsString]) -> Result<cli::Args> {
+    let matches = command.try_get_matches_from(command_line)?;
+    Ok(cli::Args::from_arg_matches(&matches)?)
+}
+
+/// The rotation's flags with the subcommands other than rotate that use them too
+const ROTATION_FLAGS: &[(&str, &[&str])] = &[
+    ("rotation_interval", &[]),
+    ("rotation_strategy", &[]),
+    ("playlists", &[]),
+    ("schedule", &["record"]),
+];
+
+/// Reject rotation flags given to a command that isn't rotating, which would ignore them. The
+/// config may still set them as defaults
+fn check_rotation_flags(command: &clap::Command, matches: &clap::ArgMatches) -> Result<()> {
+    let subcommand = matches.subcommand_name();
+    if subcommand == Some("rotate") || matches.get_flag("rotate") {
+        return Ok(());
+    }
+    for (id, also_used_by) in ROTATION_FLAGS {
+        if !config::given(matches, id)
+            || subcommand.is_some_and(|name| also_used_by.contains(&name))
+        {
+            continue;
+        }
+        let flag = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id)
+            .and_then(|arg| arg.get_long())
+            .unwrap_or(id);
+        return Err(anyhow!(
+            "--{} only applies to the rotation, use it with citycam rotate",
+            flag
+        ));
+    }
+    Ok(())
+}
+
+fn parse_matches(matches: clap::ArgMatches) -> cli::Args {
+    cli::Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+}
+
+/// `--config`, or config.toml in the config directory when there is one
+fn config_path(args: &cli::Args) -> Result<Option<PathBuf>> {
+    if let Some(path) = &args.config {
+        return Ok(Some(path.clone()));
+    }
+    let path = utils::get_config_path()?;
+    Ok(path.exists().then_some(path))
+}
+
+/// Cameras from `--cams-file`, the config file, or the embedded list
+fn load_cameras(args: &cli::Args, config: &Config) -> Result<Vec<camera::Camera>> {
+    match &args.cams_file {
+        Some(path) => camera::load_cameras(path)
+            .context(format!("Failed to load cameras from {}", path.display())),
+        None if !config.cameras.is_empty() => Ok(config.cameras.clone()),
+        None => camera::get_embedded_cameras().context("Failed to load embedded cameras"),
+    }
 }
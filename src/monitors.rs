@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use citycam::backend;
+use citycam::camera::{self, Camera};
+use citycam::config::Config;
+use citycam::display::{self, Monitor, OutputAssignment};
+use citycam::effects::Pipeline;
+use citycam::fit;
+use citycam::history::HistoryEntry;
+use citycam::stream::SegmentInfo;
+use image::RgbImage;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::cli;
+use crate::image_processor;
+
+/// Monitors from `--monitor`, detected when none are listed
+pub fn monitors(args: &cli::Args) -> Result<Vec<Monitor>> {
+    if args.monitors.is_empty() {
+        return display::detect_monitors();
+    }
+    args.monitors
+        .iter()
+        .map(|spec| Monitor::parse(spec))
+        .collect()
+}
+
+/// Whether to set a wallpaper per monitor rather than one for the whole desktop
+pub fn is_enabled(args: &cli::Args) -> bool {
+    args.span || !args.outputs.is_empty() || !args.monitors.is_empty()
+}
+
+/// Capture a frame for every monitor, from its `--output` camera or else `--camera`, and set
+/// them as one wallpaper per monitor or, with `--span`, one image across the layout
+pub fn fetch(args: &cli::Args, config: &Config, cache_dir: &Path) -> Result<()> {
+    let cameras = crate::load_cameras(args, config)?;
+    set_wallpapers(args, &cameras, None, HashMap::new(), cache_dir)
+}
+
+/// Set the wallpapers of every monitor as `fetch` does, showing `default` instead of `--camera`
+/// on monitors without an `--output` camera. `captures` holds frames already captured, by
+/// camera name
+pub fn set_wallpapers(
+    args: &cli::Args,
+    cameras: &[Camera],
+    default: Option<&Camera>,
+    mut captures: HashMap<String, (RgbImage, SegmentInfo)>,
+    cache_dir: &Path,
+) -> Result<()> {
+    let monitors = monitors(args)?;
+    let assignments = args
+        .outputs
+        .iter()
+        .map(|spec| OutputAssignment::parse(spec))
+        .collect::<Result<Vec<_>>>()?;
+
+    for assignment in &assignments {
+        if !monitors.iter().any(|m| m.name == assignment.output) {
+            let names: Vec<&str> = monitors.iter().map(|m| m.name.as_str()).collect();
+            return Err(anyhow!(
+                "Unknown output: {} (connected: {})",
+                assignment.output,
+                names.join(", ")
+            ));
+        }
+    }
+
+    let (mode, filter) = image_processor::fit_options(args)?;
+    let setter = backend::parse(&args.wallpaper_backend)?;
+
+    // Monitors showing the same camera share one capture
+    let mut rendered: Vec<(Monitor, RgbImage, HistoryEntry)> = Vec::new();
+    let fit_name = image_processor::fit_name(args)?;
+    let saved_at = Utc::now();
+
+    for monitor in &monitors {
+        let assignment = assignments.iter().find(|a| a.output == monitor.name);
+        let selected_camera = match (assignment, default) {
+            (None, Some(camera)) => camera.clone(),
+            _ => {
+                let selector = assignment
+                    .map(|a| a.camera.as_str())
+                    .or(args.camera.as_deref())
+                    .unwrap_or("");
+                camera::find_camera(cameras, selector).context(format!(
+                    "Failed to find camera for {}: {}\n{}",
+                    monitor.name,
+                    selector,
+                    camera::list_cameras(cameras)
+                ))?
+            }
+        };
+        println!("{}: {}", monitor.name, selected_camera.name);
+
+        if !captures.contains_key(&selected_camera.name) {
+            let captured = image_processor::capture_image(&selected_camera, args)?;
+            captures.insert(selected_camera.name.clone(), captured);
+        }
+        let (original_image, segment) = &captures[&selected_camera.name];
+
+        let pipeline = match assignment.and_then(|a| a.pipeline.as_deref()) {
+            Some(spec) => Pipeline::parse(spec)?,
+            None => image_processor::build_pipeline(args)?,
+        };
+        let pipeline_spec = pipeline.spec();
+        let image =
+            image_processor::render(original_image, pipeline, &selected_camera, segment, args)?;
+        // A span is one image across the layout, so each part goes in at its layout size
+        let (width, height) = if args.span {
+            monitor.layout_size()
+        } else {
+            (monitor.width, monitor.height)
+        };
+        let image = fit::fit(&image, width, height, mode, filter, selected_camera.crop);
+
+        let path = image_processor::wallpaper_path(args, cache_dir, Some(&monitor.name))?;
+        let entry = HistoryEntry {
+            fit: Some(fit_name.clone()),
+            output: Some(monitor.name.clone()),
+            saved_at,
+            ..image_processor::history_entry(
+                &selected_camera,
+                segment,
+                pipeline_spec,
+                &image,
+                &path,
+                args,
+            )?
+        };
+        rendered.push((monitor.clone(), image, entry));
+    }
+
+    let Some((_, _, first)) = rendered.first() else {
+        return Err(anyhow!("No monitors to set a wallpaper on"));
+    };
+
+    if args.span {
+        let mut names: Vec<&str> = rendered.iter().map(|(_, _, e)| e.camera.as_str()).collect();
+        names.dedup();
+        let path = image_processor::wallpaper_path(args, cache_dir, Some("span"))?;
+        let parts: Vec<(Monitor, RgbImage)> = rendered
+            .iter()
+            .map(|(monitor, image, _)| (monitor.clone(), image.clone()))
+            .collect();
+        let image = display::stitch(&parts);
+        image_processor::save_wallpaper(&image, &path, args)?;
+        setter.set(&path)?;
+
+        // The history has one entry per file, so the cameras of a span share it. There is no
+        // one original to render it from again
+        let entry = HistoryEntry {
+            camera: names.join(" + "),
+            output: Some("span".to_string()),
+            width: image.width(),
+            height: image.height(),
+            path,
+            ..first.clone()
+        };
+        image_processor::record_history(args, cache_dir, &entry);
+        image_processor::prune_cache(args, cache_dir);
+        return Ok(());
+    }
+
+    let mut wallpapers = Vec::new();
+    for (monitor, image, mut entry) in rendered {
+        image_processor::save_wallpaper(&image, &entry.path, args)?;
+        let (original_image, _) = &captures[&entry.camera];
+        entry.original = image_processor::save_original(args, original_image, &entry.path);
+        image_processor::record_history(args, cache_dir, &entry);
+        wallpapers.push((monitor.name, entry.path));
+    }
+    backend::apply(setter.as_ref(), &wallpapers)?;
+    image_processor::prune_cache(args, cache_dir);
+    Ok(())
+}
@@ -0,0 +1,257 @@
+use image::{Rgb, RgbImage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::sky_detection;
+
+/// Draw a saucer hovering in the detected sky with light beams reaching down to the skyline.
+/// The same seed always produces the same scene for a given image
+pub fn draw_alien_beams(
+    img: &RgbImage,
+    beam_color: Rgb<u8>,
+    beam_count: u32,
+    seed: u64,
+) -> RgbImage {
+    let width = img.width();
+    let height = img.height();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut result = img.clone();
+
+    if width == 0 || height == 0 {
+        return result;
+    }
+
+    // Lowest sky pixel per column, beams end where the sky meets the city
+    let gray = image::imageops::grayscale(img);
+    let sky_mask = sky_detection::detect_sky_region_growing(&gray);
+    let skyline: Vec<u32> = (0..width as usize)
+        .map(|x| {
+            (0..height as usize)
+                .rev()
+                .find(|&y| sky_mask[y][x])
+                .unwrap_or(0) as u32
+        })
+        .collect();
+    let mean_skyline = skyline.iter().map(|&y| y as f32).sum::<f32>() / width as f32;
+
+    // Keep the saucer in the upper part of the sky, even when hardly any sky was found
+    let saucer_width = (width as f32 * 0.12).max(6.0);
+    let saucer_height = saucer_width * 0.3;
+    let saucer_x = rng.random_range(0.2..0.8) * width as f32;
+    let saucer_y = (mean_skyline * 0.4).max(height as f32 * 0.12);
+
+    for _ in 0..beam_count {
+        let target_x =
+            (saucer_x + rng.random_range(-1.5..1.5) * saucer_width).clamp(0.0, width as f32 - 1.0);
+        let target_y = (skyline[target_x as usize] as f32 + saucer_height)
+            .max(saucer_y + saucer_height)
+            .min(height as f32 - 1.0);
+        let strength = rng.random_range(0.55..0.9);
+
+        draw_beam(
+            &mut result,
+            (saucer_x, saucer_y + saucer_height * 0.5),
+            (target_x, target_y),
+            (
+                saucer_width * 0.12,
+                saucer_width * rng.random_range(0.35..0.6),
+            ),
+            beam_color,
+            strength,
+        );
+        draw_glow(
+            &mut result,
+            (target_x, target_y),
+            saucer_width * 0.7,
+            beam_color,
+            strength * 0.6,
+        );
+    }
+
+    draw_glow(
+        &mut result,
+        (saucer_x, saucer_y),
+        saucer_width * 1.2,
+        beam_color,
+        0.5,
+    );
+    draw_saucer(
+        &mut result,
+        (saucer_x, saucer_y),
+        saucer_width,
+        saucer_height,
+        beam_color,
+    );
+
+    result
+}
+
+// Cone of light from `start` down to `end`, fading towards its edges and its far end
+fn draw_beam(
+    img: &mut RgbImage,
+    start: (f32, f32),
+    end: (f32, f32),
+    half_widths: (f32, f32),
+    color: Rgb<u8>,
+    strength: f32,
+) {
+    let (x0, y0) = start;
+    let (x1, y1) = end;
+    let length = (y1 - y0).max(1.0);
+    let max_half_width = half_widths.0.max(half_widths.1);
+
+    let min_x = (x0.min(x1) - max_half_width).max(0.0) as u32;
+    let max_x = ((x0.max(x1) + max_half_width) as u32).min(img.width() - 1);
+    let min_y = y0.max(0.0) as u32;
+    let max_y = (y1 as u32).min(img.height() - 1);
+
+    for y in min_y..=max_y {
+        let t = ((y as f32 - y0) / length).clamp(0.0, 1.0);
+        let center = x0 + (x1 - x0) * t;
+        let half_width = half_widths.0 + (half_widths.1 - half_widths.0) * t;
+
+        for x in min_x..=max_x {
+            let d = (x as f32 - center).abs() / half_width;
+            if d < 1.0 {
+                let intensity = (1.0 - d * d) * (1.0 - 0.4 * t) * strength;
+                add_light(img, x, y, color, intensity);
+            }
+        }
+    }
+}
+
+// Soft radial glow, used around the saucer and where beams hit the skyline
+fn draw_glow(img: &mut RgbImage, center: (f32, f32), radius: f32, color: Rgb<u8>, strength: f32) {
+    let min_x = (center.0 - radius).max(0.0) as u32;
+    let max_x = ((center.0 + radius) as u32).min(img.width() - 1);
+    let min_y = (center.1 - radius).max(0.0) as u32;
+    let max_y = ((center.1 + radius) as u32).min(img.height() - 1);
+
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let dx = x as f32 - center.0;
+            let dy = y as f32 - center.1;
+            let d = (dx * dx + dy * dy).sqrt() / radius;
+            if d < 1.0 {
+                add_light(img, x, y, color, (1.0 - d).powi(2) * strength);
+            }
+        }
+    }
+}
+
+// Dark hull and dome with a row of lights along the rim
+fn draw_saucer(img: &mut RgbImage, center: (f32, f32), width: f32, height: f32, light: Rgb<u8>) {
+    let hull = Rgb([18, 20, 24]);
+    let half_width = width / 2.0;
+    let half_height = height / 2.0;
+    let dome_radius = width * 0.2;
+
+    let min_x = (center.0 - half_width).max(0.0) as u32;
+    let max_x = ((center.0 + half_width) as u32).min(img.width() - 1);
+    let min_y = (center.1 - half_height - dome_radius).max(0.0) as u32;
+    let max_y = ((center.1 + half_height) as u32).min(img.height() - 1);
+
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let dx = x as f32 - center.0;
+            let dy = y as f32 - center.1;
+            let in_hull = (dx / half_width).powi(2) + (dy / half_height).powi(2) <= 1.0;
+            let dome_dy = y as f32 - (center.1 - half_height * 0.5);
+            let in_dome = dome_dy <= 0.0 && dx * dx + dome_dy * dome_dy <= dome_radius.powi(2);
+
+            if in_hull || in_dome {
+                img.put_pixel(x, y, hull);
+            }
+        }
+    }
+
+    let light_count = 7;
+    for i in 0..light_count {
+        let t = (i as f32 + 0.5) / light_count as f32;
+        let x = center.0 - half_width * 0.8 + width * 0.8 * t;
+        if x >= 0.0
+            && (x as u32) < img.width()
+            && center.1 >= 0.0
+            && (center.1 as u32) < img.height()
+        {
+            img.put_pixel(x as u32, center.1 as u32, light);
+        }
+    }
+}
+
+// Screen blend so light never darkens what is underneath
+fn add_light(img: &mut RgbImage, x: u32, y: u32, color: Rgb<u8>, intensity: f32) {
+    let intensity = intensity.clamp(0.0, 1.0);
+    let pixel = img.get_pixel_mut(x, y);
+    for c in 0..3 {
+        let base = pixel[c] as f32 / 255.0;
+        let light = color[c] as f32 / 255.0 * intensity;
+        pixel[c] = ((1.0 - (1.0 - base) * (1.0 - light)) * 255.0) as u8;
+    }
+}
+
+/// Glyphs of a 5x7 pixel font, one row per byte with the leftmost pixel in bit 4. Enough for
+/// dates and times
+const GLYPHS: &[(char, [u8; 7])] = &[
+    ('0', [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e]),
+    ('1', [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    ('2', [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f]),
+    ('3', [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e]),
+    ('4', [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02]),
+    ('5', [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e]),
+    ('6', [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e]),
+    ('7', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e]),
+    ('9', [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c]),
+    ('-', [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00]),
+    (':', [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+];
+
+/// Write `text` in white on a dark box in the bottom right corner, sized to the image. Only
+/// digits and `-:./` are drawn, anything else leaves a space
+pub fn draw_label(img: &mut RgbImage, text: &str) {
+    let scale = (img.height() / 180).max(1);
+    let advance = 6 * scale;
+    let padding = 2 * scale;
+    let box_width = text.chars().count() as u32 * advance + padding * 2;
+    let box_height = 7 * scale + padding * 2;
+    if box_width + padding > img.width() || box_height + padding > img.height() {
+        return;
+    }
+
+    let left = img.width() - box_width - padding;
+    let top = img.height() - box_height - padding;
+    for y in top..top + box_height {
+        for x in left..left + box_width {
+            let pixel = img.get_pixel_mut(x, y);
+            for c in 0..3 {
+                pixel[c] /= 3;
+            }
+        }
+    }
+
+    for (i, ch) in text.chars().enumerate() {
+        let Some((_, rows)) = GLYPHS.iter().find(|(glyph, _)| *glyph == ch) else {
+            continue;
+        };
+        let glyph_left = left + padding + i as u32 * advance;
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..5 {
+                if bits & (0x10 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        img.put_pixel(
+                            glyph_left + column * scale + dx,
+                            top + padding + row as u32 * scale + dy,
+                            Rgb([255, 255, 255]),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
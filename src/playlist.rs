@@ -0,0 +1,334 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveTime;
+use rand::distr::weighted::WeightedIndex;
+use rand::distr::Distribution;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::camera::{self, Camera, CameraFilter};
+
+/// How the rotation picks the next camera
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// In cams.json order
+    #[default]
+    Sequential,
+    /// Uniformly at random, never the same camera twice in a row
+    Random,
+    /// Random order without repeats until every camera has been shown
+    Shuffle,
+    /// At random, in proportion to each camera's `weight`
+    Weighted,
+}
+
+impl Strategy {
+    /// Parse `sequential`, `random`, `shuffle` or `weighted`
+    pub fn parse(spec: &str) -> Result<Self> {
+        match spec.trim().to_lowercase().as_str() {
+            "sequential" => Ok(Strategy::Sequential),
+            "random" => Ok(Strategy::Random),
+            "shuffle" => Ok(Strategy::Shuffle),
+            "weighted" => Ok(Strategy::Weighted),
+            other => Err(anyhow!("Unknown rotation strategy: {}", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Strategy::Sequential => "sequential",
+            Strategy::Random => "random",
+            Strategy::Shuffle => "shuffle",
+            Strategy::Weighted => "weighted",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Cameras to rotate through during a time of day, e.g. harbor cams in the early morning
+#[derive(Clone, Debug, PartialEq)]
+pub struct Playlist {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// Camera indexes (1-based), names, `tag:<tag>` or `region:<region>`, matched like
+    /// `--camera`
+    pub cameras: Vec<String>,
+}
+
+impl Playlist {
+    /// Parse `<HH:MM>-<HH:MM>=<camera>,<camera>,...`. Windows ending before they start wrap
+    /// past midnight
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (window, cameras) = spec
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Playlists look like 06:00-09:00=harbor,pier: {}", spec))?;
+        let (start, end) = window
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Invalid playlist window: {}", window))?;
+        let parse_time = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|_| anyhow!("Invalid time in playlist window: {}", time))
+        };
+
+        let mut selectors: Vec<String> = Vec::new();
+        for part in cameras.split(',').map(str::trim) {
+            // Region selectors have commas of their own, keep their coordinates together
+            match selectors.last_mut() {
+                Some(region) if is_partial_region(region) => {
+                    region.push(',');
+                    region.push_str(part);
+                }
+                _ => selectors.push(part.to_string()),
+            }
+        }
+        let cameras: Vec<String> = selectors
+            .into_iter()
+            .filter(|camera| !camera.is_empty())
+            .collect();
+        if cameras.is_empty() {
+            return Err(anyhow!("Playlist has no cameras: {}", spec));
+        }
+
+        Ok(Playlist {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+            cameras,
+        })
+    }
+
+    pub fn is_active(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+// `region:` selector still missing coordinates, bounds take four and a radius ends in `@<km>`
+fn is_partial_region(selector: &str) -> bool {
+    selector
+        .get(..7)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("region:"))
+        && !selector.contains('@')
+        && selector.matches(',').count() < 3
+}
+
+/// What the rotation remembers between runs
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RotationState {
+    pub strategy: Strategy,
+    /// Name of the camera shown last
+    pub current: Option<String>,
+    /// Cameras the shuffle strategy still has to show this round, by name
+    #[serde(default)]
+    pub remaining: Vec<String>,
+}
+
+impl RotationState {
+    /// Saved state, or the default when there is none or it can't be read
+    pub fn load(path: &Path) -> RotationState {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?).context(format!(
+            "Failed to save rotation state to {}",
+            path.display()
+        ))
+    }
+}
+
+/// Picks cameras one after another according to a strategy and the active playlist
+pub struct Rotation {
+    cameras: Vec<Camera>,
+    playlists: Vec<Playlist>,
+    state: RotationState,
+}
+
+impl Rotation {
+    pub fn new(cameras: Vec<Camera>) -> Rotation {
+        Rotation {
+            cameras,
+            playlists: Vec::new(),
+            state: RotationState::default(),
+        }
+    }
+
+    pub fn with_playlists(mut self, playlists: Vec<Playlist>) -> Rotation {
+        self.playlists = playlists;
+        self
+    }
+
+    /// Carry on from a saved state. A different strategy keeps the current camera but starts
+    /// a fresh shuffle round
+    pub fn with_state(mut self, state: RotationState, strategy: Strategy) -> Rotation {
+        self.state = state;
+        if self.state.strategy != strategy {
+            self.state.strategy = strategy;
+            self.state.remaining.clear();
+        }
+        self
+    }
+
+    pub fn state(&self) -> &RotationState {
+        &self.state
+    }
+
+    pub fn cameras(&self) -> &[Camera] {
+        &self.cameras
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.state.strategy
+    }
+
+    /// Camera shown last, if it is still in the list
+    pub fn current(&self) -> Option<&Camera> {
+        let name = self.state.current.as_ref()?;
+        self.cameras.iter().find(|camera| &camera.name == name)
+    }
+
+    /// Swap in a new camera list, carrying on after the current camera if it is still there
+    pub fn set_cameras(&mut self, cameras: Vec<Camera>) {
+        self.cameras = cameras;
+    }
+
+    /// Pick the next camera for the time of day `now`
+    pub fn advance<R: Rng>(&mut self, now: NaiveTime, rng: &mut R) -> Option<&Camera> {
+        self.advance_available(now, rng, |_| true)
+    }
+
+    /// Like [`Rotation::advance`], passing over cameras that aren't `available`, e.g. dead
+    /// streams backing off. When none are available every camera is considered again
+    pub fn advance_available<R: Rng, F: Fn(&Camera) -> bool>(
+        &mut self,
+        now: NaiveTime,
+        rng: &mut R,
+        available: F,
+    ) -> Option<&Camera> {
+        let pool = self.pool(now);
+        if pool.is_empty() {
+            return None;
+        }
+        let mut candidates = pool.clone();
+        if candidates.iter().any(|&i| available(&self.cameras[i])) {
+            candidates.retain(|&i| available(&self.cameras[i]));
+        }
+
+        let current = self
+            .state
+            .current
+            .as_ref()
+            .and_then(|name| pool.iter().position(|&i| &self.cameras[i].name == name));
+
+        let index = match self.state.strategy {
+            // Next available camera after the current one, in order
+            Strategy::Sequential => {
+                let start = current.unwrap_or(pool.len() - 1);
+                (1..=pool.len())
+                    .map(|step| pool[(start + step) % pool.len()])
+                    .find(|index| candidates.contains(index))
+                    .unwrap_or(candidates[0])
+            }
+            Strategy::Random => {
+                let current = current.map(|position| pool[position]);
+                let choices: Vec<usize> = candidates
+                    .iter()
+                    .copied()
+                    .filter(|&index| candidates.len() == 1 || Some(index) != current)
+                    .collect();
+                choices[rng.random_range(0..choices.len())]
+            }
+            Strategy::Weighted => {
+                let weights = candidates
+                    .iter()
+                    .map(|&i| self.cameras[i].weight.unwrap_or(1.0));
+                match WeightedIndex::new(weights) {
+                    Ok(distribution) => candidates[distribution.sample(rng)],
+                    // All weights zero or invalid, fall back to a uniform pick
+                    Err(_) => candidates[rng.random_range(0..candidates.len())],
+                }
+            }
+            Strategy::Shuffle => self.next_from_bag(&candidates, rng),
+        };
+
+        self.state.current = Some(self.cameras[index].name.clone());
+        Some(&self.cameras[index])
+    }
+
+    // Draw the next camera of the shuffle round, starting a new round once every camera in
+    // the pool has been shown
+    fn next_from_bag<R: Rng>(&mut self, pool: &[usize], rng: &mut R) -> usize {
+        let in_pool = |name: &String| {
+            pool.iter()
+                .copied()
+                .find(|&i| &self.cameras[i].name == name)
+        };
+
+        while let Some(name) = self.state.remaining.pop() {
+            if let Some(index) = in_pool(&name) {
+                return index;
+            }
+        }
+
+        let mut round: Vec<String> = pool.iter().map(|&i| self.cameras[i].name.clone()).collect();
+        round.shuffle(rng);
+
+        // Don't show the last camera of a round again as the first of the next one
+        if round.len() > 1 && round.last() == self.state.current.as_ref() {
+            let last = round.len() - 1;
+            round.swap(0, last);
+        }
+
+        let name = round.pop().expect("pool is not empty");
+        self.state.remaining = round;
+        in_pool(&name).expect("round is drawn from the pool")
+    }
+
+    // Indexes of the cameras the first active playlist names, every camera when none is active.
+    // Tag and region selectors bring in every camera they match
+    fn pool(&self, now: NaiveTime) -> Vec<usize> {
+        let active =
+            self.playlists
+                .iter()
+                .find(|playlist| playlist.is_active(now))
+                .map(|playlist| {
+                    let mut indexes: Vec<usize> = Vec::new();
+                    let found = playlist.cameras.iter().flat_map(|selector| {
+                        match CameraFilter::from_selector(selector) {
+                            Ok(Some(filter)) => filter
+                                .apply(&self.cameras)
+                                .into_iter()
+                                .map(|(number, _)| number - 1)
+                                .collect(),
+                            Ok(None) => camera::find_camera(&self.cameras, selector)
+                                .ok()
+                                .and_then(|found| {
+                                    self.cameras.iter().position(|c| c.name == found.name)
+                                })
+                                .into_iter()
+                                .collect(),
+                            Err(_) => Vec::new(),
+                        }
+                    });
+                    for index in found {
+                        if !indexes.contains(&index) {
+                            indexes.push(index);
+                        }
+                    }
+                    indexes
+                })
+                .filter(|indexes| !indexes.is_empty());
+
+        active.unwrap_or_else(|| (0..self.cameras.len()).collect())
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Local, Utc};
+use citycam::archive::{self, FrameFormat};
+use citycam::camera::{self, Camera};
+use citycam::config::Config;
+use citycam::history::HistoryEntry;
+use citycam::schedule::Schedule;
+use std::path::{Path, PathBuf};
+
+use crate::cli;
+use crate::image_processor;
+use crate::ticker::Ticker;
+
+/// What `citycam record` was asked to do
+pub struct Recording {
+    /// Camera selectors, `--camera` or `--source` when empty
+    pub selectors: Vec<String>,
+    /// Time between rounds when there is no `--schedule`
+    pub interval: Duration,
+    pub format: FrameFormat,
+    /// Directory the per-camera directories go in
+    pub root: PathBuf,
+    /// Stop after the first round
+    pub once: bool,
+}
+
+/// Save a raw frame of every camera on the schedule until SIGTERM/SIGINT, without touching
+/// the wallpaper. The frames go in the archive and the history, for timelapses and ghosts
+pub fn run(
+    args: &cli::Args,
+    config: &Config,
+    cache_dir: &Path,
+    recording: &Recording,
+) -> Result<()> {
+    if args.ghost.is_some() {
+        return Err(anyhow!(
+            "record keeps frames raw, --ghost can be applied to the archive afterwards"
+        ));
+    }
+    let cameras = select_cameras(args, config, &recording.selectors)?;
+    archive::check_camera_dirs(cameras.iter().map(|camera| camera.name.as_str()))?;
+    let schedule = match &args.schedule {
+        Some(spec) => Schedule::parse(spec)?,
+        None => Schedule::every(recording.interval),
+    };
+    let mut ticker = Ticker::start(schedule)?;
+
+    let names: Vec<&str> = cameras.iter().map(|camera| camera.name.as_str()).collect();
+    println!(
+        "Recording {} to {}",
+        names.join(", "),
+        recording.root.display()
+    );
+
+    while ticker.running() {
+        if ticker.finished() {
+            println!("The schedule has no more rounds");
+            break;
+        }
+
+        if ticker.is_due() {
+            for camera in &cameras {
+                if !ticker.running() {
+                    break;
+                }
+                // One camera failing is no reason to stop recording the others
+                if let Err(e) = record_frame(camera, args, cache_dir, recording) {
+                    eprintln!("Failed to record {}: {}", camera.name, e);
+                }
+            }
+            if recording.once {
+                break;
+            }
+            ticker.reschedule();
+            continue;
+        }
+
+        std::thread::sleep(ticker.wait());
+    }
+
+    Ok(())
+}
+
+/// Capture a frame and save it untouched to the archive
+fn record_frame(
+    camera: &Camera,
+    args: &cli::Args,
+    cache_dir: &Path,
+    recording: &Recording,
+) -> Result<()> {
+    let (image, segment) = image_processor::capture_image(camera, args)?;
+    // Name the file after when the camera saw it, like the cached wallpapers
+    let captured_at = segment.timestamp.unwrap_or_else(Utc::now);
+    let path = archive::frame_path(
+        &recording.root,
+        &camera.name,
+        captured_at.with_timezone(&Local),
+        recording.format,
+    );
+    if path.exists() {
+        // Snapshots and image directories hand out the same frame until the camera updates
+        println!("{}: no new frame since {}", camera.name, path.display());
+        return Ok(());
+    }
+    archive::save(&image, &path, recording.format)?;
+    println!("{}: {}", camera.name, path.display());
+
+    let entry = HistoryEntry {
+        recorded: true,
+        ..image_processor::history_entry(camera, &segment, String::new(), &image, &path, args)?
+    };
+    image_processor::record_history(args, cache_dir, &entry);
+    Ok(())
+}
+
+/// Cameras for the selectors, or the `--source` or `--camera` one without any
+fn select_cameras(args: &cli::Args, config: &Config, selectors: &[String]) -> Result<Vec<Camera>> {
+    if selectors.is_empty() {
+        return match (&args.source, &args.camera) {
+            (Some(path), _) => Ok(vec![Camera::from_path(path)]),
+            (None, Some(selector)) => select_cameras(args, config, std::slice::from_ref(selector)),
+            (None, None) => Err(anyhow!(
+                "Name the cameras to record, e.g. citycam record harbor tag:bridge"
+            )),
+        };
+    }
+
+    let cameras = crate::load_cameras(args, config)?;
+    camera::find_cameras(&cameras, selectors)
+}
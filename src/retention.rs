@@ -0,0 +1,166 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDateTime};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+use crate::history;
+
+/// Extensions of the wallpapers saved in the cache
+pub const WALLPAPER_EXTENSIONS: &[&str] = &["jpg", "png", "webp", "avif"];
+
+/// Which cached wallpapers to keep. Every limit that is set applies, the newest wallpaper of
+/// every output is always kept since it is probably still on screen
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RetentionPolicy {
+    /// Keep at most this many wallpapers
+    pub keep_last: Option<usize>,
+    /// Keep the newest wallpapers up to this many bytes in total
+    pub max_bytes: Option<u64>,
+    /// Delete wallpapers older than this
+    pub max_age: Option<Duration>,
+    /// Past this age keep only the newest wallpaper of every hour
+    pub hourly_after: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        *self == RetentionPolicy::default()
+    }
+
+    /// The wallpapers to delete, newest first
+    pub fn expired<'a>(&self, files: &'a [CachedFile], now: NaiveDateTime) -> Vec<&'a CachedFile> {
+        let mut newest_first: Vec<&CachedFile> = files.iter().collect();
+        newest_first.sort_by(|a, b| b.saved.cmp(&a.saved).then(b.path.cmp(&a.path)));
+
+        let mut seen_outputs = HashSet::new();
+        let mut seen_hours = HashSet::new();
+        let mut kept = 0;
+        let mut kept_bytes = 0;
+        let mut expired = Vec::new();
+
+        for file in newest_first {
+            let age = now - file.saved;
+            // Every monitor has its own newest wallpaper on screen
+            let keep = seen_outputs.insert(file.output.clone())
+                || (self.max_age.is_none_or(|max_age| age <= max_age)
+                    && self.hourly_after.is_none_or(|after| {
+                        // Per-monitor wallpapers of the same hour each keep one
+                        age <= after || !seen_hours.contains(&(file.hour(), file.output.clone()))
+                    })
+                    && self.keep_last.is_none_or(|last| kept < last)
+                    && self
+                        .max_bytes
+                        .is_none_or(|max_bytes| kept_bytes + file.size <= max_bytes));
+
+            if keep {
+                kept += 1;
+                kept_bytes += file.size;
+                seen_hours.insert((file.hour(), file.output.clone()));
+            } else {
+                expired.push(file);
+            }
+        }
+
+        expired
+    }
+}
+
+/// A wallpaper in the cache
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedFile {
+    pub path: PathBuf,
+    /// From the file name, which is the save time
+    pub saved: NaiveDateTime,
+    /// Monitor the wallpaper was made for, the part of the name after the time
+    pub output: Option<String>,
+    /// Bytes on disk, with the unprocessed original when one is kept
+    pub size: u64,
+}
+
+impl CachedFile {
+    /// Read the save time and output from a `%Y%m%d-%H%M%S[_<copy>][-<output>].<ext>` name,
+    /// `None` for anything else
+    pub fn from_path(path: &Path, size: u64) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        if !WALLPAPER_EXTENSIONS.contains(&extension.as_str()) {
+            return None;
+        }
+
+        let stem = path.file_stem()?.to_str()?;
+        let timestamp = stem.get(..15)?;
+        let saved = NaiveDateTime::parse_from_str(timestamp, "%Y%m%d-%H%M%S").ok()?;
+        let rest = match stem[15..].strip_prefix('_') {
+            Some(copy) => {
+                let digits = copy.find('-').unwrap_or(copy.len());
+                if digits == 0 || !copy[..digits].bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                &copy[digits..]
+            }
+            None => &stem[15..],
+        };
+        let output = match rest {
+            "" => None,
+            rest => Some(rest.strip_prefix('-')?.to_string()),
+        };
+
+        Some(CachedFile {
+            path: path.to_path_buf(),
+            saved,
+            output,
+            size,
+        })
+    }
+
+    fn hour(&self) -> String {
+        self.saved.format("%Y%m%d%H").to_string()
+    }
+}
+
+/// Wallpapers saved in the cache directory, oldest first
+pub fn cached_files(cache_dir: &Path) -> Result<Vec<CachedFile>> {
+    let mut files: Vec<CachedFile> = fs::read_dir(cache_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            let path = entry.path();
+            let original = fs::metadata(archive::original_path(&path)).map_or(0, |m| m.len());
+            CachedFile::from_path(&path, metadata.len() + original)
+        })
+        .collect();
+    files.sort_by(|a, b| a.saved.cmp(&b.saved).then(a.path.cmp(&b.path)));
+    Ok(files)
+}
+
+/// Delete the wallpapers the policy doesn't keep, along with their history metadata, or with
+/// `dry_run` only list them. Returns what was (or would be) deleted
+pub fn prune(
+    cache_dir: &Path,
+    policy: &RetentionPolicy,
+    now: NaiveDateTime,
+    dry_run: bool,
+) -> Result<Vec<CachedFile>> {
+    let files = cached_files(cache_dir)?;
+    let expired: Vec<CachedFile> = policy.expired(&files, now).into_iter().cloned().collect();
+
+    if !dry_run {
+        for file in &expired {
+            remove(&file.path)?;
+        }
+    }
+    Ok(expired)
+}
+
+/// Delete a cached wallpaper with its metadata sidecar and original, if it has them
+pub fn remove(path: &Path) -> Result<()> {
+    fs::remove_file(path).map_err(|e| anyhow!("Failed to delete {}: {}", path.display(), e))?;
+    for extra in [history::sidecar_path(path), archive::original_path(path)] {
+        if extra.exists() {
+            fs::remove_file(&extra)
+                .map_err(|e| anyhow!("Failed to delete {}: {}", extra.display(), e))?;
+        }
+    }
+    Ok(())
+}
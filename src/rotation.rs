@@ -1,39 +1,121 @@
-use anyhow::Result;
-use std::path::Path;
-use std::thread;
-use std::time::Duration;
+use anyhow::{anyhow, Result};
+use chrono::{Local, Utc};
+use citycam::camera::Camera;
+use citycam::health::HealthTracker;
+use citycam::playlist::{Playlist, Rotation, RotationState, Strategy};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use crate::camera::Camera;
 use crate::cli;
 use crate::image_processor;
-use crate::stream;
+use crate::monitors;
 
-pub fn start_rotation(cameras: Vec<Camera>, args: &cli::Args, cache_dir: &Path) -> Result<()> {
-    println!(
-        "Starting camera rotation with interval of {} seconds",
-        args.rotation_interval
-    );
+const STATE_FILE_NAME: &str = "rotation.json";
+const HEALTH_FILE_NAME: &str = "health.json";
 
-    let mut current_index = 0;
+/// Rotation configured from the command line, carrying on where the last run left off. The
+/// saved strategy is reused when none is given
+pub fn from_args(cameras: Vec<Camera>, args: &cli::Args, cache_dir: &Path) -> Result<Rotation> {
+    let state = RotationState::load(&state_path(cache_dir));
+    let strategy = match &args.rotation_strategy {
+        Some(spec) => Strategy::parse(spec)?,
+        None => state.strategy,
+    };
+    let playlists = args
+        .playlists
+        .iter()
+        .map(|spec| Playlist::parse(spec))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Rotation::new(cameras)
+        .with_playlists(playlists)
+        .with_state(state, strategy))
+}
+
+/// Show the next camera that isn't backing off after failures, moving straight on to the
+/// one after when it fails. Returns the name of the camera now on the wallpaper
+pub fn show_next(rotation: &mut Rotation, args: &cli::Args, cache_dir: &Path) -> Result<String> {
+    let health_path = health_path(cache_dir);
+    let mut health = HealthTracker::load(&health_path);
+    let mut tried: Vec<String> = Vec::new();
+    let mut last_error = None;
+
+    for _ in 0..rotation.cameras().len() {
+        let now = Utc::now();
+        let camera = match rotation.advance_available(Local::now().time(), &mut rand::rng(), |c| {
+            health.is_available(&c.name, now) && !tried.contains(&c.name)
+        }) {
+            Some(camera) => camera.clone(),
+            None => break,
+        };
+        if tried.contains(&camera.name) {
+            // Every camera has failed this time around
+            break;
+        }
+        tried.push(camera.name.clone());
+        if let Err(e) = rotation.state().save(&state_path(cache_dir)) {
+            eprintln!("{}", e);
+        }
 
-    loop {
-        let camera = &cameras[current_index];
         println!("Rotating to camera: {}", camera.name);
+        let started = Instant::now();
+        let captured = image_processor::capture_image(&camera, args);
 
-        match stream::get_first_frame(camera) {
-            Ok(original_image) => {
-                if let Err(e) =
-                    image_processor::process_and_set_wallpaper(original_image, args, cache_dir)
-                {
-                    eprintln!("Failed to process image for {}: {}", camera.name, e);
-                }
-            }
+        match &captured {
+            Ok(_) => health.record_success(&camera.name, started.elapsed(), Utc::now()),
             Err(e) => {
                 eprintln!("Failed to get frame from {}: {}", camera.name, e);
+                health.record_failure(&camera.name, &e.to_string(), Utc::now());
             }
         }
+        if let Err(e) = health.save(&health_path) {
+            eprintln!("{}", e);
+        }
 
-        current_index = (current_index + 1) % cameras.len();
-        thread::sleep(Duration::from_secs(args.rotation_interval));
+        match captured {
+            Ok((original_image, segment)) => {
+                let shown = if monitors::is_enabled(args) {
+                    // Monitors with an --output camera keep it, the rest rotate
+                    let captures =
+                        HashMap::from([(camera.name.clone(), (original_image, segment))]);
+                    monitors::set_wallpapers(
+                        args,
+                        rotation.cameras(),
+                        Some(&camera),
+                        captures,
+                        cache_dir,
+                    )
+                } else {
+                    image_processor::process_and_set_wallpaper(
+                        original_image,
+                        &camera,
+                        &segment,
+                        args,
+                        cache_dir,
+                    )
+                };
+                shown.inspect_err(|e| {
+                    eprintln!("Failed to process image for {}: {}", camera.name, e)
+                })?;
+                return Ok(camera.name);
+            }
+            Err(e) => last_error = Some(e),
+        }
     }
+
+    Err(last_error.unwrap_or_else(|| anyhow!("No cameras to rotate through")))
+}
+
+/// Health of the cameras, as recorded by the rotation
+pub fn load_health(cache_dir: &Path) -> HealthTracker {
+    HealthTracker::load(&health_path(cache_dir))
+}
+
+fn state_path(cache_dir: &Path) -> PathBuf {
+    cache_dir.join(STATE_FILE_NAME)
+}
+
+fn health_path(cache_dir: &Path) -> PathBuf {
+    cache_dir.join(HEALTH_FILE_NAME)
 }
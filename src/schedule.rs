@@ -0,0 +1,320 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, TimeZone, Timelike};
+
+use crate::sun::{self, SunEvent};
+
+/// When the wallpaper should change. Any number of triggers separated by `;`, the earliest
+/// one wins:
+///
+/// - `every:<duration>` (or just `<duration>`), e.g. `every:15m`
+/// - `cron:<minute> <hour> <day of month> <month> <day of week>`, e.g. `cron:*/10 7-22 * * 1-5`
+/// - `sunrise[+|-<duration>]@<lat>,<lon>` and the same for `sunset`, e.g. `sunset-30m@52.37,4.9`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    triggers: Vec<Trigger>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trigger {
+    /// Fixed interval after the previous change
+    Every(Duration),
+    Cron(CronExpr),
+    /// Offset from sunrise or sunset at a location
+    Sun {
+        event: SunEvent,
+        offset: Duration,
+        latitude: f64,
+        longitude: f64,
+    },
+}
+
+impl Schedule {
+    pub fn every(interval: Duration) -> Schedule {
+        Schedule {
+            triggers: vec![Trigger::Every(interval)],
+        }
+    }
+
+    pub fn parse(spec: &str) -> Result<Schedule> {
+        let triggers = spec
+            .split(';')
+            .map(str::trim)
+            .filter(|trigger| !trigger.is_empty())
+            .map(Trigger::parse)
+            .collect::<Result<Vec<_>>>()?;
+
+        if triggers.is_empty() {
+            return Err(anyhow!("Empty schedule"));
+        }
+
+        Ok(Schedule { triggers })
+    }
+
+    /// First time strictly after `after` that any trigger fires, `None` if none ever will
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        self.triggers
+            .iter()
+            .filter_map(|trigger| trigger.next_after(after))
+            .min()
+    }
+}
+
+impl Trigger {
+    pub fn parse(spec: &str) -> Result<Trigger> {
+        let lower = spec.to_lowercase();
+
+        if let Some(interval) = lower.strip_prefix("every:") {
+            return Ok(Trigger::Every(parse_duration(interval)?));
+        }
+        if let Some(expr) = lower.strip_prefix("cron:") {
+            return Ok(Trigger::Cron(CronExpr::parse(expr)?));
+        }
+        for (name, event) in [("sunrise", SunEvent::Sunrise), ("sunset", SunEvent::Sunset)] {
+            if let Some(rest) = lower.strip_prefix(name) {
+                return parse_sun_trigger(event, rest);
+            }
+        }
+
+        parse_duration(&lower)
+            .map(Trigger::Every)
+            .map_err(|_| anyhow!("Unknown schedule trigger: {}", spec))
+    }
+
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        match self {
+            // None once the interval runs past the end of the calendar
+            Trigger::Every(interval) => after.clone().checked_add_signed(*interval),
+            Trigger::Cron(expr) => expr.next_after(after),
+            Trigger::Sun {
+                event,
+                offset,
+                latitude,
+                longitude,
+            } => {
+                let start = after
+                    .naive_utc()
+                    .date()
+                    .checked_sub_signed(Duration::days(1))?;
+                (0..=367)
+                    .filter_map(|day| start.checked_add_signed(Duration::days(day)))
+                    .filter_map(|date| sun::sun_event(*event, date, *latitude, *longitude))
+                    .filter_map(|time| {
+                        time.with_timezone(&after.timezone())
+                            .checked_add_signed(*offset)
+                    })
+                    .find(|time| time > after)
+            }
+        }
+    }
+}
+
+// `[+|-<duration>]@<lat>,<lon>` after the sunrise/sunset keyword
+fn parse_sun_trigger(event: SunEvent, spec: &str) -> Result<Trigger> {
+    let (offset, location) = spec
+        .split_once('@')
+        .ok_or_else(|| anyhow!("Sun triggers need a location, e.g. sunrise@52.37,4.9"))?;
+
+    let offset = match offset.trim() {
+        "" => Duration::zero(),
+        offset => {
+            if let Some(duration) = offset.strip_prefix('+') {
+                parse_duration(duration)?
+            } else if let Some(duration) = offset.strip_prefix('-') {
+                -parse_duration(duration)?
+            } else {
+                return Err(anyhow!("Invalid sun offset: {}", offset));
+            }
+        }
+    };
+
+    let (latitude, longitude) = location
+        .split_once(',')
+        .and_then(|(lat, lon)| Some((lat.trim().parse().ok()?, lon.trim().parse().ok()?)))
+        .ok_or_else(|| anyhow!("Invalid location: {}", location))?;
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return Err(anyhow!("Location out of range: {}", location));
+    }
+
+    Ok(Trigger::Sun {
+        event,
+        offset,
+        latitude,
+        longitude,
+    })
+}
+
+/// Parse a duration like `90s`, `15m`, `2h`, `1d` or `1h30m`. A bare number is seconds
+pub fn parse_duration(spec: &str) -> Result<Duration> {
+    let spec = spec.trim();
+    if spec.is_empty() {
+        return Err(anyhow!("Empty duration"));
+    }
+    let too_long = || anyhow!("Duration too long: {}", spec);
+    if let Ok(seconds) = spec.parse::<u64>() {
+        return i64::try_from(seconds)
+            .ok()
+            .and_then(Duration::try_seconds)
+            .ok_or_else(too_long);
+    }
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in spec.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let value: i64 = number
+            .parse()
+            .map_err(|_| anyhow!("Invalid duration: {}", spec))?;
+        let part = match c {
+            's' => Duration::try_seconds(value),
+            'm' => Duration::try_minutes(value),
+            'h' => Duration::try_hours(value),
+            'd' => Duration::try_days(value),
+            _ => return Err(anyhow!("Invalid duration unit '{}' in {}", c, spec)),
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .ok_or_else(too_long)?;
+        number.clear();
+    }
+
+    if !number.is_empty() || total <= Duration::zero() {
+        return Err(anyhow!("Invalid duration: {}", spec));
+    }
+
+    Ok(total)
+}
+
+/// Five field cron expression: minute, hour, day of month, month, day of week (0-7, both 0
+/// and 7 are Sunday). Fields take `*`, numbers, ranges `a-b`, steps `*/n` or `a-b/n` and
+/// comma separated lists of those
+#[derive(Clone, Debug, PartialEq)]
+pub struct CronExpr {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days_of_month: Vec<bool>,
+    months: Vec<bool>,
+    days_of_week: Vec<bool>,
+    // Like cron, a day matches either day field when both are restricted
+    day_of_month_any: bool,
+    day_of_week_any: bool,
+}
+
+impl CronExpr {
+    pub fn parse(expr: &str) -> Result<CronExpr> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow!(
+                "Cron expressions need 5 fields (minute hour day month weekday): {}",
+                expr
+            ));
+        }
+
+        let mut days_of_week = parse_cron_field(fields[4], 0, 7)?;
+        if days_of_week[7] {
+            days_of_week[0] = true;
+        }
+
+        Ok(CronExpr {
+            minutes: parse_cron_field(fields[0], 0, 59)?,
+            hours: parse_cron_field(fields[1], 0, 23)?,
+            days_of_month: parse_cron_field(fields[2], 1, 31)?,
+            months: parse_cron_field(fields[3], 1, 12)?,
+            days_of_week,
+            day_of_month_any: fields[2] == "*",
+            day_of_week_any: fields[4] == "*",
+        })
+    }
+
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let timezone = after.timezone();
+        let start = after
+            .naive_local()
+            .with_second(0)?
+            .with_nanosecond(0)?
+            .checked_add_signed(Duration::minutes(1))?;
+        let limit = start
+            .checked_add_signed(Duration::days(366 * 5))
+            .unwrap_or(NaiveDateTime::MAX);
+
+        let mut time = start;
+        while time < limit {
+            if !self.matches_day(&time) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if self.hours[time.hour() as usize] && self.minutes[time.minute() as usize] {
+                // Skip times that don't exist in a daylight saving gap
+                if let Some(time) = timezone.from_local_datetime(&time).earliest() {
+                    if time > *after {
+                        return Some(time);
+                    }
+                }
+            }
+            time = time.checked_add_signed(Duration::minutes(1))?;
+        }
+
+        None
+    }
+
+    fn matches_day(&self, time: &NaiveDateTime) -> bool {
+        if !self.months[time.month() as usize] {
+            return false;
+        }
+
+        let day_of_month = self.days_of_month[time.day() as usize];
+        let day_of_week = self.days_of_week[time.weekday().num_days_from_sunday() as usize];
+        match (self.day_of_month_any, self.day_of_week_any) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+}
+
+// Values allowed by one cron field, indexed by value
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<Vec<bool>> {
+    let mut allowed = vec![false; max as usize + 1];
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|&step| step > 0)
+                    .ok_or_else(|| anyhow!("Invalid cron step: {}", part))?,
+            ),
+            None => (part, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => {
+                let parse = |value: &str| {
+                    value
+                        .parse::<u32>()
+                        .map_err(|_| anyhow!("Invalid cron value: {}", part))
+                };
+                match range.split_once('-') {
+                    Some((start, end)) => (parse(start)?, parse(end)?),
+                    // `5/15` means every 15 starting at 5
+                    None if step > 1 => (parse(range)?, max),
+                    None => (parse(range)?, parse(range)?),
+                }
+            }
+        };
+
+        if start < min || end > max || start > end {
+            return Err(anyhow!("Cron value out of range {}-{}: {}", min, max, part));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            allowed[value as usize] = true;
+        }
+    }
+
+    Ok(allowed)
+}
@@ -1,10 +1,83 @@
 use chrono::{Local, Timelike};
 use image::{GrayImage, Rgb, RgbImage};
 
+/// Sky colors straight up and at the horizon, blended top to bottom over the sky
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SkyPalette {
+    pub zenith: Rgb<u8>,
+    pub horizon: Rgb<u8>,
+}
+
+impl SkyPalette {
+    /// The same color all the way down
+    pub fn uniform(color: Rgb<u8>) -> Self {
+        SkyPalette {
+            zenith: color,
+            horizon: color,
+        }
+    }
+
+    /// Color `fraction` of the way from the zenith (0.0) down to the horizon (1.0)
+    pub fn at(&self, fraction: f32) -> Rgb<u8> {
+        lerp_color(self.zenith, self.horizon, fraction.clamp(0.0, 1.0))
+    }
+}
+
+// Palettes by sun elevation in degrees, in the same muted tones as the hourly colors. Night
+// through the twilights to the warm horizon of sunrise and golden hour, then daylight blue
+const SUN_PALETTES: &[(f64, [u8; 3], [u8; 3])] = &[
+    (-18.0, [20, 24, 45], [40, 45, 70]),
+    (-12.0, [28, 34, 66], [62, 60, 96]),
+    (-6.0, [52, 62, 104], [150, 110, 120]),
+    (-0.833, [96, 110, 150], [215, 140, 100]),
+    (6.0, [140, 165, 200], [225, 185, 140]),
+    (20.0, [150, 180, 215], [190, 205, 220]),
+];
+
+/// Sky for the sun at `elevation` degrees, blending smoothly between night, the civil,
+/// nautical and astronomical twilights, golden hour and day
+pub fn sky_palette_for_sun(elevation: f64) -> SkyPalette {
+    let palette = |(_, zenith, horizon): (f64, [u8; 3], [u8; 3])| SkyPalette {
+        zenith: Rgb(zenith),
+        horizon: Rgb(horizon),
+    };
+
+    let first = SUN_PALETTES[0];
+    let last = SUN_PALETTES[SUN_PALETTES.len() - 1];
+    if elevation <= first.0 {
+        return palette(first);
+    }
+    if elevation >= last.0 {
+        return palette(last);
+    }
+
+    let (below, above) = SUN_PALETTES
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|(_, above)| elevation < above.0)
+        .expect("elevation is within the table");
+    let fraction = ((elevation - below.0) / (above.0 - below.0)) as f32;
+    let (below, above) = (palette(below), palette(above));
+
+    SkyPalette {
+        zenith: lerp_color(below.zenith, above.zenith, fraction),
+        horizon: lerp_color(below.horizon, above.horizon, fraction),
+    }
+}
+
+fn lerp_color(from: Rgb<u8>, to: Rgb<u8>, fraction: f32) -> Rgb<u8> {
+    let channel =
+        |i: usize| (from.0[i] as f32 + (to.0[i] as f32 - from.0[i] as f32) * fraction).round();
+    Rgb([channel(0) as u8, channel(1) as u8, channel(2) as u8])
+}
+
+/// Sky color by the host's clock, for when there is no location to place the sun
 pub fn get_sky_color_for_time() -> Rgb<u8> {
-    let now = Local::now();
-    let hour = now.hour();
+    sky_color_for_hour(Local::now().hour())
+}
 
+/// Coarse sky color for an hour of the day
+pub fn sky_color_for_hour(hour: u32) -> Rgb<u8> {
     match hour {
         // Night (10 PM - 5 AM)
         22..=23 | 0..=4 => Rgb([40, 45, 70]), // Muted dark blue for historical night sky
@@ -121,12 +194,27 @@ fn smooth_sky_boundary(sky_mask: &mut Vec<Vec<bool>>) {
     }
 }
 
+/// Clear the sky below the horizon, `horizon` being how far down the frame it sits from 0.0
+/// (top) to 1.0 (bottom). Bright water or pavement further down then keeps its color
+pub fn limit_sky_to_horizon(sky_mask: &mut [Vec<bool>], horizon: f32) {
+    let first_row = (horizon.clamp(0.0, 1.0) * sky_mask.len() as f32).round() as usize;
+    for row in sky_mask.iter_mut().skip(first_row) {
+        row.fill(false);
+    }
+}
+
 // Apply a gradient blend at sky boundary for smoother transition
 pub fn apply_sky_color_with_gradient(
     img: &RgbImage,
     sky_mask: &Vec<Vec<bool>>,
     sky_color: Rgb<u8>,
 ) -> RgbImage {
+    apply_sky_palette(img, sky_mask, &SkyPalette::uniform(sky_color))
+}
+
+/// Color the sky from the palette's zenith at the top of the frame to its horizon color at
+/// the bottom of the sky in each column, blending into the rest of the frame at the edge
+pub fn apply_sky_palette(img: &RgbImage, sky_mask: &[Vec<bool>], palette: &SkyPalette) -> RgbImage {
     let width = img.width();
     let height = img.height();
     let mut result = img.clone();
@@ -134,6 +222,7 @@ pub fn apply_sky_color_with_gradient(
     // Find transition zone (pixels near boundary)
     let transition_width = 5;
     let mut transition_mask = vec![vec![0.0; width as usize]; height as usize];
+    let mut sky_bottoms = vec![0; width as usize];
 
     for x in 0..width as usize {
         // Find sky boundary for this column
@@ -143,6 +232,7 @@ pub fn apply_sky_color_with_gradient(
                 sky_bottom = y;
             }
         }
+        sky_bottoms[x] = sky_bottom;
 
         // Mark transition zone
         for y in 0..height as usize {
@@ -164,6 +254,8 @@ pub fn apply_sky_color_with_gradient(
 
             if blend_factor > 0.0 {
                 let original = img.get_pixel(x, y).0;
+                let sky_bottom = sky_bottoms[x as usize].max(1);
+                let sky_color = palette.at(y as f32 / sky_bottom as f32);
 
                 // Blend original with sky color
                 let r = (sky_color.0[0] as f32 * blend_factor
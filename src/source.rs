@@ -0,0 +1,455 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use image::RgbImage;
+use regex::Regex;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::camera::{Camera, CameraKind};
+use crate::image_processing;
+use crate::stream::{self, Capture, FrameSelection, SegmentInfo, StreamOptions};
+
+/// Give up on an MJPEG stream that goes this many bytes without a complete frame
+const MJPEG_PART_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Where the headers of a multipart part have to end by
+const MULTIPART_HEADER_LIMIT: usize = 16 * 1024;
+
+/// How long to wait for an MJPEG camera to answer
+const MJPEG_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long an MJPEG stream may go quiet before it counts as stalled
+const MJPEG_READ_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Somewhere frames can be grabbed from
+pub trait CameraSource {
+    /// Grab frames for a `Spread` or `At` selection, `Best` is resolved by [`capture`]
+    fn frames(&self, options: &StreamOptions, selection: FrameSelection) -> Result<Capture>;
+}
+
+pub fn source_for(camera: &Camera) -> Box<dyn CameraSource> {
+    let url = camera.url.clone();
+    match camera.kind {
+        CameraKind::Wetmet => Box::new(WetmetPage { url }),
+        CameraKind::Hls => Box::new(HlsStream { url }),
+        CameraKind::Snapshot => Box::new(Snapshot { url }),
+        CameraKind::Mjpeg => Box::new(Mjpeg { url }),
+        CameraKind::File => Box::new(VideoFile {
+            path: PathBuf::from(url),
+        }),
+        CameraKind::Directory => Box::new(ImageDirectory {
+            path: PathBuf::from(url),
+        }),
+    }
+}
+
+pub fn capture(
+    camera: &Camera,
+    options: &StreamOptions,
+    selection: FrameSelection,
+) -> Result<Capture> {
+    let source = source_for(camera);
+
+    match selection {
+        FrameSelection::Best(count) => {
+            let mut capture = source.frames(options, FrameSelection::Spread(count.max(1)))?;
+            let best = image_processing::pick_best_frame(std::mem::take(&mut capture.frames))
+                .ok_or_else(|| anyhow!("No frames decoded"))?;
+            capture.frames = vec![best];
+            Ok(capture)
+        }
+        selection => source.frames(options, selection),
+    }
+}
+
+/// wetmet.net frame.php widget page with the HLS URL in its javascript
+pub struct WetmetPage {
+    pub url: String,
+}
+
+impl CameraSource for WetmetPage {
+    fn frames(&self, options: &StreamOptions, selection: FrameSelection) -> Result<Capture> {
+        let response = reqwest::blocking::get(&self.url)?.text()?;
+
+        let re = Regex::new(r"var vurl = '(https://[^']+)'")?;
+        let m3u8_url = match re.captures(&response) {
+            Some(captures) => captures[1].to_string(),
+            None => {
+                return Err(anyhow!(
+                    "Could not find stream URL in the frame.php response"
+                ))
+            }
+        };
+
+        HlsStream { url: m3u8_url }.frames(options, selection)
+    }
+}
+
+/// Direct link to, or local path of, an HLS master or media playlist
+pub struct HlsStream {
+    pub url: String,
+}
+
+impl CameraSource for HlsStream {
+    fn frames(&self, options: &StreamOptions, selection: FrameSelection) -> Result<Capture> {
+        stream::init()?;
+
+        let (segment_data, segment) = stream::fetch_segment(&self.url, options)?;
+        let frames = stream::decode_frames(&segment_data, selection)?;
+
+        Ok(Capture { frames, segment })
+    }
+}
+
+/// Still image webcam that refreshes a JPEG (or PNG) at a fixed URL. Always gives one frame
+pub struct Snapshot {
+    pub url: String,
+}
+
+impl CameraSource for Snapshot {
+    fn frames(&self, _options: &StreamOptions, _selection: FrameSelection) -> Result<Capture> {
+        let response = reqwest::blocking::get(&self.url)?.error_for_status()?;
+
+        let timestamp = response
+            .headers()
+            .get(reqwest::header::LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .map(|t| t.with_timezone(&Utc));
+
+        let bytes = response.bytes()?;
+        let image = image::load_from_memory(&bytes)?.to_rgb8();
+
+        Ok(Capture {
+            frames: vec![image],
+            segment: SegmentInfo {
+                uri: Some(self.url.clone()),
+                timestamp,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// Motion JPEG over HTTP (multipart/x-mixed-replace), frames are the parts between boundaries
+pub struct Mjpeg {
+    pub url: String,
+}
+
+impl CameraSource for Mjpeg {
+    fn frames(&self, _options: &StreamOptions, selection: FrameSelection) -> Result<Capture> {
+        // The timeout is for each read, so a live stream can be followed for as long as the
+        // offset asks while one that stalls is given up on
+        let client = reqwest::blocking::Client::builder()
+            .connect_timeout(MJPEG_CONNECT_TIMEOUT)
+            .timeout(MJPEG_READ_TIMEOUT)
+            .build()?;
+        let response = client.get(&self.url).send()?.error_for_status()?;
+        let boundary = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(multipart_boundary);
+
+        // The stream is live, so an offset means waiting that long before keeping a frame
+        let (wanted, skip_for) = match selection {
+            FrameSelection::Spread(count) | FrameSelection::Best(count) => (count.max(1), 0.0),
+            FrameSelection::At(offset) => (1, offset),
+        };
+
+        let frames = read_mjpeg(response, boundary.as_deref(), wanted, skip_for)?;
+
+        Ok(Capture {
+            frames,
+            segment: SegmentInfo {
+                uri: Some(self.url.clone()),
+                timestamp: Some(Utc::now()),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// The `boundary` parameter of a multipart Content-Type, without quotes
+pub fn multipart_boundary(content_type: &str) -> Option<String> {
+    content_type.split(';').find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("boundary") {
+            return None;
+        }
+        let value = value.trim().trim_matches('"');
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// Decode `wanted` frames from a multipart MJPEG body, leaving out those that arrive in the
+/// first `skip_for` seconds. The boundary is read from the body when the Content-Type had
+/// none. Parts that don't decode are skipped, and fewer frames come back when the stream ends
+/// early
+pub fn read_mjpeg(
+    mut reader: impl Read,
+    boundary: Option<&str>,
+    wanted: usize,
+    skip_for: f64,
+) -> Result<Vec<RgbImage>> {
+    let started = Instant::now();
+    let mut frames: Vec<RgbImage> = Vec::with_capacity(wanted);
+    let mut parts = MultipartParts::new(boundary);
+    let mut chunk = [0u8; 64 * 1024];
+    let mut undecodable = 0;
+
+    while frames.len() < wanted {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        parts.extend(&chunk[..read]);
+
+        while let Some(part) = parts.take()? {
+            if started.elapsed().as_secs_f64() < skip_for || frames.len() >= wanted {
+                continue;
+            }
+            match image::load_from_memory(&part) {
+                Ok(frame) => frames.push(frame.to_rgb8()),
+                Err(_) => undecodable += 1,
+            }
+        }
+        // Bytes read since the last complete part
+        if parts.buffer.len() > MJPEG_PART_LIMIT {
+            return Err(anyhow!(
+                "No complete frame in {} bytes of the MJPEG stream",
+                MJPEG_PART_LIMIT
+            ));
+        }
+    }
+
+    if frames.is_empty() {
+        if undecodable > 0 {
+            return Err(anyhow!(
+                "None of the {} frames from the MJPEG stream could be decoded",
+                undecodable
+            ));
+        }
+        return Err(anyhow!("MJPEG stream ended before a full frame arrived"));
+    }
+    Ok(frames)
+}
+
+/// Cuts the parts of a multipart body out as it arrives, by their Content-Length when they
+/// have one and at the next boundary otherwise. Frame data may contain anything, a JPEG end
+/// marker included, so the boundary is all that can be trusted
+struct MultipartParts {
+    buffer: Vec<u8>,
+    /// `--` and the boundary, once known
+    delimiter: Option<Vec<u8>>,
+    // Where to carry on looking for the delimiter that ends the part at the start of the buffer
+    searched: usize,
+}
+
+impl MultipartParts {
+    fn new(boundary: Option<&str>) -> Self {
+        MultipartParts {
+            buffer: Vec::new(),
+            // Some servers put the dashes in the header's boundary too
+            delimiter: boundary.map(|b| format!("--{}", b.trim_start_matches("--")).into_bytes()),
+            searched: 0,
+        }
+    }
+
+    fn extend(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    // Remove and return the body of the first complete part
+    fn take(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.delimiter.is_none() {
+            // Without a Content-Type boundary the body's first line is the delimiter
+            let blank = self
+                .buffer
+                .iter()
+                .take_while(|b| b.is_ascii_whitespace())
+                .count();
+            self.buffer.drain(..blank);
+            let Some(line_end) = self.buffer.iter().position(|&b| b == b'\n') else {
+                return Ok(None);
+            };
+            let line = String::from_utf8_lossy(&self.buffer[..line_end]);
+            let line = line.trim();
+            if !line.starts_with("--") || line.len() == 2 {
+                return Err(anyhow!("MJPEG stream isn't multipart, it has no boundary"));
+            }
+            self.delimiter = Some(line.as_bytes().to_vec());
+        }
+        let delimiter = self.delimiter.clone().unwrap_or_default();
+        let delimiter = delimiter.as_slice();
+
+        // Everything up to the first delimiter is a preamble or the end of a part cut short
+        let Some(start) = find(&self.buffer, delimiter, 0) else {
+            // Keep what could be the start of a delimiter
+            let keep = delimiter.len().saturating_sub(1);
+            self.buffer.drain(..self.buffer.len().saturating_sub(keep));
+            self.searched = 0;
+            return Ok(None);
+        };
+        if start > 0 {
+            self.buffer.drain(..start);
+            self.searched = 0;
+        }
+
+        // Headers end at a blank line, which some servers break with a bare \n
+        let headers = &self.buffer[..self.buffer.len().min(MULTIPART_HEADER_LIMIT)];
+        let crlf = find(headers, b"\r\n\r\n", 0).map(|i| i + 4);
+        let lf = find(headers, b"\n\n", 0).map(|i| i + 2);
+        let Some(headers_end) = crlf.into_iter().chain(lf).min() else {
+            if headers.len() == MULTIPART_HEADER_LIMIT {
+                return Err(anyhow!(
+                    "MJPEG part headers run past {} bytes",
+                    headers.len()
+                ));
+            }
+            return Ok(None);
+        };
+        let headers = String::from_utf8_lossy(&self.buffer[..headers_end]);
+        let length = headers.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            if !name.trim().eq_ignore_ascii_case("content-length") {
+                return None;
+            }
+            value.trim().parse::<usize>().ok()
+        });
+
+        match length {
+            Some(length) if length > MJPEG_PART_LIMIT => Err(anyhow!(
+                "MJPEG frame of {} bytes is over the {} byte limit",
+                length,
+                MJPEG_PART_LIMIT
+            )),
+            Some(length) => {
+                if self.buffer.len() < headers_end + length {
+                    return Ok(None);
+                }
+                let part = self.buffer[headers_end..headers_end + length].to_vec();
+                self.buffer.drain(..headers_end + length);
+                self.searched = 0;
+                Ok(Some(part))
+            }
+            None => {
+                let from = self.searched.max(headers_end);
+                let Some(end) = find(&self.buffer, delimiter, from) else {
+                    self.searched = self
+                        .buffer
+                        .len()
+                        .saturating_sub(delimiter.len() - 1)
+                        .max(headers_end);
+                    return Ok(None);
+                };
+                // The line break before the delimiter belongs to it
+                let mut body_end = end;
+                if self.buffer[..body_end].ends_with(b"\n") {
+                    body_end -= 1;
+                }
+                if self.buffer[..body_end].ends_with(b"\r") {
+                    body_end -= 1;
+                }
+                let part = self.buffer[headers_end..body_end.max(headers_end)].to_vec();
+                self.buffer.drain(..end);
+                self.searched = 0;
+                Ok(Some(part))
+            }
+        }
+    }
+}
+
+// Where `needle` first appears in `haystack` at or after `from`
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|i| from + i)
+}
+
+/// Video file on disk in any container ffmpeg can read
+pub struct VideoFile {
+    pub path: PathBuf,
+}
+
+impl CameraSource for VideoFile {
+    fn frames(&self, _options: &StreamOptions, selection: FrameSelection) -> Result<Capture> {
+        stream::init()?;
+
+        let frames = stream::decode_file(&self.path, selection)?;
+        let timestamp = std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok()
+            .map(DateTime::<Utc>::from);
+
+        Ok(Capture {
+            frames,
+            segment: SegmentInfo {
+                uri: Some(self.path.display().to_string()),
+                timestamp,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// Directory of still images, e.g. an earlier capture run. Frames are the newest images by
+/// file name, so `Spread(n)` gives the last `n` images oldest first
+pub struct ImageDirectory {
+    pub path: PathBuf,
+}
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "webp", "bmp"];
+
+impl CameraSource for ImageDirectory {
+    fn frames(&self, _options: &StreamOptions, selection: FrameSelection) -> Result<Capture> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&self.path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", self.path.display(), e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.extension().is_some_and(|ext| {
+                    IMAGE_EXTENSIONS
+                        .iter()
+                        .any(|known| ext.eq_ignore_ascii_case(known))
+                })
+            })
+            .collect();
+        paths.sort();
+
+        if paths.is_empty() {
+            return Err(anyhow!("No images found in {}", self.path.display()));
+        }
+
+        let wanted = match selection {
+            FrameSelection::Spread(count) | FrameSelection::Best(count) => count.max(1),
+            FrameSelection::At(_) => 1,
+        };
+        let chosen = &paths[paths.len().saturating_sub(wanted)..];
+
+        let frames = chosen
+            .iter()
+            .map(|path| {
+                image::open(path)
+                    .map(|img| img.to_rgb8())
+                    .map_err(|e| anyhow!("Failed to load {}: {}", path.display(), e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let timestamp = chosen
+            .last()
+            .and_then(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .map(DateTime::<Utc>::from);
+
+        Ok(Capture {
+            frames,
+            segment: SegmentInfo {
+                uri: chosen.last().map(|path| path.display().to_string()),
+                timestamp,
+                ..Default::default()
+            },
+        })
+    }
+}
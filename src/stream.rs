@@ -1,62 +1,192 @@
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
 use ffmpeg_next as ffmpeg;
 use image::RgbImage;
-use m3u8_rs::Playlist;
-use regex::Regex;
+use m3u8_rs::{MediaPlaylist, Playlist};
+use std::fs;
 use std::io::Cursor;
+use std::path::Path;
 
-use crate::camera::Camera;
+/// Which decoded frames of a segment to keep
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FrameSelection {
+    /// `count` frames spread evenly across the segment, oldest first
+    Spread(usize),
+    /// The first frame at or after this many seconds into the segment
+    At(f64),
+    /// The sharpest, best exposed of `count` frames spread across the segment
+    Best(usize),
+}
 
-pub fn get_first_frame(camera: &Camera) -> Result<RgbImage> {
-    ffmpeg::init()?;
-    ffmpeg::log::set_level(ffmpeg::log::Level::Error);
+/// Which segment of a live media playlist to fetch
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum SegmentSelection {
+    /// Newest segment, closest to what the camera sees now
+    #[default]
+    Latest,
+    /// Oldest segment still in the sliding window
+    First,
+    /// Segment by index, negative values count back from the newest
+    Nth(i64),
+    /// Segment whose EXT-X-PROGRAM-DATE-TIME is closest to the current time
+    Closest,
+}
 
-    let m3u8_url = get_current_stream_url(&camera.url)?;
-    let segment_data = fetch_first_segment(&m3u8_url)?;
+impl SegmentSelection {
+    /// Parse `latest`, `first`, `closest` or `nth:<index>`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim().to_lowercase();
+        match spec.split_once(':') {
+            Some(("nth", index)) => {
+                Ok(SegmentSelection::Nth(index.trim().parse().map_err(
+                    |_| anyhow!("Invalid segment index: {}", index),
+                )?))
+            }
+            None if spec == "latest" => Ok(SegmentSelection::Latest),
+            None if spec == "first" => Ok(SegmentSelection::First),
+            None if spec == "closest" => Ok(SegmentSelection::Closest),
+            _ => Err(anyhow!("Unknown segment selection: {}", spec)),
+        }
+    }
+}
 
-    decode_first_frame(&segment_data)
+/// Which rendition of a master playlist to follow
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum VariantSelection {
+    /// Largest resolution, then highest bandwidth
+    #[default]
+    Highest,
+    /// First variant listed, usually what the publisher considers the default
+    First,
+    /// Variant closest in size to a target resolution such as the monitor's
+    Closest { width: u64, height: u64 },
+    /// Best variant whose bandwidth is at most this many bits per second
+    MaxBandwidth(u64),
+    /// Variant by zero based position in the playlist
+    Index(usize),
 }
 
-fn get_current_stream_url(frame_url: &str) -> Result<String> {
-    let response = reqwest::blocking::get(frame_url)?.text()?;
+impl VariantSelection {
+    /// Parse `highest`, `first`, `closest:<width>x<height>`, `max-bandwidth:<bps>` or
+    /// `index:<n>`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim().to_lowercase();
+        match spec.split_once(':') {
+            Some(("closest", size)) => {
+                let (width, height) = size
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+                    .ok_or_else(|| anyhow!("Invalid resolution: {}", size))?;
+                Ok(VariantSelection::Closest { width, height })
+            }
+            Some(("max-bandwidth", bandwidth)) => Ok(VariantSelection::MaxBandwidth(
+                bandwidth
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid bandwidth: {}", bandwidth))?,
+            )),
+            Some(("index", index)) => {
+                Ok(VariantSelection::Index(index.trim().parse().map_err(
+                    |_| anyhow!("Invalid variant index: {}", index),
+                )?))
+            }
+            None if spec == "highest" => Ok(VariantSelection::Highest),
+            None if spec == "first" => Ok(VariantSelection::First),
+            _ => Err(anyhow!("Unknown variant selection: {}", spec)),
+        }
+    }
+}
 
-    let re = Regex::new(r"var vurl = '(https://[^']+)'")?;
-    if let Some(captures) = re.captures(&response) {
-        Ok(captures[1].to_string())
-    } else {
-        Err(anyhow!(
-            "Could not find stream URL in the frame.php response"
-        ))
+/// Attributes of one master playlist variant
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VariantInfo {
+    pub uri: String,
+    pub bandwidth: u64,
+    pub resolution: Option<(u64, u64)>,
+    pub codecs: Option<String>,
+}
+
+impl std::fmt::Display for VariantInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.resolution {
+            Some((width, height)) => write!(f, "{}x{}", width, height)?,
+            None => write!(f, "unknown resolution")?,
+        }
+        write!(f, ", {} kbps", self.bandwidth / 1000)?;
+        if let Some(codecs) = &self.codecs {
+            write!(f, ", {}", codecs)?;
+        }
+        Ok(())
     }
 }
 
-fn fetch_first_segment(m3u8_url: &str) -> Result<Vec<u8>> {
-    let response = reqwest::blocking::get(m3u8_url)?.text()?;
+/// How to pick a stream and segment to decode
+#[derive(Clone, Debug, Default)]
+pub struct StreamOptions {
+    pub segment: SegmentSelection,
+    pub variant: VariantSelection,
+}
 
-    let base_url = m3u8_url
-        .rsplit_once('/')
-        .map(|(base, _)| format!("{}/", base))
-        .unwrap_or_default();
+/// The segment, snapshot or file a capture was decoded from
+#[derive(Clone, Debug, Default)]
+pub struct SegmentInfo {
+    /// Segment, snapshot or file the frames were decoded from
+    pub uri: Option<String>,
+    /// Wall-clock time the frames were recorded, e.g. from EXT-X-PROGRAM-DATE-TIME, when the
+    /// source tells us
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Variant followed when the stream had a master playlist
+    pub variant: Option<VariantInfo>,
+    /// Every variant the master playlist offered
+    pub available_variants: Vec<VariantInfo>,
+}
+
+/// Frames decoded from one segment, oldest first
+pub struct Capture {
+    pub frames: Vec<RgbImage>,
+    pub segment: SegmentInfo,
+}
+
+pub fn init() -> Result<()> {
+    ffmpeg::init()?;
+    ffmpeg::log::set_level(ffmpeg::log::Level::Error);
+    Ok(())
+}
+
+/// Fetch one segment of an HLS stream, following a master playlist to the selected variant.
+/// The playlist may be a URL or a local path
+pub fn fetch_segment(m3u8_url: &str, options: &StreamOptions) -> Result<(Vec<u8>, SegmentInfo)> {
+    let response = String::from_utf8(read_location(m3u8_url)?)?;
 
     let playlist = m3u8_rs::parse_playlist_res(response.as_bytes())
         .map_err(|e| anyhow!("Failed to parse m3u8: {:?}", e))?;
 
+    let mut info = SegmentInfo::default();
+
     let chunks_playlist_url = match playlist {
         Playlist::MasterPlaylist(master) => {
-            let variant = master
+            info.available_variants = master
                 .variants
-                .first()
-                .ok_or_else(|| anyhow!("No variants in master playlist"))?;
-            format!("{}{}", base_url, variant.uri)
+                .iter()
+                .filter(|v| !v.is_i_frame)
+                .map(|v| VariantInfo {
+                    uri: v.uri.clone(),
+                    bandwidth: v.bandwidth,
+                    resolution: v.resolution.as_ref().map(|r| (r.width, r.height)),
+                    codecs: v.codecs.clone(),
+                })
+                .collect();
+
+            let index = select_variant(&info.available_variants, options.variant)?;
+            let variant = info.available_variants[index].clone();
+            let url = resolve_uri(m3u8_url, &variant.uri);
+            info.variant = Some(variant);
+            url
         }
         Playlist::MediaPlaylist(_) => m3u8_url.to_string(),
     };
 
-    let chunks_response = reqwest::blocking::get(&chunks_playlist_url)?.text()?;
-    let chunks_base_url = chunks_playlist_url
-        .rsplit_once('/')
-        .map(|(base, _)| format!("{}/", base))
-        .unwrap_or_default();
+    let chunks_response = String::from_utf8(read_location(&chunks_playlist_url)?)?;
 
     let media_playlist = match m3u8_rs::parse_playlist_res(chunks_response.as_bytes())
         .map_err(|e| anyhow!("Failed to parse media playlist: {:?}", e))?
@@ -65,28 +195,186 @@ fn fetch_first_segment(m3u8_url: &str) -> Result<Vec<u8>> {
         _ => return Err(anyhow!("Expected media playlist")),
     };
 
-    let segment = media_playlist
-        .segments
-        .first()
-        .ok_or_else(|| anyhow!("No segments in playlist"))?;
+    let (index, timestamp) = select_segment(&media_playlist, options.segment)?;
+    let segment = &media_playlist.segments[index];
+
+    let segment_url = resolve_uri(&chunks_playlist_url, &segment.uri);
+    let segment_data = read_location(&segment_url)?;
+
+    info.timestamp = timestamp;
+    info.uri = Some(segment_url);
+
+    Ok((segment_data, info))
+}
+
+/// Index of the variant to follow, see [`VariantSelection`]
+pub fn select_variant(variants: &[VariantInfo], selection: VariantSelection) -> Result<usize> {
+    if variants.is_empty() {
+        return Err(anyhow!("No variants in master playlist"));
+    }
 
-    let segment_url = format!("{}{}", chunks_base_url, segment.uri);
-    let segment_data = reqwest::blocking::get(&segment_url)?.bytes()?.to_vec();
+    let area = |v: &VariantInfo| v.resolution.map(|(w, h)| w * h).unwrap_or(0);
+    let by_quality = |a: &(usize, &VariantInfo), b: &(usize, &VariantInfo)| {
+        (area(a.1), a.1.bandwidth).cmp(&(area(b.1), b.1.bandwidth))
+    };
 
-    Ok(segment_data)
+    let index = match selection {
+        VariantSelection::First => 0,
+        VariantSelection::Highest => variants
+            .iter()
+            .enumerate()
+            .max_by(by_quality)
+            .map(|(i, _)| i)
+            .unwrap_or(0),
+        VariantSelection::Closest { width, height } => {
+            let target = width * height;
+            variants
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| v.resolution.is_some())
+                .min_by_key(|(_, v)| (area(v).abs_diff(target), u64::MAX - v.bandwidth))
+                .map(|(i, _)| i)
+                .unwrap_or(0)
+        }
+        VariantSelection::MaxBandwidth(cap) => variants
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.bandwidth <= cap)
+            .max_by(by_quality)
+            .or_else(|| variants.iter().enumerate().min_by_key(|(_, v)| v.bandwidth))
+            .map(|(i, _)| i)
+            .unwrap_or(0),
+        VariantSelection::Index(index) => {
+            if index >= variants.len() {
+                return Err(anyhow!(
+                    "Variant index {} out of range, playlist has {} variants",
+                    index,
+                    variants.len()
+                ));
+            }
+            index
+        }
+    };
+
+    Ok(index)
 }
 
-fn decode_first_frame(segment_data: &[u8]) -> Result<RgbImage> {
+/// Where a URI in the playlist at `playlist` points. URLs and absolute paths stand as they
+/// are, anything else is relative to the playlist's directory or URL
+pub fn resolve_uri(playlist: &str, uri: &str) -> String {
+    if is_url(uri) {
+        return uri.to_string();
+    }
+    if is_url(playlist) {
+        return match reqwest::Url::parse(playlist).and_then(|base| base.join(uri)) {
+            Ok(url) => url.to_string(),
+            Err(_) => uri.to_string(),
+        };
+    }
+    if Path::new(uri).is_absolute() {
+        return uri.to_string();
+    }
+    let directory = Path::new(playlist).parent().unwrap_or(Path::new(""));
+    directory.join(uri).display().to_string()
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+fn read_location(location: &str) -> Result<Vec<u8>> {
+    if is_url(location) {
+        Ok(reqwest::blocking::get(location)?.bytes()?.to_vec())
+    } else {
+        fs::read(location).map_err(|e| anyhow!("Failed to read {}: {}", location, e))
+    }
+}
+
+/// Index of the segment to fetch, see [`SegmentSelection`], and when it was recorded if the
+/// playlist has program date times
+pub fn select_segment(
+    playlist: &MediaPlaylist,
+    selection: SegmentSelection,
+) -> Result<(usize, Option<DateTime<Utc>>)> {
+    let count = playlist.segments.len();
+    if count == 0 {
+        return Err(anyhow!("No segments in playlist"));
+    }
+
+    let timestamps = segment_timestamps(playlist);
+
+    let index = match selection {
+        SegmentSelection::Latest => count - 1,
+        SegmentSelection::First => 0,
+        SegmentSelection::Nth(n) => {
+            let index = if n < 0 { count as i64 + n } else { n };
+            if index < 0 || index >= count as i64 {
+                return Err(anyhow!(
+                    "Segment index {} out of range, playlist has {} segments",
+                    n,
+                    count
+                ));
+            }
+            index as usize
+        }
+        SegmentSelection::Closest => {
+            let now = Utc::now();
+            timestamps
+                .iter()
+                .enumerate()
+                .filter_map(|(i, t)| t.map(|t| (i, (now - t).num_milliseconds().abs())))
+                .min_by_key(|&(_, distance)| distance)
+                .map(|(i, _)| i)
+                // Without program date times the newest segment is the closest guess
+                .unwrap_or(count - 1)
+        }
+    };
+
+    Ok((index, timestamps[index]))
+}
+
+// A program date time applies to its segment, later segments follow on by their durations
+fn segment_timestamps(playlist: &MediaPlaylist) -> Vec<Option<DateTime<Utc>>> {
+    let mut current: Option<DateTime<Utc>> = None;
+    let mut timestamps = Vec::with_capacity(playlist.segments.len());
+
+    for segment in &playlist.segments {
+        if let Some(program_date_time) = segment.program_date_time {
+            current = Some(program_date_time.with_timezone(&Utc));
+        }
+        timestamps.push(current);
+        current =
+            current.map(|t| t + chrono::Duration::milliseconds((segment.duration * 1000.0) as i64));
+    }
+
+    timestamps
+}
+
+pub fn decode_frames(segment_data: &[u8], selection: FrameSelection) -> Result<Vec<RgbImage>> {
     let mut temp_file = tempfile::NamedTempFile::new()?;
     std::io::copy(&mut Cursor::new(segment_data), &mut temp_file)?;
-    let temp_path = temp_file.path();
 
-    let mut input_ctx = ffmpeg::format::input(temp_path)?;
+    decode_file(temp_file.path(), selection)
+}
+
+/// Decode frames from any video file ffmpeg can read. `Best` is treated like `Spread`
+pub fn decode_file(path: &Path, selection: FrameSelection) -> Result<Vec<RgbImage>> {
+    // Count packets up front so the wanted frames can be spread over the whole segment
+    let (step, wanted) = match selection {
+        FrameSelection::Spread(count) if count > 1 => {
+            ((count_video_packets(path)? / count).max(1), count)
+        }
+        _ => (1, 1),
+    };
+
+    let mut input_ctx = ffmpeg::format::input(path)?;
     let input_stream = input_ctx
         .streams()
         .best(ffmpeg::media::Type::Video)
         .ok_or_else(|| anyhow!("No video stream found"))?;
     let stream_index = input_stream.index();
+    let time_base = f64::from(input_stream.time_base());
+    let frame_rate = f64::from(input_stream.avg_frame_rate());
 
     let mut decoder = ffmpeg::codec::context::Context::from_parameters(input_stream.parameters())?
         .decoder()
@@ -103,29 +391,91 @@ fn decode_first_frame(segment_data: &[u8]) -> Result<RgbImage> {
     )?;
 
     let mut frame = ffmpeg::frame::Video::empty();
+    let mut frames = Vec::with_capacity(wanted);
+    let mut frame_index = 0;
+    let mut first_timestamp = None;
+    let mut seconds = 0.0;
+
+    // A trailing `None` flushes the frames still buffered in the decoder
+    let packets = input_ctx
+        .packets()
+        .filter(|(stream, _)| stream.index() == stream_index)
+        .map(|(_, packet)| Some(packet))
+        .chain(std::iter::once(None));
+
+    for packet in packets {
+        match &packet {
+            Some(packet) => decoder.send_packet(packet)?,
+            None => decoder.send_eof()?,
+        }
+
+        while decoder.receive_frame(&mut frame).is_ok() {
+            seconds = match frame.timestamp().or(frame.pts()) {
+                Some(timestamp) => {
+                    let first = *first_timestamp.get_or_insert(timestamp);
+                    (timestamp - first) as f64 * time_base
+                }
+                None if frame_rate.is_finite() && frame_rate > 0.0 => {
+                    frame_index as f64 / frame_rate
+                }
+                None => seconds,
+            };
+
+            let keep = match selection {
+                FrameSelection::Spread(_) | FrameSelection::Best(_) => frame_index % step == 0,
+                FrameSelection::At(offset) => seconds >= offset,
+            };
 
-    for (stream, packet) in input_ctx.packets() {
-        if stream.index() == stream_index {
-            decoder.send_packet(&packet)?;
-            if decoder.receive_frame(&mut frame).is_ok() {
-                let mut rgb_frame = ffmpeg::frame::Video::new(
-                    ffmpeg::format::Pixel::RGB24,
-                    frame.width(),
-                    frame.height(),
-                );
-                scaler.run(&frame, &mut rgb_frame)?;
-
-                let width = rgb_frame.width() as u32;
-                let height = rgb_frame.height() as u32;
-                let data = rgb_frame.data(0).to_vec();
-
-                let img = RgbImage::from_raw(width, height, data)
-                    .ok_or_else(|| anyhow!("Failed to create image from raw data"))?;
-
-                return Ok(img);
+            if keep {
+                frames.push(frame_to_image(&mut scaler, &frame)?);
+                if frames.len() == wanted {
+                    return Ok(frames);
+                }
             }
+            frame_index += 1;
         }
     }
 
-    Err(anyhow!("No frames decoded"))
+    if frames.is_empty() {
+        return match selection {
+            FrameSelection::At(offset) => Err(anyhow!(
+                "Offset {:.1}s is past the end of the segment ({:.1}s)",
+                offset,
+                seconds
+            )),
+            _ => Err(anyhow!("No frames decoded")),
+        };
+    }
+
+    Ok(frames)
+}
+
+fn count_video_packets(path: &Path) -> Result<usize> {
+    let mut input_ctx = ffmpeg::format::input(path)?;
+    let stream_index = input_ctx
+        .streams()
+        .best(ffmpeg::media::Type::Video)
+        .ok_or_else(|| anyhow!("No video stream found"))?
+        .index();
+
+    Ok(input_ctx
+        .packets()
+        .filter(|(stream, _)| stream.index() == stream_index)
+        .count())
+}
+
+fn frame_to_image(
+    scaler: &mut ffmpeg::software::scaling::context::Context,
+    frame: &ffmpeg::frame::Video,
+) -> Result<RgbImage> {
+    let mut rgb_frame =
+        ffmpeg::frame::Video::new(ffmpeg::format::Pixel::RGB24, frame.width(), frame.height());
+    scaler.run(frame, &mut rgb_frame)?;
+
+    let width = rgb_frame.width();
+    let height = rgb_frame.height();
+    let data = rgb_frame.data(0).to_vec();
+
+    RgbImage::from_raw(width, height, data)
+        .ok_or_else(|| anyhow!("Failed to create image from raw data"))
 }
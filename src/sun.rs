@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, Timelike, Utc};
+use std::f64::consts::PI;
+
+/// Zenith angle of the sun's center at sunrise and sunset, accounting for refraction and the
+/// size of the solar disk
+const SUNRISE_ZENITH: f64 = 90.833;
+
+/// Sunrise or sunset
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+/// Time of sunrise or sunset on `date` at a location, using the NOAA approximation which is
+/// good to a minute or two. `None` during polar day or night
+pub fn sun_event(
+    event: SunEvent,
+    date: NaiveDate,
+    latitude: f64,
+    longitude: f64,
+) -> Option<DateTime<Utc>> {
+    let (declination, equation_of_time) = solar_parameters(date.ordinal() as f64);
+    let latitude = latitude.to_radians();
+
+    let cos_hour_angle = SUNRISE_ZENITH.to_radians().cos() / (latitude.cos() * declination.cos())
+        - latitude.tan() * declination.tan();
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+
+    // Minutes after midnight UTC, east longitudes are positive
+    let minutes = match event {
+        SunEvent::Sunrise => 720.0 - 4.0 * (longitude + hour_angle) - equation_of_time,
+        SunEvent::Sunset => 720.0 - 4.0 * (longitude - hour_angle) - equation_of_time,
+    };
+
+    let midnight = date.and_hms_opt(0, 0, 0)?.and_utc();
+    Some(midnight + Duration::seconds((minutes * 60.0).round() as i64))
+}
+
+/// Where the sun is in the sky, in degrees
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SunPosition {
+    /// Angle above the horizon, negative once the sun has set
+    pub elevation: f64,
+    /// Compass bearing, clockwise from north
+    pub azimuth: f64,
+}
+
+/// How light the sky is, by the sun's elevation
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SkyPhase {
+    Night,
+    /// Sun 12 to 18 degrees below the horizon
+    AstronomicalTwilight,
+    /// Sun 6 to 12 degrees below the horizon
+    NauticalTwilight,
+    /// Between sunset and the sun being 6 degrees below the horizon
+    CivilTwilight,
+    /// Sun less than 6 degrees above the horizon
+    GoldenHour,
+    Day,
+}
+
+impl SkyPhase {
+    pub fn from_elevation(elevation: f64) -> Self {
+        match elevation {
+            e if e < -18.0 => SkyPhase::Night,
+            e if e < -12.0 => SkyPhase::AstronomicalTwilight,
+            e if e < -6.0 => SkyPhase::NauticalTwilight,
+            e if e < 90.0 - SUNRISE_ZENITH => SkyPhase::CivilTwilight,
+            e if e < 6.0 => SkyPhase::GoldenHour,
+            _ => SkyPhase::Day,
+        }
+    }
+}
+
+impl std::fmt::Display for SkyPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SkyPhase::Night => "night",
+            SkyPhase::AstronomicalTwilight => "astronomical twilight",
+            SkyPhase::NauticalTwilight => "nautical twilight",
+            SkyPhase::CivilTwilight => "civil twilight",
+            SkyPhase::GoldenHour => "golden hour",
+            SkyPhase::Day => "day",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Position of the sun at a moment as seen from a location, using the same NOAA
+/// approximation as [`sun_event`]
+pub fn sun_position(time: DateTime<Utc>, latitude: f64, longitude: f64) -> SunPosition {
+    let hours = time.num_seconds_from_midnight() as f64 / 3600.0;
+    let (declination, equation_of_time) =
+        solar_parameters(time.ordinal() as f64 + (hours - 12.0) / 24.0);
+
+    // Solar time in minutes, east longitudes are positive
+    let solar_minutes = hours * 60.0 + equation_of_time + 4.0 * longitude;
+    let hour_angle = (solar_minutes / 4.0 - 180.0).to_radians();
+    let latitude = latitude.to_radians();
+
+    let cos_zenith =
+        latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+    let elevation = 90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees();
+
+    // Measured from south towards west, turned around to a compass bearing
+    let from_south = hour_angle
+        .sin()
+        .atan2(hour_angle.cos() * latitude.sin() - declination.tan() * latitude.cos());
+    let azimuth = (from_south.to_degrees() + 180.0).rem_euclid(360.0);
+
+    SunPosition { elevation, azimuth }
+}
+
+/// Parse `now`, an RFC 3339 time such as `2025-06-21T21:30:00-04:00` or a local
+/// `YYYY-MM-DD HH:MM[:SS]`
+pub fn parse_datetime(text: &str) -> Result<DateTime<Utc>> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("now") {
+        return Ok(Utc::now());
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+    .and_then(|naive| naive.and_local_timezone(Local).earliest())
+    .map(|time| time.with_timezone(&Utc))
+    .ok_or_else(|| {
+        anyhow!(
+            "Times look like 2025-06-21T21:30:00-04:00 or 2025-06-21 21:30: {}",
+            text
+        )
+    })
+}
+
+// Solar declination in radians and the equation of time in minutes. Whole days are noon on
+// that day of the year
+fn solar_parameters(day_of_year: f64) -> (f64, f64) {
+    let gamma = 2.0 * PI / 365.0 * (day_of_year - 1.0);
+
+    let equation_of_time = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+
+    let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+
+    (declination, equation_of_time)
+}
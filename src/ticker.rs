@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use citycam::schedule::Schedule;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often a loop wakes up to check for signals while idle
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When the next round of a long-running command is due, and whether it should stop. The first
+/// round is due straight away
+pub struct Ticker {
+    schedule: Schedule,
+    next: Option<DateTime<Local>>,
+    paused: bool,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl Ticker {
+    /// Follow the schedule until SIGTERM/SIGINT or `stop`
+    pub fn start(schedule: Schedule) -> Result<Self> {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        for signal in [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT] {
+            signal_hook::flag::register(signal, Arc::clone(&shutdown))
+                .context("Failed to install signal handler")?;
+        }
+
+        Ok(Ticker {
+            schedule,
+            next: Some(Local::now()),
+            paused: false,
+            shutdown,
+        })
+    }
+
+    pub fn running(&self) -> bool {
+        !self.shutdown.load(Ordering::Relaxed)
+    }
+
+    pub fn stop(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a round should run now. Call `reschedule` once it has
+    pub fn is_due(&self) -> bool {
+        !self.paused && self.next.is_some_and(|due| due <= Local::now())
+    }
+
+    /// The schedule has no more rounds
+    pub fn finished(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Count the next round from now
+    pub fn reschedule(&mut self) {
+        self.next = self.schedule.next_after(&Local::now());
+    }
+
+    /// Follow a different schedule from now on
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+        self.reschedule();
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.reschedule();
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// When the next round is due, `None` while paused or when there are no more
+    pub fn next(&self) -> Option<DateTime<Local>> {
+        self.next.filter(|_| !self.paused)
+    }
+
+    /// How long to wait before checking again, never so long that a signal goes unnoticed
+    pub fn wait(&self) -> Duration {
+        self.next()
+            .and_then(|due| (due - Local::now()).to_std().ok())
+            .unwrap_or(POLL_INTERVAL)
+            .min(POLL_INTERVAL)
+    }
+}
@@ -0,0 +1,424 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use ffmpeg_next as ffmpeg;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::imageops::FilterType;
+use image::{Delay, DynamicImage, Frame, RgbImage};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::fit::{self, FitMode};
+use crate::history::{HistoryEntry, HistoryFilter};
+use crate::overlay;
+
+/// Deflicker evens each frame out against this many frames on either side of it
+pub const DEFLICKER_RADIUS: usize = 5;
+/// Deflicker never brightens or darkens a frame by more than this factor
+const MAX_GAIN: f64 = 2.0;
+/// Bits per pixel of every frame for the encoders that need a bit rate, enough to keep
+/// MPEG-4 from blocking on city scenes
+const BITS_PER_PIXEL: f64 = 0.2;
+
+/// What to write a timelapse as, picked from the file extension
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimelapseFormat {
+    Mp4,
+    Webm,
+    Gif,
+    /// Animated PNG
+    Apng,
+}
+
+impl TimelapseFormat {
+    /// Format for a .mp4, .webm, .gif or .png/.apng file
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        match extension.as_str() {
+            "mp4" | "m4v" => Ok(TimelapseFormat::Mp4),
+            "webm" => Ok(TimelapseFormat::Webm),
+            "gif" => Ok(TimelapseFormat::Gif),
+            "png" | "apng" => Ok(TimelapseFormat::Apng),
+            _ => Err(anyhow!(
+                "Can't tell the timelapse format from {} (expected .mp4, .webm, .gif or .png)",
+                path.display()
+            )),
+        }
+    }
+
+    /// Size the frames are written at. Video encoders want even dimensions, so the odd pixel
+    /// is cut off
+    pub fn frame_size(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            TimelapseFormat::Mp4 | TimelapseFormat::Webm => {
+                ((width & !1).max(2), (height & !1).max(2))
+            }
+            TimelapseFormat::Gif | TimelapseFormat::Apng => (width, height),
+        }
+    }
+}
+
+/// Wallpapers in the history matching the filter whose image is still in the cache, oldest
+/// first. Wallpapers spanning several monitors are left out, and of per-monitor copies of the
+/// same capture only the first is kept
+pub fn select_frames<'a>(
+    entries: &'a [HistoryEntry],
+    filter: &HistoryFilter,
+) -> Vec<&'a HistoryEntry> {
+    let mut frames: Vec<&HistoryEntry> = entries
+        .iter()
+        .filter(|entry| filter.matches(entry))
+        .filter(|entry| entry.output.as_deref() != Some("span"))
+        .filter(|entry| entry.path.exists())
+        .collect();
+    frames.sort_by_key(|entry| entry.captured_at.unwrap_or(entry.saved_at));
+
+    // Per-monitor copies are saved one after the other, so only the capture tells them apart
+    let mut seen = HashSet::new();
+    frames.retain(|entry| {
+        let captured_at = entry.captured_at.unwrap_or(entry.saved_at);
+        seen.insert((entry.camera.clone(), entry.segment.clone(), captured_at))
+    });
+    frames
+}
+
+/// Mean brightness of a frame, 0 to 255
+pub fn mean_brightness(img: &RgbImage) -> f64 {
+    if img.width() == 0 || img.height() == 0 {
+        return 0.0;
+    }
+    let total: f64 = img
+        .pixels()
+        .map(|p| 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64)
+        .sum();
+    total / (img.width() as f64 * img.height() as f64)
+}
+
+/// Gain for each frame that brings its brightness to the median of the frames within `radius`
+/// of it. Slow changes such as nightfall survive, a frame jumping out of line doesn't
+pub fn exposure_gains(brightness: &[f64], radius: usize) -> Vec<f64> {
+    (0..brightness.len())
+        .map(|i| {
+            // Shrinking the window near the ends keeps it centered, so a steady trend is kept
+            let radius = radius.min(i).min(brightness.len() - 1 - i);
+            let mut window = brightness[i - radius..=i + radius].to_vec();
+            window.sort_by(|a, b| a.total_cmp(b));
+            let target = window[window.len() / 2];
+            if brightness[i] < 1.0 {
+                // Nothing to scale in a black frame
+                1.0
+            } else {
+                (target / brightness[i]).clamp(1.0 / MAX_GAIN, MAX_GAIN)
+            }
+        })
+        .collect()
+}
+
+/// Multiply every channel by `gain`
+pub fn apply_gain(img: &mut RgbImage, gain: f64) {
+    if (gain - 1.0).abs() < f64::EPSILON {
+        return;
+    }
+    for pixel in img.pixels_mut() {
+        for c in 0..3 {
+            pixel[c] = (pixel[c] as f64 * gain).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// How to put a timelapse together
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimelapseOptions {
+    pub fps: u32,
+    /// Even out the exposure between frames
+    pub deflicker: bool,
+    /// Write the capture time into the corner of every frame
+    pub timestamps: bool,
+}
+
+/// Write the frames' images to `path` as a timelapse, in the format its extension asks for.
+/// Every frame is fitted to the size of the first
+pub fn write(path: &Path, frames: &[&HistoryEntry], options: &TimelapseOptions) -> Result<()> {
+    let format = TimelapseFormat::from_path(path)?;
+    let first = frames
+        .first()
+        .ok_or_else(|| anyhow!("No frames for the timelapse"))?;
+    let (width, height) = image::image_dimensions(&first.path)
+        .context(format!("Failed to read {}", first.path.display()))?;
+    let size = format.frame_size(width, height);
+
+    let load = |entry: &HistoryEntry| -> Result<RgbImage> {
+        let img = image::open(&entry.path)
+            .context(format!("Failed to read {}", entry.path.display()))?
+            .to_rgb8();
+        Ok(fit::fit(
+            &img,
+            size.0,
+            size.1,
+            FitMode::Cover,
+            FilterType::Lanczos3,
+            None,
+        ))
+    };
+
+    // Decoding twice beats holding every frame in memory
+    let gains = if options.deflicker {
+        let brightness = frames
+            .iter()
+            .map(|entry| Ok(mean_brightness(&load(entry)?)))
+            .collect::<Result<Vec<_>>>()?;
+        exposure_gains(&brightness, DEFLICKER_RADIUS)
+    } else {
+        vec![1.0; frames.len()]
+    };
+
+    let mut sink = create(path, format, size, options.fps, frames.len() as u32)?;
+    for (entry, gain) in frames.iter().zip(gains) {
+        let mut frame = load(entry)?;
+        apply_gain(&mut frame, gain);
+        if options.timestamps {
+            let time = entry.captured_at.unwrap_or(entry.saved_at);
+            let label = time.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+            overlay::draw_label(&mut frame, &label.to_string());
+        }
+        sink.push(&frame)?;
+    }
+    sink.finish()
+}
+
+/// Something frames can be written to one after another
+pub trait FrameSink {
+    /// Add a frame, which must have the size the sink was created with
+    fn push(&mut self, frame: &RgbImage) -> Result<()>;
+
+    /// Flush what is buffered and close the file
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Start writing a timelapse of `frame_count` frames of `width` x `height` to `path`
+pub fn create(
+    path: &Path,
+    format: TimelapseFormat,
+    (width, height): (u32, u32),
+    fps: u32,
+    frame_count: u32,
+) -> Result<Box<dyn FrameSink>> {
+    if fps == 0 {
+        return Err(anyhow!("The frame rate has to be at least 1"));
+    }
+    let create_file = || {
+        File::create(path)
+            .map(BufWriter::new)
+            .context(format!("Failed to create {}", path.display()))
+    };
+
+    match format {
+        TimelapseFormat::Mp4 => Ok(Box::new(VideoSink::new(
+            path,
+            &[ffmpeg::codec::Id::H264, ffmpeg::codec::Id::MPEG4],
+            (width, height),
+            fps,
+        )?)),
+        TimelapseFormat::Webm => Ok(Box::new(VideoSink::new(
+            path,
+            &[ffmpeg::codec::Id::VP9, ffmpeg::codec::Id::VP8],
+            (width, height),
+            fps,
+        )?)),
+        TimelapseFormat::Gif => {
+            // Speed 10 of 30 quantizes well enough at a fraction of the time of the best
+            let mut encoder = GifEncoder::new_with_speed(create_file()?, 10);
+            encoder.set_repeat(Repeat::Infinite)?;
+            Ok(Box::new(GifSink {
+                encoder,
+                delay: Delay::from_numer_denom_ms(1000, fps),
+            }))
+        }
+        TimelapseFormat::Apng => {
+            let mut encoder = png::Encoder::new(create_file()?, width, height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(frame_count, 0)?;
+            encoder.set_frame_delay(1, u16::try_from(fps)?)?;
+            Ok(Box::new(ApngSink {
+                writer: encoder.write_header()?,
+            }))
+        }
+    }
+}
+
+struct GifSink {
+    encoder: GifEncoder<BufWriter<File>>,
+    delay: Delay,
+}
+
+impl FrameSink for GifSink {
+    fn push(&mut self, frame: &RgbImage) -> Result<()> {
+        let rgba = DynamicImage::ImageRgb8(frame.clone()).into_rgba8();
+        self.encoder
+            .encode_frame(Frame::from_parts(rgba, 0, 0, self.delay))?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        // The trailer is written when the encoder is dropped
+        Ok(())
+    }
+}
+
+struct ApngSink {
+    writer: png::Writer<BufWriter<File>>,
+}
+
+impl FrameSink for ApngSink {
+    fn push(&mut self, frame: &RgbImage) -> Result<()> {
+        self.writer.write_image_data(frame.as_raw())?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Encodes frames with the first of the codecs ffmpeg was built with
+struct VideoSink {
+    output: ffmpeg::format::context::Output,
+    encoder: ffmpeg::encoder::Video,
+    scaler: ffmpeg::software::scaling::context::Context,
+    width: u32,
+    height: u32,
+    time_base: ffmpeg::Rational,
+    stream_time_base: ffmpeg::Rational,
+    next_pts: i64,
+}
+
+impl VideoSink {
+    fn new(
+        path: &Path,
+        codecs: &[ffmpeg::codec::Id],
+        (width, height): (u32, u32),
+        fps: u32,
+    ) -> Result<Self> {
+        ffmpeg::init()?;
+        ffmpeg::log::set_level(ffmpeg::log::Level::Error);
+
+        let mut output =
+            ffmpeg::format::output(path).context(format!("Failed to create {}", path.display()))?;
+        let codec = codecs
+            .iter()
+            .find_map(|&id| ffmpeg::encoder::find(id))
+            .ok_or_else(|| anyhow!("ffmpeg has none of the encoders for {}", path.display()))?;
+        let global_header = output
+            .format()
+            .flags()
+            .contains(ffmpeg::format::Flags::GLOBAL_HEADER);
+        let time_base = ffmpeg::Rational(1, fps as i32);
+
+        let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .video()?;
+        encoder.set_width(width);
+        encoder.set_height(height);
+        encoder.set_format(ffmpeg::format::Pixel::YUV420P);
+        encoder.set_time_base(time_base);
+        encoder.set_frame_rate(Some(ffmpeg::Rational(fps as i32, 1)));
+        if global_header {
+            encoder.set_flags(ffmpeg::codec::Flags::GLOBAL_HEADER);
+        }
+        // MPEG-4 and VP8 default to a couple hundred kb/s whatever the size, H.264 and VP9
+        // pick a quality of their own
+        if matches!(
+            codec.id(),
+            ffmpeg::codec::Id::MPEG4 | ffmpeg::codec::Id::VP8
+        ) {
+            let bits = width as f64 * height as f64 * fps as f64 * BITS_PER_PIXEL;
+            encoder.set_bit_rate(bits as usize);
+        }
+        let encoder = encoder.open_as(codec)?;
+
+        let mut stream = output.add_stream(codec)?;
+        stream.set_parameters(&encoder);
+        stream.set_time_base(time_base);
+        output.write_header()?;
+        // The muxer may have picked a time base of its own
+        let stream_time_base = output
+            .stream(0)
+            .map(|stream| stream.time_base())
+            .unwrap_or(time_base);
+
+        let scaler = ffmpeg::software::scaling::context::Context::get(
+            ffmpeg::format::Pixel::RGB24,
+            width,
+            height,
+            ffmpeg::format::Pixel::YUV420P,
+            width,
+            height,
+            ffmpeg::software::scaling::flag::Flags::BILINEAR,
+        )?;
+
+        Ok(VideoSink {
+            output,
+            encoder,
+            scaler,
+            width,
+            height,
+            time_base,
+            stream_time_base,
+            next_pts: 0,
+        })
+    }
+
+    fn write_packets(&mut self) -> Result<()> {
+        let mut packet = ffmpeg::Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(0);
+            packet.rescale_ts(self.time_base, self.stream_time_base);
+            packet.write_interleaved(&mut self.output)?;
+        }
+        Ok(())
+    }
+}
+
+impl FrameSink for VideoSink {
+    fn push(&mut self, frame: &RgbImage) -> Result<()> {
+        if frame.dimensions() != (self.width, self.height) {
+            return Err(anyhow!(
+                "Frame is {}x{}, the video {}x{}",
+                frame.width(),
+                frame.height(),
+                self.width,
+                self.height
+            ));
+        }
+
+        let mut rgb =
+            ffmpeg::frame::Video::new(ffmpeg::format::Pixel::RGB24, self.width, self.height);
+        let stride = rgb.stride(0);
+        let row_bytes = self.width as usize * 3;
+        for (y, row) in frame.as_raw().chunks_exact(row_bytes).enumerate() {
+            rgb.data_mut(0)[y * stride..y * stride + row_bytes].copy_from_slice(row);
+        }
+
+        let mut yuv = ffmpeg::frame::Video::empty();
+        self.scaler.run(&rgb, &mut yuv)?;
+        yuv.set_pts(Some(self.next_pts));
+        self.next_pts += 1;
+
+        self.encoder.send_frame(&yuv)?;
+        self.write_packets()
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.encoder.send_eof()?;
+        self.write_packets()?;
+        self.output.write_trailer()?;
+        Ok(())
+    }
+}
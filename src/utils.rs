@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Result};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
 pub fn get_cache_dir() -> Result<PathBuf> {
     let cache_dir = dirs::cache_dir()
@@ -8,14 +8,19 @@ pub fn get_cache_dir() -> Result<PathBuf> {
     Ok(cache_dir)
 }
 
-pub fn set_wallpaper(path: &Path) -> Result<()> {
-    let path_str = path.to_str().ok_or_else(|| anyhow!("Invalid path"))?;
-
-    wallpaper::set_from_path(path_str).map_err(|e| anyhow!("Failed to set wallpaper: {}", e))?;
-
-    #[cfg(target_os = "windows")]
-    wallpaper::set_mode(wallpaper::Mode::Crop)
-        .map_err(|e| anyhow!("Failed to set wallpaper mode: {}", e))?;
+/// config.toml in the user's config directory, e.g. ~/.config/citycam/config.toml
+pub fn get_config_path() -> Result<PathBuf> {
+    let config_dir = dirs::config_dir()
+        .ok_or_else(|| anyhow!("Could not determine config directory"))?
+        .join(env!("CARGO_PKG_NAME"));
+    Ok(config_dir.join("config.toml"))
+}
 
-    Ok(())
+/// Where the rotation daemon keeps its pidfile and control socket: $XDG_RUNTIME_DIR when
+/// there is one, the cache directory otherwise
+pub fn get_runtime_dir() -> Result<PathBuf> {
+    match dirs::runtime_dir() {
+        Some(dir) => Ok(dir.join(env!("CARGO_PKG_NAME"))),
+        None => get_cache_dir(),
+    }
 }
@@ -0,0 +1,94 @@
+use chrono::{Local, TimeZone};
+use citycam::archive::{
+    camera_dir_name, check_camera_dirs, frame_path, original_path, readable, save, FrameFormat,
+};
+use image::{Rgb, RgbImage};
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn test_frame_format_parse() {
+    assert_eq!(FrameFormat::parse("PNG", 95).unwrap(), FrameFormat::Png);
+    assert_eq!(
+        FrameFormat::parse("jpeg", 90).unwrap(),
+        FrameFormat::Jpeg {
+            quality: 90,
+            progressive: false
+        }
+    );
+    assert_eq!(
+        FrameFormat::parse("avif", 60).unwrap(),
+        FrameFormat::Avif { quality: 60 }
+    );
+    assert_eq!(FrameFormat::parse("webp", 0).unwrap().extension(), "webp");
+    assert_eq!(FrameFormat::parse("jpg", 90).unwrap().extension(), "jpg");
+    assert!(FrameFormat::parse("jpg", 0).is_err());
+    assert!(FrameFormat::parse("jpg", 101).is_err());
+    assert!(FrameFormat::parse("avif", 0).is_err());
+    assert!(FrameFormat::parse("tiff", 95).is_err());
+}
+
+#[test]
+fn test_frame_paths() {
+    assert_eq!(
+        camera_dir_name("Ambassador Bridge (US side)"),
+        "ambassador-bridge-us-side"
+    );
+    assert_eq!(camera_dir_name("  Pier #3 "), "pier-3");
+    assert_eq!(camera_dir_name("???"), "camera");
+
+    assert!(check_camera_dirs(["Harbor", "Pier #3", "Pier 4"]).is_ok());
+    let error = check_camera_dirs(["Pier #3", "Harbor", "pier 3"]).unwrap_err();
+    assert!(
+        error.to_string().contains("Pier #3 and pier 3"),
+        "{}",
+        error
+    );
+
+    let time = Local.with_ymd_and_hms(2025, 3, 10, 18, 5, 9).unwrap();
+    assert_eq!(
+        frame_path(Path::new("/archive"), "Harbor Cam", time, FrameFormat::Png),
+        Path::new("/archive/harbor-cam/2025-03-10/20250310-180509.png")
+    );
+
+    assert_eq!(
+        original_path(Path::new("/cache/20250310-180509-DP-1.jpg")),
+        Path::new("/cache/originals/20250310-180509-DP-1.jpg")
+    );
+}
+
+#[test]
+fn test_save_creates_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    let img = RgbImage::from_pixel(16, 12, Rgb([200, 40, 40]));
+    let time = Local.with_ymd_and_hms(2025, 3, 10, 18, 5, 9).unwrap();
+
+    let png = frame_path(dir.path(), "Harbor", time, FrameFormat::Png);
+    save(&img, &png, FrameFormat::Png).unwrap();
+    assert_eq!(image::open(&png).unwrap().to_rgb8(), img);
+
+    for progressive in [false, true] {
+        let jpeg_format = FrameFormat::Jpeg {
+            quality: 95,
+            progressive,
+        };
+        let jpeg = frame_path(dir.path(), "Harbor", time, jpeg_format);
+        save(&img, &jpeg, jpeg_format).unwrap();
+        let decoded = image::open(&jpeg).unwrap().to_rgb8();
+        assert_eq!(decoded.dimensions(), (16, 12));
+        assert!(decoded.get_pixel(8, 6)[0] > 180);
+    }
+
+    let webp = frame_path(dir.path(), "Harbor", time, FrameFormat::Webp);
+    save(&img, &webp, FrameFormat::Webp).unwrap();
+    assert_eq!(image::open(&webp).unwrap().to_rgb8(), img);
+
+    let avif_format = FrameFormat::Avif { quality: 80 };
+    let avif = frame_path(dir.path(), "Harbor", time, avif_format);
+    save(&img, &avif, avif_format).unwrap();
+    assert!(fs::metadata(&avif).unwrap().len() > 0);
+    // Written, but without a decoder it can't be opened again
+    assert!(!avif_format.readable() && !readable(&avif));
+    assert!(image::open(&avif).is_err());
+    assert!(readable(&webp) && readable(&png));
+}
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use citycam::backend::{self, CommandTemplate, Plasma, WallpaperSetter};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Remembers what it was asked to show
+#[derive(Default)]
+struct Fake {
+    calls: RefCell<Vec<(String, PathBuf)>>,
+}
+
+impl WallpaperSetter for Fake {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn set(&self, path: &Path) -> Result<()> {
+        self.calls
+            .borrow_mut()
+            .push(("*".to_string(), path.to_path_buf()));
+        Ok(())
+    }
+
+    fn set_outputs(&self, wallpapers: &[(String, PathBuf)]) -> Result<()> {
+        self.calls.borrow_mut().extend(wallpapers.iter().cloned());
+        Ok(())
+    }
+}
+
+fn detected(vars: &[(&str, &str)]) -> &'static str {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    backend::detect(|name| vars.get(name).cloned()).name()
+}
+
+#[test]
+fn test_apply_sets_one_or_per_output() {
+    let fake = Fake::default();
+    backend::apply(&fake, &[("DP-1".to_string(), PathBuf::from("a.jpg"))]).unwrap();
+    backend::apply(
+        &fake,
+        &[
+            ("DP-1".to_string(), PathBuf::from("b.jpg")),
+            ("DP-2".to_string(), PathBuf::from("c.jpg")),
+        ],
+    )
+    .unwrap();
+
+    let calls = fake.calls.borrow();
+    assert_eq!(calls[0], ("*".to_string(), PathBuf::from("a.jpg")));
+    assert_eq!(calls[2], ("DP-2".to_string(), PathBuf::from("c.jpg")));
+    assert!(backend::apply(&fake, &[]).is_err());
+
+    // Backends without per-output support say so
+    let gnome = backend::parse("gnome").unwrap();
+    assert!(gnome
+        .set_outputs(&[("DP-1".to_string(), PathBuf::from("a.jpg"))])
+        .is_err());
+}
+
+#[test]
+fn test_parse_backends() {
+    for name in ["native", "sway", "swaybg", "swww", "feh", "kde", "file"] {
+        assert_eq!(backend::parse(name).unwrap().name(), name);
+    }
+    assert_eq!(backend::parse("GSettings").unwrap().name(), "gnome");
+    assert_eq!(
+        backend::parse("command:swww img {path}").unwrap().name(),
+        "command"
+    );
+    assert!(backend::parse("command:swww img").is_err());
+    assert!(backend::parse("xfce").is_err());
+}
+
+#[test]
+fn test_detect_backend() {
+    assert_eq!(
+        detected(&[
+            ("SWAYSOCK", "/run/sway.sock"),
+            ("WAYLAND_DISPLAY", "wayland-1")
+        ]),
+        "sway"
+    );
+    assert_eq!(detected(&[("HYPRLAND_INSTANCE_SIGNATURE", "abc")]), "swww");
+    assert_eq!(detected(&[("XDG_CURRENT_DESKTOP", "KDE")]), "kde");
+    assert_eq!(
+        detected(&[("XDG_CURRENT_DESKTOP", "ubuntu:GNOME")]),
+        "gnome"
+    );
+    assert_eq!(detected(&[("DISPLAY", ":0")]), "feh");
+    assert_eq!(
+        detected(&[("DISPLAY", ":0"), ("XDG_CURRENT_DESKTOP", "i3")]),
+        "feh"
+    );
+    assert_eq!(
+        detected(&[("DISPLAY", ":0"), ("XDG_CURRENT_DESKTOP", "XFCE")]),
+        "native"
+    );
+}
+
+#[test]
+fn test_command_template() {
+    let template = CommandTemplate {
+        template: "swww img -o {output} {path}".to_string(),
+    };
+    assert_eq!(
+        template.command_line(Path::new("/tmp/it's.jpg"), Some("DP-1")),
+        r"swww img -o 'DP-1' '/tmp/it'\''s.jpg'"
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("frame.jpg");
+    std::fs::write(&source, b"jpeg").unwrap();
+    let copy = CommandTemplate {
+        template: format!("cp {{path}} '{}'", dir.path().join("copy.jpg").display()),
+    };
+    copy.set(&source).unwrap();
+    assert_eq!(std::fs::read(dir.path().join("copy.jpg")).unwrap(), b"jpeg");
+
+    // Per output needs somewhere to put the output name
+    assert!(copy
+        .set_outputs(&[("DP-1".to_string(), source.clone())])
+        .is_err());
+    assert!(CommandTemplate {
+        template: "false {path}".to_string()
+    }
+    .set(&source)
+    .is_err());
+}
+
+#[test]
+fn test_plasma_script_quotes_the_path() {
+    let script = Plasma::script(Path::new("/tmp/a \"b\".jpg")).unwrap();
+    assert!(script.contains(r#"d.writeConfig('Image', "file:///tmp/a \"b\".jpg")"#));
+}
@@ -0,0 +1,175 @@
+use citycam::camera::CameraKind;
+use citycam::config::{negatable_flags, Config};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+
+const CONFIG: &str = r##"
+rotation-interval = 300
+tint-color = "#ff5500"
+grayscale = true
+camera = "harbor"
+playlist = ["05:30-09:00=harbor", "21:00-05:00=downtown"]
+
+[profile.noir]
+noise = "gaussian"
+noise-intensity = 20.5
+
+[profile.day]
+grayscale = false
+source = "day.m3u8"
+playlist = ["06:00-18:00=harbor"]
+
+[[cameras]]
+name = "Harbor"
+url = "https://example.com/harbor.m3u8"
+kind = "hls"
+"##;
+
+// The settings of the real command line that the merge cares about
+#[derive(Parser, Debug)]
+#[command(args_override_self = true)]
+struct Cli {
+    #[arg(short, long, global = true)]
+    grayscale: bool,
+    #[arg(long, global = true)]
+    rotation_interval: Option<u64>,
+    #[arg(long, global = true)]
+    tint_color: Option<String>,
+    #[arg(long, global = true)]
+    noise: Option<String>,
+    #[arg(long, global = true)]
+    noise_intensity: Option<f64>,
+    #[arg(long, global = true)]
+    playlist: Vec<String>,
+    #[arg(short, long, global = true)]
+    camera: Option<String>,
+    #[arg(long, conflicts_with = "camera", global = true)]
+    source: Option<String>,
+    #[command(subcommand)]
+    command: Option<Sub>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Sub {
+    Fetch,
+}
+
+fn parse(config: &Config, command_line: &[&str], profile: Option<&str>) -> anyhow::Result<Cli> {
+    let command = negatable_flags(Cli::command());
+    let command_line: Vec<OsString> = std::iter::once("citycam")
+        .chain(command_line.iter().copied())
+        .map(OsString::from)
+        .collect();
+    let merged = config.merge_args(&command, &command_line, profile)?;
+    Ok(Cli::from_arg_matches(
+        &command.try_get_matches_from(merged)?,
+    )?)
+}
+
+#[test]
+fn test_defaults_become_flags() {
+    let config = Config::parse(CONFIG).unwrap();
+    let cli = parse(&config, &[], None).unwrap();
+
+    assert!(cli.grayscale);
+    assert_eq!(cli.rotation_interval, Some(300));
+    assert_eq!(cli.tint_color.as_deref(), Some("#ff5500"));
+    assert_eq!(cli.camera.as_deref(), Some("harbor"));
+    assert_eq!(cli.playlist, ["05:30-09:00=harbor", "21:00-05:00=downtown"]);
+    assert!(cli.command.is_none());
+
+    let empty = parse(&Config::parse("").unwrap(), &["-g"], None).unwrap();
+    assert!(empty.grayscale);
+    assert_eq!(empty.rotation_interval, None);
+}
+
+#[test]
+fn test_profile_overrides_defaults() {
+    let config = Config::parse(CONFIG).unwrap();
+
+    let noir = parse(&config, &[], Some("noir")).unwrap();
+    assert!(noir.grayscale);
+    assert_eq!(noir.noise.as_deref(), Some("gaussian"));
+    assert_eq!(noir.noise_intensity, Some(20.5));
+
+    // false turns a default off, and a source replaces the default camera
+    let day = parse(&config, &[], Some("day")).unwrap();
+    assert!(!day.grayscale);
+    assert_eq!(day.source.as_deref(), Some("day.m3u8"));
+    assert_eq!(day.camera, None);
+    assert_eq!(day.playlist, ["06:00-18:00=harbor"]);
+    assert_eq!(day.rotation_interval, Some(300));
+
+    let error = parse(&config, &[], Some("sepia")).unwrap_err().to_string();
+    assert!(error.contains("sepia") && error.contains("noir"));
+}
+
+#[test]
+fn test_command_line_overrides_config() {
+    let config = Config::parse(CONFIG).unwrap();
+
+    let cli = parse(
+        &config,
+        &[
+            "--grayscale=false",
+            "--source",
+            "clip.mp4",
+            "fetch",
+            "--rotation-interval",
+            "60",
+        ],
+        Some("noir"),
+    )
+    .unwrap();
+    assert!(!cli.grayscale);
+    assert_eq!(cli.source.as_deref(), Some("clip.mp4"));
+    assert_eq!(cli.camera, None);
+    assert_eq!(cli.rotation_interval, Some(60));
+    assert_eq!(cli.noise.as_deref(), Some("gaussian"));
+    assert!(cli.command.is_some());
+
+    let cli = parse(
+        &config,
+        &["-c", "3", "--playlist", "00:00-23:59=3"],
+        Some("day"),
+    )
+    .unwrap();
+    assert_eq!(cli.camera.as_deref(), Some("3"));
+    assert_eq!(cli.source, None);
+    assert_eq!(cli.playlist, ["00:00-23:59=3"]);
+}
+
+#[test]
+fn test_config_rejects_unknown_settings() {
+    let nested = Config::parse("[grayscale]\nenabled = true\n").unwrap();
+    assert!(parse(&nested, &[], None).is_err());
+
+    let typo = Config::parse("greyscale = true\n").unwrap();
+    assert!(parse(&typo, &[], None)
+        .unwrap_err()
+        .to_string()
+        .contains("greyscale"));
+
+    assert!(Config::parse("grayscale = ").is_err());
+}
+
+#[test]
+fn test_config_cameras() {
+    let config = Config::parse(CONFIG).unwrap();
+
+    assert_eq!(config.cameras.len(), 1);
+    assert_eq!(config.cameras[0].name, "Harbor");
+    assert_eq!(config.cameras[0].kind, CameraKind::Hls);
+    assert!(!config.defaults.contains_key("cameras"));
+    assert!(!config.defaults.contains_key("profile"));
+}
+
+#[test]
+fn test_config_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    std::fs::write(&path, CONFIG).unwrap();
+
+    assert_eq!(Config::load(&path).unwrap().profiles.len(), 2);
+    assert!(Config::load(&dir.path().join("missing.toml")).is_err());
+}
@@ -0,0 +1,129 @@
+use citycam::display::{
+    layout_bounds, parse_wlr_randr, parse_xrandr, stitch, Monitor, OutputAssignment,
+};
+use image::{Rgb, RgbImage};
+
+const XRANDR: &str = "\
+Screen 0: minimum 320 x 200, current 4480 x 1440, maximum 16384 x 16384
+eDP-1 connected primary 1920x1080+0+360 (normal left inverted right x axis y axis) 309mm x 174mm
+   1920x1080     60.01*+  59.97
+HDMI-1 disconnected (normal left inverted right x axis y axis)
+DP-1 connected 2560x1440+1920+0 (normal left inverted right x axis y axis) 597mm x 336mm
+   2560x1440     59.95*+
+DP-2 connected (normal left inverted right x axis y axis)
+   1920x1080     60.00 +
+";
+
+const WLR_RANDR: &str = "\
+eDP-1 \"Sharp Corporation 0x1453 (eDP-1)\"
+  Enabled: yes
+  Modes:
+    1920x1080 px, 60.052000 Hz (preferred, current)
+  Position: 0,0
+  Transform: normal
+  Scale: 1.000000
+DP-3 \"Dell Inc. DELL U2719D (DP-3)\"
+  Enabled: yes
+  Modes:
+    2560x1440 px, 59.951000 Hz (preferred, current)
+    1920x1080 px, 60.000000 Hz
+  Position: 1920,0
+  Transform: 90
+DP-4 \"LG Electronics LG HDR 4K (DP-4)\"
+  Enabled: yes
+  Modes:
+    3840x2160 px, 60.000000 Hz (preferred, current)
+  Position: 3360,0
+  Transform: normal
+  Scale: 2.000000
+HDMI-A-1 \"Unknown\"
+  Enabled: no
+  Modes:
+    1920x1080 px, 60.000000 Hz (preferred)
+";
+
+fn monitor(name: &str, width: u32, height: u32, x: i32, y: i32) -> Monitor {
+    Monitor {
+        name: name.to_string(),
+        width,
+        height,
+        x,
+        y,
+        primary: false,
+        scale: 1.0,
+    }
+}
+
+#[test]
+fn test_parse_xrandr() {
+    let monitors = parse_xrandr(XRANDR);
+    assert_eq!(
+        monitors,
+        [
+            Monitor {
+                primary: true,
+                ..monitor("eDP-1", 1920, 1080, 0, 360)
+            },
+            monitor("DP-1", 2560, 1440, 1920, 0),
+        ]
+    );
+    assert_eq!(layout_bounds(&monitors), (0, 0, 4480, 1440));
+}
+
+#[test]
+fn test_parse_wlr_randr() {
+    assert_eq!(
+        parse_wlr_randr(WLR_RANDR),
+        [
+            monitor("eDP-1", 1920, 1080, 0, 0),
+            monitor("DP-3", 1440, 2560, 1920, 0),
+            Monitor {
+                scale: 2.0,
+                ..monitor("DP-4", 3840, 2160, 3360, 0)
+            },
+        ]
+    );
+    // The scaled monitor renders at its full mode but takes half that in the layout
+    let monitors = parse_wlr_randr(WLR_RANDR);
+    assert_eq!(monitors[2].layout_size(), (1920, 1080));
+    assert_eq!(layout_bounds(&monitors), (0, 0, 5280, 2560));
+}
+
+#[test]
+fn test_configured_monitors_and_outputs() {
+    assert_eq!(
+        Monitor::parse("HDMI-1=1920x1080+-1920+0").unwrap(),
+        monitor("HDMI-1", 1920, 1080, -1920, 0)
+    );
+    assert_eq!(
+        Monitor::parse("HDMI-1=1280x1024").unwrap(),
+        monitor("HDMI-1", 1280, 1024, 0, 0)
+    );
+    assert!(Monitor::parse("1920x1080").is_err());
+    assert!(Monitor::parse("HDMI-1=big").is_err());
+    assert!(Monitor::parse("HDMI-1=0x0").is_err());
+    assert!(Monitor::parse("HDMI-1=1920x0+0+0").is_err());
+
+    let output = OutputAssignment::parse("DP-2=tag:lake|grayscale,sky").unwrap();
+    assert_eq!(output.output, "DP-2");
+    assert_eq!(output.camera, "tag:lake");
+    assert_eq!(output.pipeline.as_deref(), Some("grayscale,sky"));
+    assert_eq!(OutputAssignment::parse("HDMI-1=3").unwrap().pipeline, None);
+    assert!(OutputAssignment::parse("HDMI-1=").is_err());
+}
+
+#[test]
+fn test_stitch_places_images_in_layout() {
+    let left = monitor("left", 4, 2, -4, 1);
+    let right = monitor("right", 3, 3, 0, 0);
+    let spanned = stitch(&[
+        (left, RgbImage::from_pixel(4, 2, Rgb([255, 0, 0]))),
+        (right, RgbImage::from_pixel(3, 3, Rgb([0, 0, 255]))),
+    ]);
+
+    assert_eq!(spanned.dimensions(), (7, 3));
+    assert_eq!(spanned.get_pixel(0, 1).0, [255, 0, 0]);
+    assert_eq!(spanned.get_pixel(6, 0).0, [0, 0, 255]);
+    // Nothing above the shorter monitor
+    assert_eq!(spanned.get_pixel(0, 0).0, [0, 0, 0]);
+}
@@ -0,0 +1,204 @@
+use chrono::{TimeZone, Utc};
+use citycam::effects::{
+    parse_effect, parse_hue_ranges, Grayscale, Pipeline, Scene, Tint, AVAILABLE_EFFECTS,
+};
+use citycam::sky_detection::{sky_color_for_hour, SkyPalette};
+use image::{Rgb, RgbImage};
+
+#[test]
+fn test_pipeline_parse_keeps_order_and_repeats() {
+    let pipeline = Pipeline::parse("grayscale, sky,tint:#ff5500@0.3,noise:gaussian@20,grayscale")
+        .expect("pipeline should parse");
+
+    assert_eq!(
+        pipeline.names(),
+        vec!["grayscale", "sky", "tint", "noise", "grayscale"]
+    );
+}
+
+#[test]
+fn test_pipeline_parse_rejects_bad_stages() {
+    assert!(
+        parse_effect("sparkle").is_err(),
+        "Unknown effects should fail"
+    );
+    assert!(parse_effect("tint").is_err(), "Tint needs a color");
+    assert!(
+        parse_effect("tint:#ff55").is_err(),
+        "Tint color must be 6 hex digits"
+    );
+    assert!(
+        parse_effect("noise:gaussian@lots").is_err(),
+        "Amount must be numeric"
+    );
+    assert!(
+        parse_effect("noise:sparkly").is_err(),
+        "Unknown noise types should fail"
+    );
+}
+
+#[test]
+fn test_effect_amounts_out_of_range() {
+    for stage in [
+        "noise:gaussian@-5",
+        "noise:gaussian@nan",
+        "noise:gaussian@inf",
+        "noise:salt-pepper@300",
+        "tint:#ff5500@1.5",
+        "tint:#ff5500@-0.1",
+        "tint:#ff5500@NaN",
+        "neon:*@2",
+        "neon:*@0.5@-1",
+        "neon:*@0.5@1000",
+        "beams:#7dffb0@-1",
+        "beams:#7dffb0@1000",
+        "beams:#7dffb0@3@-1",
+    ] {
+        assert!(parse_effect(stage).is_err(), "{}", stage);
+    }
+
+    for stage in [
+        "noise:gaussian@0",
+        "noise:salt-pepper@255",
+        "tint:#ff5500@0",
+        "tint:#ff5500@1",
+        "neon:*@1@0",
+        "beams:#7dffb0@0",
+    ] {
+        assert!(parse_effect(stage).is_ok(), "{}", stage);
+    }
+}
+
+#[test]
+fn test_pipeline_applies_effects_in_order() {
+    let mut img = RgbImage::new(4, 4);
+    for pixel in img.pixels_mut() {
+        *pixel = Rgb([200, 100, 50]);
+    }
+
+    // Tinting after grayscale leaves a colored result, the reverse leaves it gray
+    let mut tint_last = Pipeline::new();
+    tint_last.push(Grayscale);
+    tint_last.push(Tint {
+        color: "#ff0000".to_string(),
+        intensity: 0.5,
+    });
+
+    let mut gray_last = Pipeline::new();
+    gray_last.push(Tint {
+        color: "#ff0000".to_string(),
+        intensity: 0.5,
+    });
+    gray_last.push(Grayscale);
+
+    let tinted = tint_last.apply(&img).get_pixel(0, 0).0;
+    let grayed = gray_last.apply(&img).get_pixel(0, 0).0;
+
+    assert!(tinted[0] > tinted[1], "Tint applied last should keep red");
+    assert_eq!(
+        grayed[0], grayed[1],
+        "Grayscale applied last should be gray"
+    );
+    assert_eq!(
+        grayed[1], grayed[2],
+        "Grayscale applied last should be gray"
+    );
+}
+
+#[test]
+fn test_empty_pipeline_is_identity() {
+    let mut img = RgbImage::new(2, 2);
+    img.put_pixel(1, 1, Rgb([10, 20, 30]));
+
+    let pipeline = Pipeline::parse("").unwrap();
+
+    assert!(pipeline.is_empty());
+    assert_eq!(pipeline.apply(&img), img);
+}
+
+#[test]
+fn test_neon_stage_and_hue_ranges() {
+    let pipeline = Pipeline::parse("neon:280-340+0-40@0.5@8,neon,neon:*@0.3").unwrap();
+    assert_eq!(pipeline.names(), vec!["neon", "neon", "neon"]);
+
+    assert_eq!(
+        parse_hue_ranges("280-340, 330-30").unwrap(),
+        vec![(280.0, 340.0), (330.0, 30.0)]
+    );
+    assert!(parse_hue_ranges("280").is_err());
+    assert!(parse_hue_ranges("0-400").is_err());
+    assert!(parse_effect("neon:0-40@bright").is_err());
+}
+
+#[test]
+fn test_beams_stage_with_seed_is_reproducible() {
+    let img = RgbImage::from_pixel(40, 30, Rgb([150, 150, 150]));
+
+    let pipeline = Pipeline::parse("noise:gaussian@10,beams:#7dffb0@2@42").unwrap();
+    assert_eq!(pipeline.names(), vec!["noise", "beams"]);
+
+    let beams = Pipeline::parse("beams:*@2@42").unwrap();
+    assert_eq!(beams.apply(&img), beams.apply(&img));
+
+    // Without a seed one is picked and kept in the spec, so the scene can be drawn again
+    let random = Pipeline::parse("beams:*@2").unwrap();
+    let spec = random.spec();
+    assert!(spec.starts_with("beams:#7dffb0@2@"), "{}", spec);
+    let again = Pipeline::parse(&spec).unwrap();
+    assert_eq!(again.spec(), spec);
+    assert_eq!(again.apply(&img), random.apply(&img));
+
+    assert!(parse_effect("beams:green").is_err());
+    assert!(parse_effect("beams:#7dffb0@2@soon").is_err());
+}
+
+#[test]
+fn test_effect_catalog_examples_parse() {
+    for effect in AVAILABLE_EFFECTS {
+        let parsed = parse_effect(effect.example)
+            .unwrap_or_else(|e| panic!("example for {} should parse: {}", effect.name, e));
+        assert_eq!(parsed.name(), effect.name);
+        for alias in effect.aliases {
+            assert_eq!(parse_effect(alias).unwrap().name(), effect.name);
+        }
+    }
+
+    let error = parse_effect("sepia").err().unwrap().to_string();
+    assert!(error.contains("sepia") && error.contains("grayscale, sky"));
+}
+
+#[test]
+fn test_pipeline_spec_parses_back() {
+    let spec = "grayscale,sky,tint:#ff5500@0.3,noise:salt-pepper@40,neon:280-340+0-40@0.5@8,\
+                beams:#7dffb0@3@42";
+    let pipeline = Pipeline::parse(spec).unwrap();
+    assert_eq!(pipeline.spec(), spec);
+    assert_eq!(Pipeline::parse(&pipeline.spec()).unwrap().spec(), spec);
+
+    assert_eq!(Pipeline::parse("neon").unwrap().spec(), "neon:*@0.45@6");
+    assert_eq!(Pipeline::new().spec(), "");
+}
+
+#[test]
+fn test_sky_by_the_cameras_clock_without_location() {
+    let time = Utc.with_ymd_and_hms(2025, 6, 21, 3, 0, 0).unwrap();
+    let tokyo = Scene {
+        time,
+        location: None,
+        timezone: Some(chrono_tz::Asia::Tokyo),
+        horizon: None,
+    };
+    assert_eq!(
+        tokyo.sky_palette(),
+        SkyPalette::uniform(sky_color_for_hour(12))
+    );
+
+    let detroit = Scene {
+        timezone: Some(chrono_tz::America::Detroit),
+        ..tokyo
+    };
+    assert_eq!(
+        detroit.sky_palette(),
+        SkyPalette::uniform(sky_color_for_hour(23))
+    );
+}
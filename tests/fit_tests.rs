@@ -0,0 +1,101 @@
+use citycam::camera::Crop;
+use citycam::fit::{fit, interesting_center, parse_filter, parse_resolution, FitMode};
+use image::imageops::FilterType;
+use image::{Rgb, RgbImage};
+
+// Left half red, right half blue
+fn halves(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, _| {
+        if x < width / 2 {
+            Rgb([255, 0, 0])
+        } else {
+            Rgb([0, 0, 255])
+        }
+    })
+}
+
+#[test]
+fn test_parse_fit_options() {
+    assert_eq!(FitMode::parse("Contain").unwrap(), FitMode::Contain);
+    assert_eq!(FitMode::parse("smart-crop").unwrap(), FitMode::Smart);
+    assert!(FitMode::parse("zoom").is_err());
+    for mode in [
+        FitMode::Cover,
+        FitMode::Contain,
+        FitMode::Stretch,
+        FitMode::Smart,
+    ] {
+        assert_eq!(FitMode::parse(&mode.to_string()).unwrap(), mode);
+    }
+    assert_eq!(parse_filter("bicubic").unwrap(), FilterType::CatmullRom);
+    assert!(parse_filter("sinc").is_err());
+    assert_eq!(parse_resolution("2560x1440").unwrap(), (2560, 1440));
+    assert!(parse_resolution("2560").is_err());
+    assert!(parse_resolution("0x1440").is_err());
+}
+
+#[test]
+fn test_fit_modes_produce_display_size() {
+    let frame = halves(64, 36);
+    for mode in [
+        FitMode::Cover,
+        FitMode::Contain,
+        FitMode::Stretch,
+        FitMode::Smart,
+    ] {
+        let fitted = fit(&frame, 30, 40, mode, FilterType::Triangle, None);
+        assert_eq!(fitted.dimensions(), (30, 40), "{:?}", mode);
+    }
+}
+
+#[test]
+fn test_cover_keeps_the_middle_or_the_preferred_crop() {
+    let frame = halves(64, 32);
+
+    // A square out of the middle of a 2:1 frame still has both halves
+    let middle = fit(&frame, 16, 16, FitMode::Cover, FilterType::Nearest, None);
+    assert_eq!(middle.get_pixel(0, 8).0, [255, 0, 0]);
+    assert_eq!(middle.get_pixel(15, 8).0, [0, 0, 255]);
+
+    let crop = Crop {
+        x: 0.75,
+        y: 0.0,
+        width: 0.25,
+        height: 1.0,
+    };
+    let right = fit(
+        &frame,
+        16,
+        16,
+        FitMode::Cover,
+        FilterType::Nearest,
+        Some(crop),
+    );
+    assert!(right.pixels().all(|pixel| pixel.0 == [0, 0, 255]));
+}
+
+#[test]
+fn test_contain_fills_around_the_frame() {
+    let frame = RgbImage::from_pixel(40, 20, Rgb([200, 200, 200]));
+    let contained = fit(&frame, 40, 40, FitMode::Contain, FilterType::Triangle, None);
+
+    // The whole frame is in the middle, the bands above and below are filled in
+    assert_eq!(contained.get_pixel(20, 20).0, [200, 200, 200]);
+    assert!(contained.get_pixel(20, 2).0.iter().all(|&c| c > 150));
+}
+
+#[test]
+fn test_smart_crop_finds_detail() {
+    // Flat on the left, a checkerboard on the right
+    let frame = RgbImage::from_fn(120, 40, |x, y| {
+        if x >= 80 && (x + y) % 2 == 0 {
+            Rgb([255, 255, 255])
+        } else {
+            Rgb([0, 0, 0])
+        }
+    });
+
+    let (x, y) = interesting_center(&frame, 40, 40);
+    assert!(x > 0.7, "center at {}", x);
+    assert_eq!(y, 0.5);
+}
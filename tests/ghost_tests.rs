@@ -0,0 +1,67 @@
+use citycam::ghost::{composite_frames, GhostMode};
+use image::{Rgb, RgbImage};
+
+fn solid(width: u32, height: u32, color: [u8; 3]) -> RgbImage {
+    RgbImage::from_pixel(width, height, Rgb(color))
+}
+
+#[test]
+fn test_ghost_mode_parse() {
+    assert_eq!(GhostMode::parse("mean").unwrap(), GhostMode::Mean);
+    assert_eq!(GhostMode::parse("MAX").unwrap(), GhostMode::Max);
+    assert_eq!(
+        GhostMode::parse("trail@0.5").unwrap(),
+        GhostMode::Trail { decay: 0.5 }
+    );
+    assert_eq!(
+        GhostMode::parse("median").unwrap(),
+        GhostMode::Median { opacity: 0.5 }
+    );
+    assert!(GhostMode::parse("blur").is_err());
+    assert!(GhostMode::parse("trail@fast").is_err());
+}
+
+#[test]
+fn test_mean_and_max_blend() {
+    let frames = vec![solid(4, 4, [0, 100, 200]), solid(4, 4, [100, 200, 0])];
+
+    let mean = composite_frames(&frames, GhostMode::Mean).unwrap();
+    assert_eq!(mean.get_pixel(0, 0).0, [50, 150, 100]);
+
+    let max = composite_frames(&frames, GhostMode::Max).unwrap();
+    assert_eq!(max.get_pixel(0, 0).0, [100, 200, 200]);
+}
+
+#[test]
+fn test_trail_favors_newest_frame() {
+    let frames = vec![solid(2, 2, [0, 0, 0]), solid(2, 2, [200, 200, 200])];
+
+    let trail = composite_frames(&frames, GhostMode::Trail { decay: 0.25 }).unwrap();
+
+    // Weights are 0.25 for the old frame and 1.0 for the new one
+    assert_eq!(trail.get_pixel(0, 0).0, [160, 160, 160]);
+}
+
+#[test]
+fn test_median_keeps_background_and_ghosts_movers() {
+    let background = solid(3, 1, [50, 50, 50]);
+    let mut mover = background.clone();
+    mover.put_pixel(1, 0, Rgb([250, 250, 250]));
+    let frames = vec![background.clone(), mover, background];
+
+    let result = composite_frames(&frames, GhostMode::Median { opacity: 0.5 }).unwrap();
+
+    assert_eq!(result.get_pixel(0, 0).0, [50, 50, 50], "Static pixels stay");
+    assert_eq!(
+        result.get_pixel(1, 0).0,
+        [150, 150, 150],
+        "Movers are ghosted"
+    );
+}
+
+#[test]
+fn test_composite_rejects_mismatched_frames() {
+    let frames = vec![solid(2, 2, [0, 0, 0]), solid(3, 2, [0, 0, 0])];
+    assert!(composite_frames(&frames, GhostMode::Mean).is_err());
+    assert!(composite_frames(&[], GhostMode::Mean).is_err());
+}
@@ -0,0 +1,102 @@
+use chrono::{Duration, TimeZone, Utc};
+use citycam::camera::{Camera, CameraKind};
+use citycam::health::{format_table, HealthTracker};
+
+fn camera(name: &str) -> Camera {
+    Camera {
+        name: name.to_string(),
+        url: format!("https://example.com/{}.jpg", name),
+        kind: CameraKind::Snapshot,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_backoff_doubles_and_is_capped() {
+    let start = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+    let mut tracker = HealthTracker::default();
+
+    tracker.record_failure("dead", "timeout", start);
+    let health = tracker.get("dead").unwrap();
+    assert_eq!(health.retry_at(), Some(start + Duration::minutes(1)));
+    assert!(!tracker.is_available("dead", start + Duration::seconds(30)));
+    assert!(tracker.is_available("dead", start + Duration::minutes(1)));
+
+    tracker.record_failure("dead", "timeout", start);
+    tracker.record_failure("dead", "timeout", start);
+    assert_eq!(
+        tracker.get("dead").unwrap().retry_at(),
+        Some(start + Duration::minutes(4))
+    );
+
+    for _ in 0..40 {
+        tracker.record_failure("dead", "timeout", start);
+    }
+    assert_eq!(
+        tracker.get("dead").unwrap().retry_at(),
+        Some(start + Duration::hours(6))
+    );
+
+    // Unknown cameras are always worth a try
+    assert!(tracker.is_available("new", start));
+}
+
+#[test]
+fn test_success_resets_failures_and_averages_latency() {
+    let now = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+    let mut tracker = HealthTracker::default();
+
+    tracker.record_failure("cam", "404", now);
+    tracker.record_success("cam", std::time::Duration::from_millis(1000), now);
+    tracker.record_success("cam", std::time::Duration::from_millis(2000), now);
+
+    let health = tracker.get("cam").unwrap();
+    assert_eq!(health.consecutive_failures, 0);
+    assert_eq!(health.last_success, Some(now));
+    assert!(health.retry_at().is_none());
+    let average = health.average_latency_ms.unwrap();
+    assert!(average > 1000.0 && average < 2000.0);
+}
+
+#[test]
+fn test_health_persists() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("health.json");
+    let now = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+
+    let mut tracker = HealthTracker::load(&path);
+    assert!(tracker.cameras.is_empty());
+    tracker.record_failure("cam", "connection refused", now);
+    tracker.save(&path).unwrap();
+
+    assert_eq!(HealthTracker::load(&path), tracker);
+}
+
+#[test]
+fn test_health_table() {
+    let now = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+    let mut tracker = HealthTracker::default();
+    tracker.record_success("Harbor", std::time::Duration::from_millis(850), now);
+    tracker.record_failure("Pier", "HTTP 503", now);
+    tracker.record_failure("Removed", "gone", now);
+
+    let table = format_table(
+        &tracker,
+        &[camera("Harbor"), camera("Pier"), camera("Lake")],
+        now,
+    );
+    let lines: Vec<&str> = table.lines().collect();
+
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with("CAMERA"));
+    assert!(
+        lines[1].starts_with("Harbor") && lines[1].contains("ok") && lines[1].contains("850 ms")
+    );
+    assert!(
+        lines[2].starts_with("Pier")
+            && lines[2].contains("backoff")
+            && lines[2].contains("HTTP 503")
+    );
+    assert!(lines[3].starts_with("Lake") && lines[3].contains("unknown"));
+    assert!(lines[4].starts_with("Removed"));
+}
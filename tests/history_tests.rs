@@ -0,0 +1,144 @@
+use chrono::{Duration, TimeZone, Utc};
+use citycam::history::{self, HistoryEntry, HistoryFilter};
+use std::fs;
+use std::path::Path;
+
+fn entry(dir: &Path, id: &str, camera: &str, pipeline: &str, hour: u32) -> HistoryEntry {
+    let captured_at = Utc.with_ymd_and_hms(2025, 3, 10, hour, 0, 0).unwrap();
+    HistoryEntry {
+        url: "https://example.com/cam".to_string(),
+        segment: Some("https://example.com/cam/seg-100.ts".to_string()),
+        saved_at: captured_at + Duration::minutes(1),
+        pipeline: pipeline.to_string(),
+        width: 1920,
+        height: 1080,
+        ..HistoryEntry::new(camera, &dir.join(format!("{}.jpg", id)), Some(captured_at))
+    }
+}
+
+#[test]
+fn test_record_and_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let index = dir.path().join(history::INDEX_FILE_NAME);
+    let first = entry(
+        dir.path(),
+        "20250310-100000",
+        "Ambassador Bridge",
+        "grayscale",
+        10,
+    );
+    let second = HistoryEntry {
+        output: Some("DP-1".to_string()),
+        original: Some(dir.path().join("originals/20250310-110000-DP-1.jpg")),
+        ..entry(dir.path(), "20250310-110000-DP-1", "Renaissance", "sky", 11)
+    };
+
+    assert!(history::load(&index).unwrap().is_empty());
+    history::record(&index, &first).unwrap();
+    history::record(&index, &second).unwrap();
+    fs::write(
+        &index,
+        fs::read_to_string(&index).unwrap() + "not an entry\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        history::load(&index).unwrap(),
+        [first.clone(), second.clone()]
+    );
+    let sidecar = fs::read_to_string(dir.path().join("20250310-100000.json")).unwrap();
+    assert_eq!(
+        serde_json::from_str::<HistoryEntry>(&sidecar).unwrap(),
+        first
+    );
+
+    // Only wallpapers with a kept original, and recorded frames, can be rendered again
+    assert_eq!(first.source_frame(), None);
+    assert_eq!(second.source_frame(), second.original.as_deref());
+    let recorded = HistoryEntry {
+        recorded: true,
+        ..first
+    };
+    assert_eq!(recorded.source_frame(), Some(recorded.path.as_path()));
+}
+
+#[test]
+fn test_filter_and_find() {
+    let dir = Path::new("/cache");
+    let entries = [
+        entry(dir, "20250310-100000", "Ambassador Bridge", "grayscale", 10),
+        entry(
+            dir,
+            "20250310-110000",
+            "Renaissance Center",
+            "sky,tint:#ff5500@0.3",
+            11,
+        ),
+        entry(dir, "20250310-120000", "Ambassador Bridge", "", 12),
+    ];
+    let ids = |filter: &HistoryFilter| -> Vec<String> {
+        entries
+            .iter()
+            .filter(|e| filter.matches(e))
+            .map(|e| e.id())
+            .collect()
+    };
+
+    let bridge = HistoryFilter {
+        camera: Some("bridge".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ids(&bridge), ["20250310-100000", "20250310-120000"]);
+
+    let tinted = HistoryFilter {
+        text: Some("TINT".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ids(&tinted), ["20250310-110000"]);
+
+    let morning = HistoryFilter {
+        since: Some(Utc.with_ymd_and_hms(2025, 3, 10, 10, 30, 0).unwrap()),
+        until: Some(Utc.with_ymd_and_hms(2025, 3, 10, 11, 30, 0).unwrap()),
+        ..Default::default()
+    };
+    assert_eq!(ids(&morning), ["20250310-110000"]);
+
+    let recorded = HistoryFilter {
+        recorded: Some(true),
+        ..Default::default()
+    };
+    assert!(ids(&recorded).is_empty());
+
+    assert_eq!(
+        history::find(&entries, "latest").unwrap().id(),
+        "20250310-120000"
+    );
+    assert_eq!(
+        history::find(&entries, "20250310-11").unwrap().id(),
+        "20250310-110000"
+    );
+    assert!(history::find(&entries, "2024").is_err());
+    assert!(history::find(&[], "latest").is_err());
+}
+
+#[test]
+fn test_forget_missing() {
+    let dir = tempfile::tempdir().unwrap();
+    let index = dir.path().join(history::INDEX_FILE_NAME);
+    let kept = entry(dir.path(), "20250310-110000", "Renaissance", "", 11);
+    let gone = entry(dir.path(), "20250310-100000", "Renaissance", "", 10);
+    fs::write(&kept.path, b"frame").unwrap();
+    history::record(&index, &gone).unwrap();
+    // Written by something newer or cut short, not for this version to throw away
+    let mut content = fs::read_to_string(&index).unwrap();
+    content.push_str("{\"camera\": \"Renaiss\n");
+    fs::write(&index, content).unwrap();
+    history::record(&index, &kept).unwrap();
+
+    assert_eq!(history::forget_missing(&index).unwrap(), 1);
+    assert_eq!(history::load(&index).unwrap(), [kept]);
+    let content = fs::read_to_string(&index).unwrap();
+    assert!(content.starts_with("{\"camera\": \"Renaiss\n"));
+    assert_eq!(content.lines().count(), 2);
+    assert_eq!(history::forget_missing(&index).unwrap(), 0);
+}
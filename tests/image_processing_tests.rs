@@ -1,5 +1,6 @@
 use citycam::image_processing::{
-    add_gaussian_noise_to_rgb, add_salt_and_pepper_noise_to_rgb, convert_grayscale_to_rgb,
+    add_gaussian_noise_to_rgb, add_salt_and_pepper_noise_to_rgb, apply_neon_over_grayscale,
+    convert_grayscale_to_rgb, exposure_score, hex_to_rgb, laplacian_variance, pick_best_frame,
 };
 use image::{GrayImage, Rgb, RgbImage};
 
@@ -80,3 +81,103 @@ fn test_salt_and_pepper_noise() {
     assert!(pepper_count > 0, "Should have some pepper pixels");
     assert!(salt_count + pepper_count > 0, "Should have noise applied");
 }
+
+#[test]
+fn test_neon_keeps_saturated_lights_and_grays_the_rest() {
+    let mut rgb_img = RgbImage::new(10, 10);
+    for x in 0..10 {
+        for y in 0..10 {
+            rgb_img.put_pixel(x, y, Rgb([120, 110, 100]));
+        }
+    }
+    // A bright red sign and a bright blue window
+    rgb_img.put_pixel(2, 2, Rgb([250, 30, 40]));
+    rgb_img.put_pixel(7, 7, Rgb([30, 60, 250]));
+
+    // Only keep reds, no glow so neighbours stay untouched
+    let neon_img = apply_neon_over_grayscale(&rgb_img, &[(330.0, 30.0)], 0.5, 0.0);
+
+    let sign = neon_img.get_pixel(2, 2);
+    assert!(
+        sign.0[0] > 200 && sign.0[1] < 60,
+        "Red sign should stay red"
+    );
+
+    let window = neon_img.get_pixel(7, 7);
+    assert_eq!(
+        window.0[0], window.0[2],
+        "Blue outside hue range should be gray"
+    );
+
+    let wall = neon_img.get_pixel(5, 5);
+    assert_eq!(wall.0[0], wall.0[1], "Dull pixels should be gray");
+    assert_eq!(wall.0[1], wall.0[2], "Dull pixels should be gray");
+}
+
+#[test]
+fn test_neon_glow_spreads_color() {
+    let mut rgb_img = RgbImage::new(15, 15);
+    for x in 0..15 {
+        for y in 0..15 {
+            rgb_img.put_pixel(x, y, Rgb([40, 40, 40]));
+        }
+    }
+    rgb_img.put_pixel(7, 7, Rgb([255, 0, 200]));
+
+    let neon_img = apply_neon_over_grayscale(&rgb_img, &[], 0.5, 2.0);
+
+    let neighbour = neon_img.get_pixel(8, 7);
+    assert!(
+        neighbour.0[0] > neighbour.0[1],
+        "Glow should tint pixels next to the light source"
+    );
+}
+
+#[test]
+fn test_best_frame_prefers_sharp_well_exposed_frames() {
+    // Checkerboard is sharp, flat gray is blurry, near-black checkerboard is underexposed
+    let mut sharp = RgbImage::new(16, 16);
+    let mut dark = RgbImage::new(16, 16);
+    for x in 0..16 {
+        for y in 0..16 {
+            let on = (x + y) % 2 == 0;
+            sharp.put_pixel(
+                x,
+                y,
+                if on {
+                    Rgb([200, 200, 200])
+                } else {
+                    Rgb([60, 60, 60])
+                },
+            );
+            dark.put_pixel(
+                x,
+                y,
+                if on {
+                    Rgb([12, 12, 12])
+                } else {
+                    Rgb([0, 0, 0])
+                },
+            );
+        }
+    }
+    let blurry = RgbImage::from_pixel(16, 16, Rgb([128, 128, 128]));
+
+    assert!(laplacian_variance(&sharp) > laplacian_variance(&blurry));
+    assert!(exposure_score(&blurry) > exposure_score(&dark));
+
+    let best = pick_best_frame(vec![blurry, sharp.clone(), dark]).unwrap();
+    assert_eq!(best, sharp, "Sharp, well exposed frame should win");
+    assert!(pick_best_frame(Vec::new()).is_none());
+}
+
+#[test]
+fn test_hex_to_rgb() {
+    assert_eq!(hex_to_rgb("#ff5500"), Some([255, 85, 0]));
+    assert_eq!(hex_to_rgb("7DFFB0"), Some([125, 255, 176]));
+    assert_eq!(hex_to_rgb("fff"), None);
+    assert_eq!(hex_to_rgb("#ff55"), None);
+    assert_eq!(hex_to_rgb("#gg5500"), None);
+    assert_eq!(hex_to_rgb("#ff5500ff"), None);
+    assert_eq!(hex_to_rgb("#ff5é0"), None);
+}
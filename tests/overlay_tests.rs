@@ -0,0 +1,71 @@
+use citycam::overlay::{draw_alien_beams, draw_label};
+use image::{Rgb, RgbImage};
+
+// Bright sky over the top half, dark city below
+fn city_scene() -> RgbImage {
+    let mut img = RgbImage::new(80, 60);
+    for y in 0..60 {
+        for x in 0..80 {
+            let value = if y < 30 { 180 } else { 30 };
+            img.put_pixel(x, y, Rgb([value, value, value]));
+        }
+    }
+    img
+}
+
+#[test]
+fn test_alien_beams_are_reproducible_with_seed() {
+    let img = city_scene();
+    let beam_color = Rgb([125, 255, 176]);
+
+    let first = draw_alien_beams(&img, beam_color, 3, 42);
+    let second = draw_alien_beams(&img, beam_color, 3, 42);
+    let other = draw_alien_beams(&img, beam_color, 3, 7);
+
+    assert_eq!(first, second, "Same seed should give the same scene");
+    assert_ne!(first, other, "Different seeds should move the scene");
+}
+
+#[test]
+fn test_alien_beams_light_up_the_skyline() {
+    let img = city_scene();
+
+    let result = draw_alien_beams(&img, Rgb([0, 255, 0]), 4, 1);
+
+    assert_eq!(result.dimensions(), img.dimensions());
+
+    // Beams end just below the skyline, so some city pixels there turn green
+    let lit = (0..80)
+        .filter(|&x| {
+            let pixel = result.get_pixel(x, 32);
+            pixel.0[1] > pixel.0[0] + 20
+        })
+        .count();
+    assert!(lit > 0, "Beams should reach down onto the skyline");
+
+    // Nothing gets darker except the saucer hull in the sky
+    let darkened_city = (32..60)
+        .flat_map(|y| (0..80).map(move |x| (x, y)))
+        .filter(|&(x, y)| result.get_pixel(x, y).0[1] < img.get_pixel(x, y).0[1])
+        .count();
+    assert_eq!(darkened_city, 0, "Light should only brighten the city");
+}
+
+#[test]
+fn test_label_darkens_corner_and_writes_text() {
+    let mut img = RgbImage::from_pixel(200, 100, Rgb([90, 90, 90]));
+    draw_label(&mut img, "12:30");
+
+    assert_eq!(img.get_pixel(0, 0), &Rgb([90, 90, 90]));
+    let corner: Vec<&Rgb<u8>> = (60..200)
+        .flat_map(|x| (80..100).map(move |y| (x, y)))
+        .map(|(x, y)| img.get_pixel(x, y))
+        .collect();
+    assert!(corner.contains(&&Rgb([30, 30, 30])));
+    assert!(corner.contains(&&Rgb([255, 255, 255])));
+
+    // Too small to hold the text, left alone
+    let mut tiny = RgbImage::from_pixel(10, 10, Rgb([90, 90, 90]));
+    draw_label(&mut tiny, "2025-03-10 12:30");
+    assert!(tiny.pixels().all(|p| p == &Rgb([90, 90, 90])));
+}
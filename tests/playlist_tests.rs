@@ -0,0 +1,197 @@
+use chrono::NaiveTime;
+use citycam::camera::{Camera, CameraKind};
+use citycam::playlist::{Playlist, Rotation, RotationState, Strategy};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashSet;
+
+fn cameras(names: &[&str]) -> Vec<Camera> {
+    names
+        .iter()
+        .map(|name| Camera {
+            name: name.to_string(),
+            url: format!("https://example.com/{}.m3u8", name),
+            kind: CameraKind::Hls,
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn noon() -> NaiveTime {
+    NaiveTime::from_hms_opt(12, 0, 0).unwrap()
+}
+
+fn names(rotation: &mut Rotation, count: usize, now: NaiveTime, rng: &mut StdRng) -> Vec<String> {
+    (0..count)
+        .map(|_| rotation.advance(now, rng).unwrap().name.clone())
+        .collect()
+}
+
+#[test]
+fn test_strategy_parse() {
+    assert_eq!(Strategy::parse("Shuffle").unwrap(), Strategy::Shuffle);
+    assert_eq!(Strategy::parse("weighted").unwrap(), Strategy::Weighted);
+    assert!(Strategy::parse("backwards").is_err());
+
+    for strategy in [
+        Strategy::Sequential,
+        Strategy::Random,
+        Strategy::Shuffle,
+        Strategy::Weighted,
+    ] {
+        assert_eq!(Strategy::parse(&strategy.to_string()).unwrap(), strategy);
+    }
+}
+
+#[test]
+fn test_sequential_wraps_around() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut rotation = Rotation::new(cameras(&["a", "b", "c"]));
+
+    assert_eq!(
+        names(&mut rotation, 4, noon(), &mut rng),
+        ["a", "b", "c", "a"]
+    );
+}
+
+#[test]
+fn test_random_never_repeats_back_to_back() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut rotation = Rotation::new(cameras(&["a", "b", "c"]))
+        .with_state(RotationState::default(), Strategy::Random);
+
+    let picks = names(&mut rotation, 50, noon(), &mut rng);
+    assert!(picks.windows(2).all(|pair| pair[0] != pair[1]));
+}
+
+#[test]
+fn test_shuffle_shows_every_camera_once_per_round() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let mut rotation = Rotation::new(cameras(&["a", "b", "c", "d"]))
+        .with_state(RotationState::default(), Strategy::Shuffle);
+
+    let picks = names(&mut rotation, 12, noon(), &mut rng);
+    for round in picks.chunks(4) {
+        let unique: HashSet<_> = round.iter().collect();
+        assert_eq!(unique.len(), 4, "round {:?} repeats a camera", round);
+    }
+    assert!(picks.windows(2).all(|pair| pair[0] != pair[1]));
+}
+
+#[test]
+fn test_weighted_follows_weights() {
+    let mut rng = StdRng::seed_from_u64(11);
+    let mut list = cameras(&["rare", "common", "never"]);
+    list[0].weight = Some(1.0);
+    list[1].weight = Some(9.0);
+    list[2].weight = Some(0.0);
+    let mut rotation = Rotation::new(list).with_state(RotationState::default(), Strategy::Weighted);
+
+    let picks = names(&mut rotation, 1000, noon(), &mut rng);
+    let common = picks.iter().filter(|name| *name == "common").count();
+
+    assert!(!picks.iter().any(|name| name == "never"));
+    assert!(
+        (850..=950).contains(&common),
+        "common picked {} times",
+        common
+    );
+}
+
+#[test]
+fn test_playlist_windows() {
+    let morning = Playlist::parse("05:30-09:00=harbor,2").unwrap();
+    assert!(morning.is_active(NaiveTime::from_hms_opt(6, 0, 0).unwrap()));
+    assert!(!morning.is_active(NaiveTime::from_hms_opt(9, 0, 0).unwrap()));
+
+    let night = Playlist::parse("21:00-05:00=downtown").unwrap();
+    assert!(night.is_active(NaiveTime::from_hms_opt(23, 30, 0).unwrap()));
+    assert!(night.is_active(NaiveTime::from_hms_opt(1, 0, 0).unwrap()));
+    assert!(!night.is_active(noon()));
+
+    let lakes = Playlist::parse("06:00-08:00=region:41.7,-90.4,48.3,-82.1,tag:lake,2").unwrap();
+    assert_eq!(
+        lakes.cameras,
+        ["region:41.7,-90.4,48.3,-82.1", "tag:lake", "2"]
+    );
+
+    assert!(Playlist::parse("05:30-09:00").is_err());
+    assert!(Playlist::parse("5-9=harbor").is_err());
+    assert!(Playlist::parse("05:30-09:00= ,").is_err());
+}
+
+#[test]
+fn test_rotation_limited_to_active_playlist() {
+    let mut rng = StdRng::seed_from_u64(5);
+    let mut rotation = Rotation::new(cameras(&["Harbor East", "Pier", "Downtown"]))
+        .with_playlists(vec![Playlist::parse("05:00-09:00=harbor,2").unwrap()]);
+
+    let dawn = NaiveTime::from_hms_opt(6, 0, 0).unwrap();
+    assert_eq!(
+        names(&mut rotation, 3, dawn, &mut rng),
+        ["Harbor East", "Pier", "Harbor East"]
+    );
+
+    // Outside the window every camera is back in the rotation
+    assert_eq!(
+        names(&mut rotation, 2, noon(), &mut rng),
+        ["Pier", "Downtown"]
+    );
+}
+
+#[test]
+fn test_rotation_state_persists() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rotation.json");
+    let mut rng = StdRng::seed_from_u64(9);
+
+    let mut rotation = Rotation::new(cameras(&["a", "b", "c", "d"]))
+        .with_state(RotationState::load(&path), Strategy::Shuffle);
+    let first = names(&mut rotation, 2, noon(), &mut rng);
+    rotation.state().save(&path).unwrap();
+
+    let saved = RotationState::load(&path);
+    assert_eq!(saved.strategy, Strategy::Shuffle);
+    assert_eq!(saved.current.as_deref(), Some(first[1].as_str()));
+    assert_eq!(saved.remaining.len(), 2);
+
+    // The restarted rotation finishes the round instead of starting over
+    let mut restarted =
+        Rotation::new(cameras(&["a", "b", "c", "d"])).with_state(saved.clone(), Strategy::Shuffle);
+    let rest = names(&mut restarted, 2, noon(), &mut rng);
+    let round: HashSet<_> = first.iter().chain(&rest).collect();
+    assert_eq!(round.len(), 4);
+
+    // Switching strategy keeps the current camera but drops the shuffle round
+    let switched = Rotation::new(cameras(&["a", "b", "c", "d"]))
+        .with_state(saved.clone(), Strategy::Sequential);
+    assert_eq!(switched.state().current, saved.current);
+    assert!(switched.state().remaining.is_empty());
+
+    assert_eq!(
+        RotationState::load(&dir.path().join("missing.json")),
+        RotationState::default()
+    );
+}
+
+#[test]
+fn test_rotation_skips_unavailable_cameras() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let mut rotation = Rotation::new(cameras(&["a", "b", "c", "d"]));
+    let alive = |camera: &Camera| camera.name != "b" && camera.name != "c";
+
+    let picks: Vec<String> = (0..3)
+        .map(|_| {
+            rotation
+                .advance_available(noon(), &mut rng, alive)
+                .unwrap()
+                .name
+                .clone()
+        })
+        .collect();
+    assert_eq!(picks, ["a", "d", "a"]);
+
+    // With every camera down they are all tried again rather than none
+    let picked = rotation.advance_available(noon(), &mut rng, |_| false);
+    assert_eq!(picked.unwrap().name, "b");
+}
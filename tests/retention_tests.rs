@@ -0,0 +1,194 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use citycam::retention::{cached_files, prune, CachedFile, RetentionPolicy};
+use std::fs;
+use std::path::Path;
+
+fn now() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2025, 3, 10)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap()
+}
+
+// A wallpaper saved `minutes` ago
+fn file(minutes: i64, size: u64) -> CachedFile {
+    let saved = now() - Duration::minutes(minutes);
+    let name = format!("{}.jpg", saved.format("%Y%m%d-%H%M%S"));
+    CachedFile::from_path(Path::new(&name), size).unwrap()
+}
+
+// A wallpaper made for one monitor `minutes` ago
+fn file_on(output: &str, minutes: i64, size: u64) -> CachedFile {
+    let saved = now() - Duration::minutes(minutes);
+    let name = format!("{}-{}.jpg", saved.format("%Y%m%d-%H%M%S"), output);
+    CachedFile::from_path(Path::new(&name), size).unwrap()
+}
+
+fn expired_ages(policy: &RetentionPolicy, files: &[CachedFile]) -> Vec<i64> {
+    policy
+        .expired(files, now())
+        .iter()
+        .map(|file| (now() - file.saved).num_minutes())
+        .collect()
+}
+
+#[test]
+fn test_cached_file_names() {
+    let file = CachedFile::from_path(Path::new("/c/20250310-114500-DP-1.jpg"), 10).unwrap();
+    assert_eq!(file.output.as_deref(), Some("DP-1"));
+    assert_eq!(file.saved.format("%H:%M").to_string(), "11:45");
+
+    let copy = CachedFile::from_path(Path::new("/c/20250310-114500_2-DP-1.jpg"), 10).unwrap();
+    assert_eq!(copy.output.as_deref(), Some("DP-1"));
+    assert_eq!(copy.saved, file.saved);
+    let copy = CachedFile::from_path(Path::new("/c/20250310-114500_12.jpg"), 10).unwrap();
+    assert_eq!(copy.output, None);
+    assert!(CachedFile::from_path(Path::new("/c/20250310-114500_x.jpg"), 10).is_none());
+
+    assert!(CachedFile::from_path(Path::new("/c/20250310-114500.JPG"), 10).is_some());
+    assert!(CachedFile::from_path(Path::new("/c/20250310-114500.avif"), 10).is_some());
+    assert!(CachedFile::from_path(Path::new("/c/health.json"), 10).is_none());
+    assert!(CachedFile::from_path(Path::new("/c/holiday.jpg"), 10).is_none());
+    assert!(CachedFile::from_path(Path::new("/c/20250310-114500x.jpg"), 10).is_none());
+}
+
+#[test]
+fn test_keep_last_and_size() {
+    let files: Vec<CachedFile> = (0..5).map(|i| file(i * 10, 100)).collect();
+
+    let last_two = RetentionPolicy {
+        keep_last: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(expired_ages(&last_two, &files), [20, 30, 40]);
+
+    let size = RetentionPolicy {
+        max_bytes: Some(350),
+        ..Default::default()
+    };
+    assert_eq!(expired_ages(&size, &files), [30, 40]);
+
+    assert!(RetentionPolicy::default().expired(&files, now()).is_empty());
+}
+
+#[test]
+fn test_keep_age_and_hourly() {
+    // Every 20 minutes over the last five hours
+    let files: Vec<CachedFile> = (0..15).map(|i| file(i * 20, 100)).collect();
+
+    let age = RetentionPolicy {
+        max_age: Some(Duration::hours(1)),
+        ..Default::default()
+    };
+    assert_eq!(
+        expired_ages(&age, &files),
+        (4..15).map(|i| i * 20).collect::<Vec<_>>()
+    );
+
+    let hourly = RetentionPolicy {
+        hourly_after: Some(Duration::hours(2)),
+        ..Default::default()
+    };
+    let expired = expired_ages(&hourly, &files);
+    let kept: Vec<&CachedFile> = files
+        .iter()
+        .filter(|f| !expired.contains(&(now() - f.saved).num_minutes()))
+        .collect();
+    let old_hours: Vec<String> = kept
+        .iter()
+        .filter(|f| now() - f.saved > Duration::hours(2))
+        .map(|f| f.saved.format("%H").to_string())
+        .collect();
+    // One per hour from 07:00 to 09:00, none of the recent ones dropped
+    assert_eq!(old_hours, ["09", "08", "07"]);
+    assert!(expired.iter().all(|&minutes| minutes > 120));
+}
+
+#[test]
+fn test_newest_is_always_kept() {
+    let files = vec![file(60 * 24 * 30, 5000)];
+    let policy = RetentionPolicy {
+        keep_last: Some(0),
+        max_bytes: Some(1),
+        max_age: Some(Duration::days(1)),
+        ..Default::default()
+    };
+    assert!(policy.expired(&files, now()).is_empty());
+}
+
+#[test]
+fn test_newest_of_every_output_is_kept() {
+    let files = vec![
+        file_on("DP-1", 10, 100),
+        file_on("DP-1", 20, 100),
+        file_on("HDMI-A-1", 30, 100),
+        file_on("HDMI-A-1", 40, 100),
+    ];
+    let policy = RetentionPolicy {
+        keep_last: Some(1),
+        ..Default::default()
+    };
+    let expired: Vec<(&str, i64)> = policy
+        .expired(&files, now())
+        .iter()
+        .map(|f| {
+            let minutes = (now() - f.saved).num_minutes();
+            (f.output.as_deref().unwrap(), minutes)
+        })
+        .collect();
+    assert_eq!(expired, [("DP-1", 20), ("HDMI-A-1", 40)]);
+}
+
+#[test]
+fn test_hour_taken_only_by_kept_wallpapers() {
+    // The big one at 08:40 doesn't fit, so the 08:20 one is the one left for the hour
+    let files = vec![file(0, 100), file(200, 200), file(220, 100)];
+    let policy = RetentionPolicy {
+        max_bytes: Some(250),
+        hourly_after: Some(Duration::hours(1)),
+        ..Default::default()
+    };
+    assert_eq!(expired_ages(&policy, &files), [200]);
+}
+
+#[test]
+fn test_prune_deletes_files() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in [
+        "20250310-100000.jpg",
+        "20250310-110000.jpg",
+        "20250310-115000.jpg",
+        "20250310-100000.json",
+        "rotation.json",
+    ] {
+        fs::write(dir.path().join(name), b"frame").unwrap();
+    }
+    let originals = dir.path().join("originals");
+    fs::create_dir(&originals).unwrap();
+    for name in ["20250310-100000.jpg", "20250310-110000.jpg"] {
+        fs::write(originals.join(name), b"original").unwrap();
+    }
+    let policy = RetentionPolicy {
+        keep_last: Some(2),
+        ..Default::default()
+    };
+
+    let dry_run = prune(dir.path(), &policy, now(), true).unwrap();
+    assert_eq!(dry_run.len(), 1);
+    assert!(dir.path().join("20250310-100000.jpg").exists());
+
+    prune(dir.path(), &policy, now(), false).unwrap();
+    assert!(!dir.path().join("20250310-100000.jpg").exists());
+    assert!(!dir.path().join("20250310-100000.json").exists());
+    assert!(!originals.join("20250310-100000.jpg").exists());
+    assert!(originals.join("20250310-110000.jpg").exists());
+    assert!(dir.path().join("rotation.json").exists());
+
+    // Originals count towards the size of their wallpaper
+    let sizes: Vec<u64> = cached_files(dir.path())
+        .unwrap()
+        .iter()
+        .map(|file| file.size)
+        .collect();
+    assert_eq!(sizes, [13, 5]);
+}
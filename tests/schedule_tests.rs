@@ -0,0 +1,176 @@
+use chrono::{Duration, NaiveDate, TimeZone, Timelike, Utc};
+use citycam::schedule::{parse_duration, CronExpr, Schedule, Trigger};
+use citycam::sun::{parse_datetime, sun_event, sun_position, SkyPhase, SunEvent};
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("90").unwrap(), Duration::seconds(90));
+    assert_eq!(parse_duration("15m").unwrap(), Duration::minutes(15));
+    assert_eq!(parse_duration("1h30m").unwrap(), Duration::minutes(90));
+    assert_eq!(parse_duration("1d").unwrap(), Duration::days(1));
+    assert!(parse_duration("").is_err());
+    assert!(parse_duration("10x").is_err());
+    assert!(parse_duration("m").is_err());
+    assert!(parse_duration("0s").is_err());
+    assert!(parse_duration("99999999999999999").is_err());
+    assert!(parse_duration("99999999999999d").is_err());
+    assert!(parse_duration("100000000d100000000d").is_ok());
+    assert!(parse_duration("9223372036854775807d").is_err());
+}
+
+#[test]
+fn test_every_trigger() {
+    let schedule = Schedule::parse("every:15m").unwrap();
+    let now = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+
+    assert_eq!(schedule.next_after(&now), Some(now + Duration::minutes(15)));
+    assert_eq!(Schedule::parse("15m").unwrap(), schedule);
+
+    // Past the end of the calendar there is no next time
+    let far = Schedule::parse("every:100000000d").unwrap();
+    assert_eq!(far.next_after(&now), None);
+    assert_eq!(
+        Schedule::parse("sunset+100000000d@52.37,4.9")
+            .unwrap()
+            .next_after(&now),
+        None
+    );
+}
+
+#[test]
+fn test_cron_steps_and_ranges() {
+    let expr = CronExpr::parse("*/10 7-22 * * *").unwrap();
+
+    let now = Utc.with_ymd_and_hms(2025, 3, 1, 12, 3, 20).unwrap();
+    assert_eq!(
+        expr.next_after(&now),
+        Some(Utc.with_ymd_and_hms(2025, 3, 1, 12, 10, 0).unwrap())
+    );
+
+    // Strictly after, even when sitting on a matching minute
+    let on_match = Utc.with_ymd_and_hms(2025, 3, 1, 12, 10, 0).unwrap();
+    assert_eq!(
+        expr.next_after(&on_match),
+        Some(Utc.with_ymd_and_hms(2025, 3, 1, 12, 20, 0).unwrap())
+    );
+
+    let late = Utc.with_ymd_and_hms(2025, 3, 1, 22, 55, 0).unwrap();
+    assert_eq!(
+        expr.next_after(&late),
+        Some(Utc.with_ymd_and_hms(2025, 3, 2, 7, 0, 0).unwrap())
+    );
+}
+
+#[test]
+fn test_cron_day_of_week() {
+    // 2025-03-01 is a Saturday, so the next weekday morning is Monday the 3rd
+    let expr = CronExpr::parse("0 8 * * 1-5").unwrap();
+    let now = Utc.with_ymd_and_hms(2025, 3, 1, 9, 0, 0).unwrap();
+
+    assert_eq!(
+        expr.next_after(&now),
+        Some(Utc.with_ymd_and_hms(2025, 3, 3, 8, 0, 0).unwrap())
+    );
+
+    // 7 is Sunday as well
+    let sunday = CronExpr::parse("0 8 * * 7").unwrap();
+    assert_eq!(
+        sunday.next_after(&now),
+        Some(Utc.with_ymd_and_hms(2025, 3, 2, 8, 0, 0).unwrap())
+    );
+}
+
+#[test]
+fn test_cron_rejects_invalid_expressions() {
+    assert!(CronExpr::parse("* * * *").is_err());
+    assert!(CronExpr::parse("60 * * * *").is_err());
+    assert!(CronExpr::parse("*/0 * * * *").is_err());
+    assert!(CronExpr::parse("5-1 * * * *").is_err());
+    assert!(CronExpr::parse("a * * * *").is_err());
+}
+
+#[test]
+fn test_sun_event_times() {
+    // Amsterdam around the summer solstice: sunrise ~03:20 UTC, sunset ~20:00 UTC
+    let date = NaiveDate::from_ymd_opt(2025, 6, 21).unwrap();
+    let sunrise = sun_event(SunEvent::Sunrise, date, 52.37, 4.9).unwrap();
+    let sunset = sun_event(SunEvent::Sunset, date, 52.37, 4.9).unwrap();
+
+    assert_eq!(sunrise.hour(), 3);
+    assert!((15..=25).contains(&sunrise.minute()));
+    assert_eq!(sunset.hour(), 20);
+    assert!(sunset.minute() <= 10);
+
+    // The sun doesn't set above the arctic circle in June
+    assert!(sun_event(SunEvent::Sunset, date, 78.2, 15.6).is_none());
+}
+
+#[test]
+fn test_sun_trigger_with_offset() {
+    let trigger = Trigger::parse("sunset-30m@52.37,4.9").unwrap();
+    let morning = Utc.with_ymd_and_hms(2025, 6, 21, 6, 0, 0).unwrap();
+    let sunset = sun_event(
+        SunEvent::Sunset,
+        NaiveDate::from_ymd_opt(2025, 6, 21).unwrap(),
+        52.37,
+        4.9,
+    )
+    .unwrap();
+
+    assert_eq!(
+        trigger.next_after(&morning),
+        Some(sunset - Duration::minutes(30))
+    );
+
+    assert!(Trigger::parse("sunrise").is_err());
+    assert!(Trigger::parse("sunrise@95,0").is_err());
+    assert!(Trigger::parse("sunrise*5m@52,4").is_err());
+    // Not a sign, and more than one byte long
+    assert!(Trigger::parse("sunrise−5m@52,4").is_err());
+    assert!(Trigger::parse("sunrise→@52,4").is_err());
+}
+
+#[test]
+fn test_schedule_takes_earliest_trigger() {
+    let schedule = Schedule::parse("every:2h; cron:30 * * * *").unwrap();
+    let now = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+
+    assert_eq!(
+        schedule.next_after(&now),
+        Some(Utc.with_ymd_and_hms(2025, 3, 1, 12, 30, 0).unwrap())
+    );
+
+    assert!(Schedule::parse(" ; ").is_err());
+    assert!(Schedule::parse("hourly").is_err());
+}
+
+#[test]
+fn test_sun_position() {
+    // Solar noon in Amsterdam at the summer solstice, the sun stands ~61° high in the south
+    let noon = Utc.with_ymd_and_hms(2025, 6, 21, 11, 40, 0).unwrap();
+    let position = sun_position(noon, 52.37, 4.9);
+    assert!((position.elevation - 61.0).abs() < 0.5, "{:?}", position);
+    assert!((position.azimuth - 180.0).abs() < 3.0, "{:?}", position);
+
+    // At sunset the sun is on the horizon in the northwest
+    let sunset = sun_event(SunEvent::Sunset, noon.date_naive(), 52.37, 4.9).unwrap();
+    let position = sun_position(sunset, 52.37, 4.9);
+    assert!(position.elevation.abs() < 1.5, "{:?}", position);
+    assert!((290.0..330.0).contains(&position.azimuth), "{:?}", position);
+
+    // It never gets darker than astronomical twilight there in June
+    let midnight = sun_position(noon + Duration::hours(12), 52.37, 4.9);
+    assert_eq!(
+        SkyPhase::from_elevation(midnight.elevation),
+        SkyPhase::AstronomicalTwilight
+    );
+    assert_eq!(SkyPhase::from_elevation(3.0), SkyPhase::GoldenHour);
+    assert_eq!(SkyPhase::from_elevation(-30.0), SkyPhase::Night);
+
+    assert_eq!(
+        parse_datetime("2025-06-21T21:30:00-04:00").unwrap(),
+        Utc.with_ymd_and_hms(2025, 6, 22, 1, 30, 0).unwrap()
+    );
+    assert!(parse_datetime("2025-06-21 21:30").is_ok());
+    assert!(parse_datetime("tomorrow").is_err());
+}
@@ -1,4 +1,7 @@
-use citycam::sky_detection::{apply_sky_color_with_gradient, detect_sky_region_growing};
+use citycam::sky_detection::{
+    apply_sky_color_with_gradient, apply_sky_palette, detect_sky_region_growing,
+    limit_sky_to_horizon, sky_palette_for_sun, SkyPalette,
+};
 use image::{GrayImage, Rgb, RgbImage};
 
 #[test]
@@ -20,6 +23,12 @@ fn test_sky_detection_simple_gradient() {
 
     // Bottom rows should not be sky
     assert!(!sky_mask[9][5], "Bottom row should not be detected as sky");
+
+    // Nothing below a camera's horizon is sky
+    let mut sky_mask = sky_mask;
+    limit_sky_to_horizon(&mut sky_mask, 0.2);
+    assert!(sky_mask[1][5]);
+    assert!(sky_mask[2..].iter().all(|row| !row.contains(&true)));
 }
 
 #[test]
@@ -57,3 +66,45 @@ fn test_sky_color_application() {
         "Bottom pixels should be unchanged"
     );
 }
+
+#[test]
+fn test_sky_palette_follows_the_sun() {
+    let night = sky_palette_for_sun(-40.0);
+    let dusk = sky_palette_for_sun(-3.0);
+    let day = sky_palette_for_sun(45.0);
+
+    assert_eq!(night, sky_palette_for_sun(-18.0));
+    assert_eq!(day, sky_palette_for_sun(20.0));
+    // Warm horizon around sunset, dark sky at night, blue during the day
+    assert!(dusk.horizon.0[0] > dusk.horizon.0[2]);
+    assert!(night.zenith.0.iter().all(|&c| c < 60));
+    assert!(day.zenith.0[2] > day.zenith.0[0]);
+
+    // No jumps between the phases
+    let mut previous = sky_palette_for_sun(-20.0);
+    for step in -199..=250 {
+        let palette = sky_palette_for_sun(step as f64 / 10.0);
+        for (a, b) in palette.zenith.0.iter().zip(previous.zenith.0) {
+            assert!((*a as i32 - b as i32).abs() <= 2);
+        }
+        previous = palette;
+    }
+}
+
+#[test]
+fn test_sky_palette_gradient() {
+    let img = RgbImage::from_pixel(4, 10, Rgb([100, 100, 100]));
+    let mut sky_mask = vec![vec![false; 4]; 10];
+    for row in sky_mask.iter_mut().take(5) {
+        row.fill(true);
+    }
+    let palette = SkyPalette {
+        zenith: Rgb([0, 0, 200]),
+        horizon: Rgb([200, 100, 0]),
+    };
+
+    let result = apply_sky_palette(&img, &sky_mask, &palette);
+    assert_eq!(result.get_pixel(2, 0).0, [0, 0, 200]);
+    assert_eq!(result.get_pixel(2, 4).0, [200, 100, 0]);
+    assert_eq!(result.get_pixel(2, 9).0, [100, 100, 100]);
+}
@@ -0,0 +1,397 @@
+use citycam::camera::{self, Camera, CameraKind};
+use citycam::effects::Pipeline;
+use citycam::source;
+use citycam::stream::{FrameSelection, StreamOptions};
+use image::{Rgb, RgbImage};
+use std::fs;
+use std::io::Cursor;
+
+fn write_frames(dir: &std::path::Path, values: &[u8]) {
+    for (i, value) in values.iter().enumerate() {
+        let frame = RgbImage::from_pixel(8, 6, Rgb([*value, *value, *value]));
+        frame
+            .save(dir.join(format!("2025010{}-120000.png", i + 1)))
+            .unwrap();
+    }
+}
+
+#[test]
+fn test_camera_from_path_detects_kind() {
+    let dir = tempfile::tempdir().unwrap();
+    let video = dir.path().join("street.mp4");
+    let playlist = dir.path().join("index.m3u8");
+
+    assert_eq!(Camera::from_path(dir.path()).kind, CameraKind::Directory);
+    assert_eq!(Camera::from_path(&video).kind, CameraKind::File);
+    assert_eq!(Camera::from_path(&playlist).kind, CameraKind::Hls);
+    assert_eq!(Camera::from_path(&video).name, "street.mp4");
+}
+
+#[test]
+fn test_cameras_without_kind_default_to_wetmet() {
+    let dir = tempfile::tempdir().unwrap();
+    let cams_file = dir.path().join("cams.json");
+    fs::write(
+        &cams_file,
+        r#"[
+            {"name": "Old", "url": "https://example.com/frame.php?uid=1"},
+            {"name": "Local", "url": "/tmp/frames", "kind": "directory"}
+        ]"#,
+    )
+    .unwrap();
+
+    let cameras = camera::load_cameras(&cams_file).unwrap();
+
+    assert_eq!(cameras[0].kind, CameraKind::Wetmet);
+    assert_eq!(cameras[1].kind, CameraKind::Directory);
+    assert!(camera::get_embedded_cameras().is_ok());
+}
+
+#[test]
+fn test_directory_source_returns_newest_frames_oldest_first() {
+    let dir = tempfile::tempdir().unwrap();
+    write_frames(dir.path(), &[10, 20, 30, 40]);
+    fs::write(dir.path().join("notes.txt"), "not an image").unwrap();
+    let camera = Camera::from_path(dir.path());
+
+    let capture = source::capture(
+        &camera,
+        &StreamOptions::default(),
+        FrameSelection::Spread(2),
+    )
+    .unwrap();
+
+    let values: Vec<u8> = capture
+        .frames
+        .iter()
+        .map(|f| f.get_pixel(0, 0).0[0])
+        .collect();
+    assert_eq!(values, vec![30, 40]);
+    assert!(capture.segment.timestamp.is_some());
+}
+
+#[test]
+fn test_full_pipeline_from_local_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    write_frames(dir.path(), &[90, 180]);
+    let camera = Camera::from_path(dir.path());
+
+    let capture =
+        source::capture(&camera, &StreamOptions::default(), FrameSelection::Best(4)).unwrap();
+    assert_eq!(capture.frames.len(), 1);
+
+    let pipeline = Pipeline::parse("grayscale,tint:#ff0000@0.5,beams:*@2@7").unwrap();
+    let processed = pipeline.apply(&capture.frames[0]);
+
+    assert_eq!(processed.dimensions(), (8, 6));
+    let pixel = processed.get_pixel(0, 5);
+    assert!(pixel.0[0] > pixel.0[2], "Tint should survive the pipeline");
+}
+
+#[test]
+fn test_empty_directory_source_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let camera = Camera::from_path(dir.path());
+
+    assert!(source::capture(
+        &camera,
+        &StreamOptions::default(),
+        FrameSelection::Spread(1)
+    )
+    .is_err());
+}
+
+#[test]
+fn test_camera_filter() {
+    let cameras = camera::get_embedded_cameras().unwrap();
+    let mut local = Camera::from_path(std::path::Path::new("/tmp/frames"));
+    local.kind = CameraKind::Directory;
+    let mut all = cameras.clone();
+    all.push(local);
+
+    let drummond = camera::CameraFilter {
+        name: Some("DRUMMOND".to_string()),
+        ..Default::default()
+    }
+    .apply(&all);
+    assert!(!drummond.is_empty());
+    assert!(drummond
+        .iter()
+        .all(|(index, camera)| camera.name.contains("Drummond")
+            && all[index - 1].name == camera.name));
+
+    let directories = camera::CameraFilter {
+        kind: Some(CameraKind::parse("Directory").unwrap()),
+        ..Default::default()
+    }
+    .apply(&all);
+    assert_eq!(directories.len(), 1);
+    assert_eq!(directories[0].0, all.len());
+
+    assert_eq!(camera::CameraFilter::default().apply(&all).len(), all.len());
+    assert!(CameraKind::parse("webcam").is_err());
+}
+
+#[test]
+fn test_camera_metadata_is_optional() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cams.json");
+    fs::write(
+        &path,
+        r#"[
+            {"name": "Old Harbor", "url": "https://example.com/harbor"},
+            {
+                "name": "Lake Front",
+                "url": "https://example.com/lake.m3u8",
+                "kind": "hls",
+                "latitude": 41.88,
+                "longitude": -87.61,
+                "timezone": "America/Chicago",
+                "tags": ["lake", "Downtown"],
+                "horizon": 0.4,
+                "crop": {"x": 0.0, "y": 0.1, "width": 1.0, "height": 0.8},
+                "attribution": "City of Chicago"
+            }
+        ]"#,
+    )
+    .unwrap();
+
+    let cameras = camera::load_cameras(&path).unwrap();
+    assert!(cameras[0].tags.is_empty() && cameras[0].latitude.is_none());
+    assert_eq!(cameras[1].timezone.as_deref(), Some("America/Chicago"));
+    assert_eq!(cameras[1].crop.unwrap().height, 0.8);
+
+    // Unset fields stay out of the file when written back
+    let json = serde_json::to_string(&cameras[0]).unwrap();
+    assert!(!json.contains("tags") && !json.contains("latitude"));
+
+    assert_eq!(
+        camera::find_camera(&cameras, "tag:downtown").unwrap().name,
+        "Lake Front"
+    );
+    assert!(camera::find_camera(&cameras, "tag:zoo").is_err());
+
+    let near = camera::CameraFilter {
+        region: Some(camera::Region::parse("41.9,-87.6@10km").unwrap()),
+        ..Default::default()
+    };
+    assert_eq!(near.apply(&cameras).len(), 1);
+    assert_eq!(
+        camera::find_camera(&cameras, "region:42.5,-88.0,41.0,-87.0")
+            .unwrap()
+            .name,
+        "Lake Front"
+    );
+    assert!(camera::find_camera(&cameras, "region:45,-84,46,-83").is_err());
+    assert!(camera::Region::parse("41.9,-87.6").is_err());
+}
+
+#[test]
+fn test_camera_settings_are_checked() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cams.json");
+    let load = |extra: &str| {
+        let json = format!(
+            r#"[{{"name": "Pier", "url": "https://example.com/pier"{}}}]"#,
+            extra
+        );
+        fs::write(&path, json).unwrap();
+        camera::load_cameras(&path)
+    };
+
+    let pier = load(r#", "timezone": "Europe/Lisbon""#).unwrap();
+    assert_eq!(pier[0].tz(), Some(chrono_tz::Europe::Lisbon));
+    let error = load(r#", "timezone": "Europe/Atlantis""#).unwrap_err();
+    assert!(error.to_string().contains("unknown timezone"));
+
+    assert!(load(r#", "crop": {"x": 0.25, "y": 0, "width": 0.75, "height": 1}"#).is_ok());
+    for crop in [
+        r#"{"x": 0, "y": 0, "width": 1.5, "height": 1}"#,
+        r#"{"x": -0.1, "y": 0, "width": 0.5, "height": 1}"#,
+        r#"{"x": 0.5, "y": 0, "width": 0.6, "height": 1}"#,
+        r#"{"x": 0, "y": 0, "width": 0, "height": 1}"#,
+    ] {
+        assert!(load(&format!(r#", "crop": {}"#, crop)).is_err(), "{}", crop);
+    }
+
+    assert!(load(r#", "latitude": -33.9, "longitude": 151.2, "weight": 0.5"#).is_ok());
+    for extra in [
+        r#", "latitude": 91"#,
+        r#", "longitude": -180.5"#,
+        r#", "weight": 0"#,
+        r#", "weight": -2"#,
+        r#", "horizon": 1.2"#,
+    ] {
+        assert!(load(extra).is_err(), "{}", extra);
+    }
+    assert!(camera::Region::parse("41.9,-87.6@0km").is_err());
+    assert!(camera::Region::parse("41.9,-87.6@-5").is_err());
+    assert!(camera::Region::parse("95,-87.6@10km").is_err());
+    assert!(camera::Region::parse("41,-200,42,-87").is_err());
+}
+
+#[test]
+fn test_list_cameras_shows_location_and_tags() {
+    let cameras = vec![
+        Camera {
+            name: "Pier".to_string(),
+            latitude: Some(41.8917),
+            longitude: Some(-87.6086),
+            tags: vec!["lake".to_string(), "downtown".to_string()],
+            ..Default::default()
+        },
+        Camera {
+            name: "Garage".to_string(),
+            ..Default::default()
+        },
+    ];
+    assert_eq!(
+        camera::list_cameras(&cameras),
+        "Available cameras:\n  1. Pier (41.8917, -87.6086) [lake, downtown]\n  2. Garage\n"
+    );
+}
+
+#[test]
+fn test_find_cameras_for_every_selector() {
+    let camera = |name: &str, tags: &[&str]| Camera {
+        name: name.to_string(),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        ..Default::default()
+    };
+    let cameras = vec![
+        camera("Harbor", &["lake"]),
+        camera("Bridge", &["river", "bridge"]),
+        camera("Beach", &["lake"]),
+    ];
+    let selectors = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    let names = |s: &[&str]| -> Vec<String> {
+        camera::find_cameras(&cameras, &selectors(s))
+            .unwrap()
+            .into_iter()
+            .map(|camera| camera.name)
+            .collect()
+    };
+
+    assert_eq!(names(&["bridge"]), ["Bridge"]);
+    assert_eq!(names(&["tag:lake"]), ["Harbor", "Beach"]);
+    // In the order asked for, without repeats
+    assert_eq!(
+        names(&["3", "tag:lake", "bri"]),
+        ["Beach", "Harbor", "Bridge"]
+    );
+    assert!(names(&[]).is_empty());
+
+    assert!(camera::find_cameras(&cameras, &selectors(&["tag:zoo"])).is_err());
+    let error = camera::find_cameras(&cameras, &selectors(&["harbor", "pier"])).unwrap_err();
+    assert!(error.to_string().contains("Failed to find camera: pier"));
+}
+
+// A multipart/x-mixed-replace body with one part per JPEG, each with its length or only
+// ended by the next boundary, and the closing boundary
+fn multipart(parts: &[Vec<u8>], with_length: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    for part in parts {
+        body.extend_from_slice(b"--frame\r\nContent-Type: image/jpeg\r\n");
+        if with_length {
+            body.extend_from_slice(format!("Content-Length: {}\r\n", part.len()).as_bytes());
+        }
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(part);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(b"--frame--\r\n");
+    body
+}
+
+fn jpeg(value: u8) -> Vec<u8> {
+    let mut data = Vec::new();
+    RgbImage::from_pixel(16, 12, Rgb([value, value, value]))
+        .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Jpeg)
+        .unwrap();
+    data
+}
+
+// A JPEG carrying an EXIF thumbnail, so an end of image marker comes before its own
+fn jpeg_with_thumbnail(value: u8) -> Vec<u8> {
+    let image = jpeg(value);
+    let mut exif = b"Exif\0\0".to_vec();
+    exif.extend_from_slice(&[0xFF, 0xD8, 0x00, 0x01, 0xFF, 0xD9]);
+    let mut data = image[..2].to_vec();
+    data.extend_from_slice(&[0xFF, 0xE1]);
+    data.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+    data.extend_from_slice(&exif);
+    data.extend_from_slice(&image[2..]);
+    data
+}
+
+#[test]
+fn test_mjpeg_frames() {
+    for with_length in [true, false] {
+        let body = multipart(
+            &[jpeg(50), jpeg_with_thumbnail(200), jpeg(120)],
+            with_length,
+        );
+        let frames = source::read_mjpeg(Cursor::new(&body), Some("frame"), 2, 0.0).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].dimensions(), (16, 12));
+        assert!(frames[0].get_pixel(8, 6)[0].abs_diff(50) < 8);
+        assert!(frames[1].get_pixel(8, 6)[0].abs_diff(200) < 8);
+
+        // The boundary is read from the body when the header has none
+        let frames = source::read_mjpeg(Cursor::new(&body), None, 3, 0.0).unwrap();
+        assert_eq!(frames.len(), 3, "with length: {}", with_length);
+    }
+
+    assert_eq!(
+        source::multipart_boundary("multipart/x-mixed-replace; boundary=\"frame\"").as_deref(),
+        Some("frame")
+    );
+    assert_eq!(source::multipart_boundary("image/jpeg"), None);
+    let error = source::read_mjpeg(Cursor::new(jpeg(50)), None, 1, 0.0).unwrap_err();
+    assert!(error.to_string().contains("no boundary"), "{}", error);
+}
+
+#[test]
+fn test_mjpeg_skips_undecodable_parts() {
+    let body = multipart(&[b"not a jpeg".to_vec(), jpeg(80)], false);
+    let frames = source::read_mjpeg(Cursor::new(body), Some("frame"), 1, 0.0).unwrap();
+    assert!(frames[0].get_pixel(8, 6)[0].abs_diff(80) < 8);
+
+    let body = multipart(&[b"not a jpeg".to_vec()], true);
+    let error = source::read_mjpeg(Cursor::new(body), Some("frame"), 1, 0.0).unwrap_err();
+    assert!(error.to_string().contains("could be decoded"), "{}", error);
+}
+
+#[test]
+fn test_mjpeg_stream_cut_off() {
+    // The last part never finishes
+    let mut cut = jpeg(120);
+    cut.truncate(cut.len() / 2);
+    let mut body = multipart(&[jpeg(50)], true);
+    body.truncate(body.len() - b"--frame--\r\n".len());
+    body.extend_from_slice(b"--frame\r\nContent-Length: 5000\r\n\r\n");
+    body.extend_from_slice(&cut);
+    let frames = source::read_mjpeg(Cursor::new(body), Some("frame"), 3, 0.0).unwrap();
+    assert_eq!(frames.len(), 1);
+
+    let mut body = multipart(&[cut], false);
+    body.truncate(body.len() - b"\r\n--frame--\r\n".len());
+    let error = source::read_mjpeg(Cursor::new(body), Some("frame"), 1, 0.0).unwrap_err();
+    assert!(error.to_string().contains("ended before a full frame"));
+    let empty = multipart(&[], true);
+    assert!(source::read_mjpeg(Cursor::new(empty), Some("frame"), 1, 0.0).is_err());
+}
+
+#[test]
+fn test_mjpeg_oversized_part() {
+    // A part that never ends, and one that says it is too big
+    let mut huge = vec![0xFF, 0xD8];
+    huge.resize(65 * 1024 * 1024, 0);
+    let body = multipart(&[huge.clone(), jpeg(50)], false);
+    let error = source::read_mjpeg(Cursor::new(body), Some("frame"), 1, 0.0).unwrap_err();
+    assert!(error.to_string().contains("No complete frame"), "{}", error);
+
+    let body = multipart(&[huge, jpeg(50)], true);
+    let error = source::read_mjpeg(Cursor::new(body), Some("frame"), 1, 0.0).unwrap_err();
+    assert!(error.to_string().contains("over the"), "{}", error);
+}
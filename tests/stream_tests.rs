@@ -0,0 +1,250 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use citycam::stream::{
+    fetch_segment, resolve_uri, select_segment, select_variant, SegmentSelection, StreamOptions,
+    VariantInfo, VariantSelection,
+};
+use m3u8_rs::{MediaPlaylist, Playlist};
+use std::fs;
+
+fn variant(width: u64, height: u64, bandwidth: u64) -> VariantInfo {
+    VariantInfo {
+        uri: format!("{}p.m3u8", height),
+        bandwidth,
+        resolution: Some((width, height)),
+        codecs: Some("avc1.640028".to_string()),
+    }
+}
+
+fn ladder() -> Vec<VariantInfo> {
+    vec![
+        variant(1280, 720, 2_500_000),
+        variant(640, 360, 800_000),
+        variant(1920, 1080, 5_000_000),
+    ]
+}
+
+#[test]
+fn test_variant_selection_parse() {
+    assert_eq!(
+        VariantSelection::parse("highest").unwrap(),
+        VariantSelection::Highest
+    );
+    assert_eq!(
+        VariantSelection::parse("First").unwrap(),
+        VariantSelection::First
+    );
+    assert_eq!(
+        VariantSelection::parse("closest:1920x1080").unwrap(),
+        VariantSelection::Closest {
+            width: 1920,
+            height: 1080
+        }
+    );
+    assert_eq!(
+        VariantSelection::parse("max-bandwidth:3000000").unwrap(),
+        VariantSelection::MaxBandwidth(3_000_000)
+    );
+    assert_eq!(
+        VariantSelection::parse("index:2").unwrap(),
+        VariantSelection::Index(2)
+    );
+
+    assert!(VariantSelection::parse("closest:1920").is_err());
+    assert!(VariantSelection::parse("max-bandwidth:lots").is_err());
+    assert!(VariantSelection::parse("lowest").is_err());
+}
+
+#[test]
+fn test_select_variant() {
+    let variants = ladder();
+
+    assert_eq!(
+        select_variant(&variants, VariantSelection::Highest).unwrap(),
+        2
+    );
+    assert_eq!(
+        select_variant(&variants, VariantSelection::First).unwrap(),
+        0
+    );
+    assert_eq!(
+        select_variant(&variants, VariantSelection::Index(1)).unwrap(),
+        1
+    );
+    assert!(select_variant(&variants, VariantSelection::Index(3)).is_err());
+    assert!(select_variant(&[], VariantSelection::Highest).is_err());
+}
+
+#[test]
+fn test_select_variant_closest_resolution() {
+    let variants = ladder();
+
+    let closest = |width, height| {
+        select_variant(&variants, VariantSelection::Closest { width, height }).unwrap()
+    };
+    assert_eq!(closest(1366, 768), 0);
+    assert_eq!(closest(2560, 1440), 2);
+    assert_eq!(closest(320, 240), 1);
+}
+
+#[test]
+fn test_select_variant_bandwidth_cap() {
+    let variants = ladder();
+
+    assert_eq!(
+        select_variant(&variants, VariantSelection::MaxBandwidth(3_000_000)).unwrap(),
+        0
+    );
+    // Nothing fits under the cap, so take the cheapest stream rather than failing
+    assert_eq!(
+        select_variant(&variants, VariantSelection::MaxBandwidth(100_000)).unwrap(),
+        1
+    );
+}
+
+#[test]
+fn test_segment_selection_parse() {
+    assert_eq!(
+        SegmentSelection::parse("latest").unwrap(),
+        SegmentSelection::Latest
+    );
+    assert_eq!(
+        SegmentSelection::parse("nth:-2").unwrap(),
+        SegmentSelection::Nth(-2)
+    );
+    assert!(SegmentSelection::parse("nth:x").is_err());
+}
+
+// Six-second segments, with a program date time on those given one
+fn media_playlist(dates: &[Option<DateTime<Utc>>]) -> MediaPlaylist {
+    let mut text = String::from("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:6\n");
+    for (i, date) in dates.iter().enumerate() {
+        if let Some(date) = date {
+            text += &format!("#EXT-X-PROGRAM-DATE-TIME:{}\n", date.to_rfc3339());
+        }
+        text += &format!("#EXTINF:6.0,\nsegment{}.ts\n", i);
+    }
+    match m3u8_rs::parse_playlist_res(text.as_bytes()).unwrap() {
+        Playlist::MediaPlaylist(media) => media,
+        Playlist::MasterPlaylist(_) => unreachable!(),
+    }
+}
+
+#[test]
+fn test_select_segment_by_position() {
+    let playlist = media_playlist(&[None; 4]);
+    let index = |selection| select_segment(&playlist, selection).unwrap().0;
+
+    assert_eq!(index(SegmentSelection::Latest), 3);
+    assert_eq!(index(SegmentSelection::First), 0);
+    assert_eq!(index(SegmentSelection::Nth(1)), 1);
+    assert_eq!(index(SegmentSelection::Nth(-1)), 3);
+    assert_eq!(index(SegmentSelection::Nth(-4)), 0);
+    // No program date times to go by
+    assert_eq!(index(SegmentSelection::Closest), 3);
+    assert!(select_segment(&playlist, SegmentSelection::Nth(4)).is_err());
+    assert!(select_segment(&playlist, SegmentSelection::Nth(-5)).is_err());
+    assert!(select_segment(&media_playlist(&[]), SegmentSelection::Latest).is_err());
+}
+
+#[test]
+fn test_select_segment_closest_to_now() {
+    // Segments start 20, 14, 8 and 2 seconds ago and 4 seconds from now
+    let start = Utc::now() - Duration::seconds(20);
+    let mut dates = vec![None; 5];
+    dates[0] = Some(start);
+    let playlist = media_playlist(&dates);
+
+    let (index, timestamp) = select_segment(&playlist, SegmentSelection::Closest).unwrap();
+    assert_eq!(index, 3);
+    assert_eq!(timestamp, Some(start + Duration::seconds(18)));
+}
+
+#[test]
+fn test_program_date_time_carries_over() {
+    let first = Utc.with_ymd_and_hms(2025, 3, 10, 18, 0, 0).unwrap();
+    // A discontinuity restarts the clock
+    let second = Utc.with_ymd_and_hms(2025, 3, 10, 19, 0, 0).unwrap();
+    let playlist = media_playlist(&[None, Some(first), None, Some(second), None]);
+    let timestamp = |n| {
+        select_segment(&playlist, SegmentSelection::Nth(n))
+            .unwrap()
+            .1
+    };
+
+    assert_eq!(timestamp(0), None);
+    assert_eq!(timestamp(1), Some(first));
+    assert_eq!(timestamp(2), Some(first + Duration::seconds(6)));
+    assert_eq!(timestamp(3), Some(second));
+    assert_eq!(timestamp(4), Some(second + Duration::seconds(6)));
+}
+
+#[test]
+fn test_resolve_uri() {
+    let remote = "https://example.com/live/cam/index.m3u8?token=1";
+    assert_eq!(
+        resolve_uri(remote, "chunk_7.ts"),
+        "https://example.com/live/cam/chunk_7.ts"
+    );
+    assert_eq!(
+        resolve_uri(remote, "/hls/chunk_7.ts"),
+        "https://example.com/hls/chunk_7.ts"
+    );
+    assert_eq!(
+        resolve_uri(remote, "../720p/index.m3u8"),
+        "https://example.com/live/720p/index.m3u8"
+    );
+    assert_eq!(
+        resolve_uri(remote, "https://cdn.example.net/chunk_7.ts"),
+        "https://cdn.example.net/chunk_7.ts"
+    );
+
+    assert_eq!(
+        resolve_uri("/srv/cam/index.m3u8", "720p/a.ts"),
+        "/srv/cam/720p/a.ts"
+    );
+    assert_eq!(
+        resolve_uri("/srv/cam/index.m3u8", "/data/a.ts"),
+        "/data/a.ts"
+    );
+    assert_eq!(resolve_uri("index.m3u8", "a.ts"), "a.ts");
+    assert_eq!(
+        resolve_uri("/srv/cam/index.m3u8", "http://example.com/a.ts"),
+        "http://example.com/a.ts"
+    );
+}
+
+#[test]
+fn test_fetch_segment_from_local_playlists() {
+    let dir = tempfile::tempdir().unwrap();
+    let elsewhere = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("720p")).unwrap();
+    fs::write(
+        dir.path().join("master.m3u8"),
+        "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720\n720p/index.m3u8\n",
+    )
+    .unwrap();
+    // One segment next to the variant playlist, the newer one somewhere else entirely
+    let absolute = elsewhere.path().join("b.ts");
+    fs::write(
+        dir.path().join("720p/index.m3u8"),
+        format!(
+            "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXTINF:2.0,\na.ts\n#EXTINF:2.0,\n{}\n",
+            absolute.display()
+        ),
+    )
+    .unwrap();
+    fs::write(dir.path().join("720p/a.ts"), b"first").unwrap();
+    fs::write(&absolute, b"second").unwrap();
+
+    let master = dir.path().join("master.m3u8").display().to_string();
+    let (data, info) = fetch_segment(&master, &StreamOptions::default()).unwrap();
+    assert_eq!(data, b"second");
+    assert_eq!(info.uri, Some(absolute.display().to_string()));
+
+    let options = StreamOptions {
+        segment: SegmentSelection::parse("first").unwrap(),
+        ..Default::default()
+    };
+    let (data, _) = fetch_segment(&master, &options).unwrap();
+    assert_eq!(data, b"first");
+}
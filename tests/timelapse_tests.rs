@@ -0,0 +1,183 @@
+use chrono::{Duration, TimeZone, Utc};
+use citycam::history::{HistoryEntry, HistoryFilter};
+use citycam::stream::{decode_file, FrameSelection};
+use citycam::timelapse::{exposure_gains, select_frames, write, TimelapseFormat, TimelapseOptions};
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, Rgb, RgbImage};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+fn entry(dir: &Path, minute: u32, camera: &str, output: Option<&str>) -> HistoryEntry {
+    let suffix = output.map(|o| format!("-{}", o)).unwrap_or_default();
+    let path = dir.join(format!("20250310-18{:02}00{}.jpg", minute, suffix));
+    let captured_at = Utc.with_ymd_and_hms(2025, 3, 10, 18, minute, 0).unwrap();
+    HistoryEntry {
+        url: "https://example.com/cam".to_string(),
+        saved_at: captured_at + Duration::seconds(30),
+        width: 32,
+        height: 24,
+        output: output.map(str::to_string),
+        ..HistoryEntry::new(camera, &path, Some(captured_at))
+    }
+}
+
+#[test]
+fn test_format_from_extension() {
+    let format = |name: &str| TimelapseFormat::from_path(Path::new(name));
+    assert_eq!(format("out.MP4").unwrap(), TimelapseFormat::Mp4);
+    assert_eq!(format("out.webm").unwrap(), TimelapseFormat::Webm);
+    assert_eq!(format("out.gif").unwrap(), TimelapseFormat::Gif);
+    assert_eq!(format("out.png").unwrap(), TimelapseFormat::Apng);
+    assert!(format("out.avi").is_err());
+    assert!(format("out").is_err());
+
+    assert_eq!(TimelapseFormat::Mp4.frame_size(1281, 721), (1280, 720));
+    assert_eq!(TimelapseFormat::Gif.frame_size(1281, 721), (1281, 721));
+}
+
+#[test]
+fn test_exposure_gains_remove_flicker_but_keep_trend() {
+    let steady = exposure_gains(&[100.0; 5], 2);
+    assert!(steady.iter().all(|&gain| (gain - 1.0).abs() < 1e-9));
+
+    // One frame twice as bright as its neighbours gets pulled down
+    let gains = exposure_gains(&[100.0, 100.0, 200.0, 100.0, 100.0], 2);
+    assert_eq!(gains, [1.0, 1.0, 0.5, 1.0, 1.0]);
+
+    // A steady rise is kept
+    let rising = exposure_gains(&[100.0, 110.0, 120.0, 130.0, 140.0], 1);
+    assert!(rising.iter().all(|&gain| (gain - 1.0).abs() < 1e-9));
+
+    // Black frames are left alone and gains are capped
+    assert_eq!(exposure_gains(&[200.0, 0.0, 200.0], 1)[1], 1.0);
+    assert_eq!(exposure_gains(&[250.0, 10.0, 250.0], 1)[1], 2.0);
+    assert!(exposure_gains(&[], 2).is_empty());
+}
+
+#[test]
+fn test_select_frames() {
+    let dir = tempfile::tempdir().unwrap();
+    let entries = vec![
+        entry(dir.path(), 20, "Harbor", None),
+        entry(dir.path(), 10, "Harbor", Some("DP-1")),
+        entry(dir.path(), 10, "Harbor", Some("DP-2")),
+        entry(dir.path(), 15, "Harbor", Some("span")),
+        entry(dir.path(), 30, "Harbor", None),
+    ];
+    // The last one was pruned from the cache
+    for entry in &entries[..4] {
+        fs::write(&entry.path, b"frame").unwrap();
+    }
+
+    let frames = select_frames(&entries, &HistoryFilter::default());
+    let minutes: Vec<String> = frames
+        .iter()
+        .map(|e| e.captured_at.unwrap().format("%M").to_string())
+        .collect();
+    assert_eq!(minutes, ["10", "20"]);
+    assert_eq!(frames[0].output.as_deref(), Some("DP-1"));
+
+    // Copies of one capture for two monitors, saved a moment apart
+    let mut copies = vec![
+        entry(dir.path(), 40, "Harbor", Some("DP-1")),
+        entry(dir.path(), 40, "Harbor", Some("DP-2")),
+        entry(dir.path(), 50, "Harbor", Some("DP-1")),
+    ];
+    copies[1].saved_at += Duration::seconds(1);
+    for copy in &mut copies {
+        copy.segment = Some("https://example.com/cam/snapshot.jpg".to_string());
+        fs::write(&copy.path, b"frame").unwrap();
+    }
+    let frames = select_frames(&copies, &HistoryFilter::default());
+    let outputs: Vec<&str> = frames.iter().filter_map(|e| e.output.as_deref()).collect();
+    assert_eq!(outputs, ["DP-1", "DP-1"]);
+
+    let late = HistoryFilter {
+        since: Some(Utc.with_ymd_and_hms(2025, 3, 10, 18, 12, 0).unwrap()),
+        ..Default::default()
+    };
+    assert_eq!(select_frames(&entries, &late).len(), 1);
+}
+
+#[test]
+fn test_write_gif_and_apng() {
+    let dir = tempfile::tempdir().unwrap();
+    let entries: Vec<HistoryEntry> = (0..3)
+        .map(|i| entry(dir.path(), i, "Harbor", None))
+        .collect();
+    for (i, entry) in entries.iter().enumerate() {
+        // The last frame is a different size and gets fitted to the first
+        let size = if i == 2 { (64, 48) } else { (32, 24) };
+        let value = 60 + i as u8 * 40;
+        RgbImage::from_pixel(size.0, size.1, Rgb([value, value, value]))
+            .save(&entry.path)
+            .unwrap();
+    }
+    let frames: Vec<&HistoryEntry> = entries.iter().collect();
+    let options = TimelapseOptions {
+        fps: 12,
+        deflicker: true,
+        timestamps: true,
+    };
+
+    let gif = dir.path().join("timelapse.gif");
+    write(&gif, &frames, &options).unwrap();
+    let decoder = GifDecoder::new(BufReader::new(File::open(&gif).unwrap())).unwrap();
+    let decoded = decoder.into_frames().collect_frames().unwrap();
+    assert_eq!(decoded.len(), 3);
+    assert_eq!(decoded[2].buffer().dimensions(), (32, 24));
+
+    let apng = dir.path().join("timelapse.png");
+    write(&apng, &frames, &options).unwrap();
+    assert_eq!(image::image_dimensions(&apng).unwrap(), (32, 24));
+
+    assert!(write(&dir.path().join("empty.gif"), &[], &options).is_err());
+}
+
+#[test]
+fn test_write_video_and_decode() {
+    let dir = tempfile::tempdir().unwrap();
+    let values = [40u8, 120, 200];
+    let entries: Vec<HistoryEntry> = values
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| {
+            let entry = entry(dir.path(), i as u32, "Harbor", None);
+            RgbImage::from_pixel(32, 24, Rgb([value, value, value]))
+                .save(&entry.path)
+                .unwrap();
+            entry
+        })
+        .collect();
+    let frames: Vec<&HistoryEntry> = entries.iter().collect();
+    let options = TimelapseOptions {
+        fps: 4,
+        deflicker: false,
+        timestamps: false,
+    };
+
+    for name in ["timelapse.mp4", "timelapse.webm"] {
+        let path = dir.path().join(name);
+        if let Err(e) = write(&path, &frames, &options) {
+            let message = format!("{:#}", e);
+            assert!(message.contains("none of the encoders"), "{}", message);
+            eprintln!("Skipping {}: {}", name, message);
+            continue;
+        }
+
+        let decoded = decode_file(&path, FrameSelection::Spread(values.len())).unwrap();
+        assert_eq!(decoded.len(), values.len(), "{}", name);
+        for (frame, &value) in decoded.iter().zip(&values) {
+            assert_eq!(frame.dimensions(), (32, 24));
+            let mean = frame.pixels().map(|p| p[1] as u32).sum::<u32>() / (32 * 24);
+            assert!(
+                mean.abs_diff(value as u32) < 16,
+                "{}: {} for {}",
+                name,
+                mean,
+                value
+            );
+        }
+    }
+}